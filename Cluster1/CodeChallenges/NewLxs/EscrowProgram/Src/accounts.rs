@@ -0,0 +1,270 @@
+use crate::utils::next_named;
+use solana_program::{account_info::AccountInfo, program_error::ProgramError};
+
+/// The accounts of [crate::instruction::EscrowInstruction::InitEscrow], in order
+pub struct InitEscrowAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub temp_token_account: &'a AccountInfo<'info>,
+    pub token_to_receive_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub rent_sysvar: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    pub initializer_stats: &'a AccountInfo<'info>,
+    pub mint: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> InitEscrowAccounts<'a, 'info> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(Self {
+            initializer: next_named(account_info_iter, "initializer")?,
+            temp_token_account: next_named(account_info_iter, "temp_token_account")?,
+            token_to_receive_account: next_named(account_info_iter, "token_to_receive_account")?,
+            escrow_account: next_named(account_info_iter, "escrow_account")?,
+            rent_sysvar: next_named(account_info_iter, "rent_sysvar")?,
+            token_program: next_named(account_info_iter, "token_program")?,
+            config: next_named(account_info_iter, "config")?,
+            initializer_stats: next_named(account_info_iter, "initializer_stats")?,
+            mint: account_info_iter.next(),
+        })
+    }
+}
+
+/// The accounts of [crate::instruction::EscrowInstruction::InitEscrowPda], in order
+pub struct InitEscrowPdaAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub temp_token_account: &'a AccountInfo<'info>,
+    pub token_to_receive_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    pub initializer_stats: &'a AccountInfo<'info>,
+    pub mint: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> InitEscrowPdaAccounts<'a, 'info> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(Self {
+            initializer: next_named(account_info_iter, "initializer")?,
+            temp_token_account: next_named(account_info_iter, "temp_token_account")?,
+            token_to_receive_account: next_named(account_info_iter, "token_to_receive_account")?,
+            escrow_account: next_named(account_info_iter, "escrow_account")?,
+            system_program: next_named(account_info_iter, "system_program")?,
+            token_program: next_named(account_info_iter, "token_program")?,
+            config: next_named(account_info_iter, "config")?,
+            initializer_stats: next_named(account_info_iter, "initializer_stats")?,
+            mint: account_info_iter.next(),
+        })
+    }
+}
+
+/// The accounts of [crate::instruction::EscrowInstruction::Exchange], in order
+pub struct ExchangeAccounts<'a, 'info> {
+    pub taker: &'a AccountInfo<'info>,
+    pub takers_sending_token_account: &'a AccountInfo<'info>,
+    pub takers_token_to_receive_account: &'a AccountInfo<'info>,
+    pub pdas_temp_token_account: &'a AccountInfo<'info>,
+    pub initializers_main_account: &'a AccountInfo<'info>,
+    pub initializers_token_to_receive_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    pub config: &'a AccountInfo<'info>,
+    pub initializer_stats: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> ExchangeAccounts<'a, 'info> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(Self {
+            taker: next_named(account_info_iter, "taker")?,
+            takers_sending_token_account: next_named(
+                account_info_iter,
+                "takers_sending_token_account",
+            )?,
+            takers_token_to_receive_account: next_named(
+                account_info_iter,
+                "takers_token_to_receive_account",
+            )?,
+            pdas_temp_token_account: next_named(account_info_iter, "pdas_temp_token_account")?,
+            initializers_main_account: next_named(account_info_iter, "initializers_main_account")?,
+            initializers_token_to_receive_account: next_named(
+                account_info_iter,
+                "initializers_token_to_receive_account",
+            )?,
+            escrow_account: next_named(account_info_iter, "escrow_account")?,
+            token_program: next_named(account_info_iter, "token_program")?,
+            pda_account: next_named(account_info_iter, "pda_account")?,
+            config: next_named(account_info_iter, "config")?,
+            initializer_stats: next_named(account_info_iter, "initializer_stats")?,
+        })
+    }
+}
+
+/// The accounts of [crate::instruction::EscrowInstruction::Cancel], in order
+pub struct CancelAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub temp_token_account: &'a AccountInfo<'info>,
+    pub initializer_token_account: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub initializer_stats: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> CancelAccounts<'a, 'info> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(Self {
+            initializer: next_named(account_info_iter, "initializer")?,
+            temp_token_account: next_named(account_info_iter, "temp_token_account")?,
+            initializer_token_account: next_named(account_info_iter, "initializer_token_account")?,
+            escrow_account: next_named(account_info_iter, "escrow_account")?,
+            token_program: next_named(account_info_iter, "token_program")?,
+            initializer_stats: next_named(account_info_iter, "initializer_stats")?,
+        })
+    }
+}
+
+/// The accounts of the initializer-only instructions that just update the escrow account,
+/// like [crate::instruction::EscrowInstruction::ResetTimeLock], in order
+pub struct UpdateEscrowAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> UpdateEscrowAccounts<'a, 'info> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(Self {
+            initializer: next_named(account_info_iter, "initializer")?,
+            escrow_account: next_named(account_info_iter, "escrow_account")?,
+        })
+    }
+}
+
+/// The accounts of [crate::instruction::EscrowInstruction::SetReceiveAccount], in order
+pub struct SetReceiveAccountAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub current_receive_account: &'a AccountInfo<'info>,
+    pub new_receive_account: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> SetReceiveAccountAccounts<'a, 'info> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(Self {
+            initializer: next_named(account_info_iter, "initializer")?,
+            escrow_account: next_named(account_info_iter, "escrow_account")?,
+            current_receive_account: next_named(account_info_iter, "current_receive_account")?,
+            new_receive_account: next_named(account_info_iter, "new_receive_account")?,
+        })
+    }
+}
+
+/// The accounts of [crate::instruction::EscrowInstruction::TransferEscrow], in order
+pub struct TransferEscrowAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub current_receive_account: &'a AccountInfo<'info>,
+    pub new_receive_account: &'a AccountInfo<'info>,
+    pub initializer_stats: &'a AccountInfo<'info>,
+    pub new_initializer_stats: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TransferEscrowAccounts<'a, 'info> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(Self {
+            initializer: next_named(account_info_iter, "initializer")?,
+            escrow_account: next_named(account_info_iter, "escrow_account")?,
+            current_receive_account: next_named(account_info_iter, "current_receive_account")?,
+            new_receive_account: next_named(account_info_iter, "new_receive_account")?,
+            initializer_stats: next_named(account_info_iter, "initializer_stats")?,
+            new_initializer_stats: next_named(account_info_iter, "new_initializer_stats")?,
+        })
+    }
+}
+
+/// The accounts of [crate::instruction::EscrowInstruction::MergeEscrows], in order
+pub struct MergeEscrowsAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub escrow_a: &'a AccountInfo<'info>,
+    pub temp_token_account_a: &'a AccountInfo<'info>,
+    pub escrow_b: &'a AccountInfo<'info>,
+    pub temp_token_account_b: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    pub initializer_stats: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> MergeEscrowsAccounts<'a, 'info> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(Self {
+            initializer: next_named(account_info_iter, "initializer")?,
+            escrow_a: next_named(account_info_iter, "escrow_a")?,
+            temp_token_account_a: next_named(account_info_iter, "temp_token_account_a")?,
+            escrow_b: next_named(account_info_iter, "escrow_b")?,
+            temp_token_account_b: next_named(account_info_iter, "temp_token_account_b")?,
+            token_program: next_named(account_info_iter, "token_program")?,
+            pda_account: next_named(account_info_iter, "pda_account")?,
+            initializer_stats: next_named(account_info_iter, "initializer_stats")?,
+        })
+    }
+}
+
+/// The accounts of [crate::instruction::EscrowInstruction::SplitEscrow], in order
+pub struct SplitEscrowAccounts<'a, 'info> {
+    pub initializer: &'a AccountInfo<'info>,
+    pub escrow_account: &'a AccountInfo<'info>,
+    pub temp_token_account: &'a AccountInfo<'info>,
+    pub new_escrow_account: &'a AccountInfo<'info>,
+    pub new_temp_token_account: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub pda_account: &'a AccountInfo<'info>,
+    pub initializer_stats: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> SplitEscrowAccounts<'a, 'info> {
+    pub fn from_slice(accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(Self {
+            initializer: next_named(account_info_iter, "initializer")?,
+            escrow_account: next_named(account_info_iter, "escrow_account")?,
+            temp_token_account: next_named(account_info_iter, "temp_token_account")?,
+            new_escrow_account: next_named(account_info_iter, "new_escrow_account")?,
+            new_temp_token_account: next_named(account_info_iter, "new_temp_token_account")?,
+            token_program: next_named(account_info_iter, "token_program")?,
+            pda_account: next_named(account_info_iter, "pda_account")?,
+            initializer_stats: next_named(account_info_iter, "initializer_stats")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_program::pubkey::Pubkey;
+
+    #[test]
+    fn test_from_slice_rejects_short_slice() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(&key, true, true, &mut lamports, &mut [], &key, false, 0);
+        let accounts = vec![account; 4];
+
+        assert!(matches!(
+            ExchangeAccounts::from_slice(&accounts),
+            Err(ProgramError::NotEnoughAccountKeys)
+        ));
+        assert!(matches!(
+            InitEscrowAccounts::from_slice(&accounts[..2]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        ));
+        assert!(UpdateEscrowAccounts::from_slice(&accounts[..2]).is_ok());
+    }
+}
@@ -17,6 +17,29 @@ pub enum EscrowError {
     InvalidUnlockTime,
     #[error("Cannot exchange after time out")]
     InvalidTimeOut,
+    #[error("Signer is not allowed to do this")]
+    Unauthorized,
+    #[error("Token account is not owned by the expected wallet")]
+    InvalidOwner,
+    #[error("Escrow program is paused")]
+    Paused,
+    #[error("Escrow was already filled")]
+    AlreadyFilled,
+    #[error("Failed to serialize account or event data")]
+    SerializationFailed,
+    #[error("Temp token account does not hold the expected mint")]
+    InvalidTokenMint,
+    #[error("Escrow is frozen by its initializer")]
+    Frozen,
+    #[error("Initializer already has the maximum number of open escrows")]
+    TooManyEscrows,
+}
+
+/// Lets Borsh `serialize`/`deserialize` call sites use `?`, the io error itself is dropped
+impl From<std::io::Error> for EscrowError {
+    fn from(_: std::io::Error) -> Self {
+        EscrowError::SerializationFailed
+    }
 }
 
 impl From<EscrowError> for ProgramError {
@@ -24,3 +47,45 @@ impl From<EscrowError> for ProgramError {
         ProgramError::Custom(e as u32)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        let codes = [
+            (EscrowError::InvalidInstruction, 0),
+            (EscrowError::NotRentExempt, 1),
+            (EscrowError::ExpectedAmountMismatch, 2),
+            (EscrowError::AmountOverflow, 3),
+            (EscrowError::InvalidUnlockTime, 4),
+            (EscrowError::InvalidTimeOut, 5),
+            (EscrowError::Unauthorized, 6),
+            (EscrowError::InvalidOwner, 7),
+            (EscrowError::Paused, 8),
+            (EscrowError::AlreadyFilled, 9),
+            (EscrowError::SerializationFailed, 10),
+            (EscrowError::InvalidTokenMint, 11),
+            (EscrowError::Frozen, 12),
+            (EscrowError::TooManyEscrows, 13),
+        ];
+        for (error, code) in codes {
+            assert_eq!(ProgramError::from(error), ProgramError::Custom(code));
+        }
+    }
+
+    #[test]
+    fn test_io_error_maps_to_serialization_failed() {
+        fn write_past_end(buf: &mut [u8]) -> Result<(), EscrowError> {
+            std::io::Write::write_all(&mut &mut buf[..], &[1, 2, 3])?;
+            Ok(())
+        }
+
+        assert!(write_past_end(&mut [0; 3]).is_ok());
+        assert!(matches!(
+            write_past_end(&mut [0; 2]),
+            Err(EscrowError::SerializationFailed)
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{log::sol_log_data, program_error::ProgramError, pubkey::Pubkey};
+
+/// First byte of every event, telling an indexer which event the rest of the log data holds
+pub const INITIALIZED_TAG: u8 = 0;
+
+/// Logged once an escrow is initialized, enough to build an order book from the logs alone
+pub struct Initialized {
+    pub escrow: Pubkey,
+    pub initializer: Pubkey,
+    pub expected_amount: u64,
+}
+
+impl Initialized {
+    pub const LEN: usize = 1 + 32 + 32 + 8;
+
+    pub fn pack(&self) -> [u8; Initialized::LEN] {
+        let mut dst = [0; Initialized::LEN];
+        {
+            let dst = array_mut_ref![dst, 0, Initialized::LEN];
+            let (tag_dst, escrow_dst, initializer_dst, expected_amount_dst) =
+                mut_array_refs![dst, 1, 32, 32, 8];
+            tag_dst[0] = INITIALIZED_TAG;
+            escrow_dst.copy_from_slice(self.escrow.as_ref());
+            initializer_dst.copy_from_slice(self.initializer.as_ref());
+            *expected_amount_dst = self.expected_amount.to_le_bytes();
+        }
+        dst
+    }
+
+    pub fn unpack(src: &[u8]) -> Result<Self, ProgramError> {
+        if src.len() != Initialized::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let src = array_ref![src, 0, Initialized::LEN];
+        let (tag, escrow, initializer, expected_amount) = array_refs![src, 1, 32, 32, 8];
+        if tag[0] != INITIALIZED_TAG {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Initialized {
+            escrow: Pubkey::new_from_array(*escrow),
+            initializer: Pubkey::new_from_array(*initializer),
+            expected_amount: u64::from_le_bytes(*expected_amount),
+        })
+    }
+
+    pub fn emit(&self) {
+        sol_log_data(&[&self.pack()]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_initialized_round_trip() {
+        let event = Initialized {
+            escrow: Pubkey::new_unique(),
+            initializer: Pubkey::new_unique(),
+            expected_amount: 1_000,
+        };
+
+        let decoded = Initialized::unpack(&event.pack()).unwrap();
+        assert_eq!(decoded.escrow, event.escrow);
+        assert_eq!(decoded.initializer, event.initializer);
+        assert_eq!(decoded.expected_amount, 1_000);
+        assert_eq!(
+            Initialized::unpack(&event.pack()[1..]).err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+    }
+}
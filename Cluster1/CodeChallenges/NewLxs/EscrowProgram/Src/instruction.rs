@@ -1,10 +1,14 @@
 use crate::error::EscrowError::InvalidInstruction;
 use solana_program::{
+    bpf_loader_upgradeable,
+    hash::hashv,
     instruction::{AccountMeta, Instruction},
+    msg,
     program_error::ProgramError,
     pubkey::Pubkey,
+    system_program, sysvar,
 };
-use std::{convert::TryInto, mem::size_of};
+use std::convert::TryInto;
 
 pub enum EscrowInstruction {
     /// Accounts expected:
@@ -15,9 +19,16 @@ pub enum EscrowInstruction {
     /// 3. `[writable]` The escrow account, it will hold all necessary info about the trade.
     /// 4. `[]` The rent sysvar
     /// 5. `[]` The token program
+    /// 6. `[]` The config account
+    /// 7. `[writable]` The initializer's stats PDA, see [EscrowInstruction::InitInitializerStats]
+    /// 8. `[]` (optional) The mint of the token to receive, used to reject amounts above its supply
     InitEscrow {
         /// The amount party A expects to receive of token Y
         amount: u64,
+        /// The mint of the token party A offers, the temp token account must hold this mint
+        offered_mint: Pubkey,
+        /// The only taker allowed to exchange, anyone can take the trade if `None`
+        allowed_taker: Option<Pubkey>,
     },
     /// Accounts expected:
     ///
@@ -29,9 +40,14 @@ pub enum EscrowInstruction {
     /// 5. `[writable]` The initializer's token account that will receive tokens
     /// 6. `[writable]` The escrow account holding the escrow info
     /// 7. `[]` The token program
+    /// 8. `[]` The PDA account
+    /// 9. `[]` The config account
+    /// 10. `[writable]` The initializer's stats PDA
     Exchange {
         /// the amount the taker expects to be paid in the other token, as a u64 because that's the max possible supply of a token
         amount: u64,
+        /// The escrow's current nonce, the exchange fails if the escrow was updated since
+        nonce: u64,
     },
     /// Accounts expected:
     ///
@@ -40,12 +56,184 @@ pub enum EscrowInstruction {
     /// 2. `[]` The initializer's token account that will receive tokens
     /// 3. `[writable]` The escrow account holding the escrow info
     /// 4. `[]` The token program
-    Cancel {},
+    /// 5. `[writable]` The initializer's stats PDA
+    Cancel {
+        /// The escrow's current nonce, the cancel fails if the escrow was updated since
+        nonce: u64,
+    },
     /// Accounts expected:
     ///
     /// 0. `[signer]` The initializer that is resetting the escrow
     /// 1. `[writable]` The escrow account holding the escrow info
     ResetTimeLock {},
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The account of the person initializing the escrow, pays for the escrow account
+    /// 1. `[writable]` Temporary token account that should be created prior to this instruction and owned by the initializer
+    /// 2. `[]` The initializer's token account for the token they will receive should the trade go through
+    /// 3. `[writable]` The escrow account PDA derived from the initializer and nonce, created by this instruction
+    /// 4. `[]` The system program
+    /// 5. `[]` The token program
+    /// 6. `[]` The config account
+    /// 7. `[writable]` The initializer's stats PDA
+    /// 8. `[]` (optional) The mint of the token to receive, used to reject amounts above its supply
+    InitEscrowPda {
+        /// The amount party A expects to receive of token Y
+        amount: u64,
+        /// Lets the same initializer open several escrows at distinct addresses
+        nonce: u64,
+        /// The mint of the token party A offers, the temp token account must hold this mint
+        offered_mint: Pubkey,
+        /// The only taker allowed to exchange, anyone can take the trade if `None`
+        allowed_taker: Option<Pubkey>,
+    },
+    /// Logs every field of the escrow state, only available with the `debug` feature
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The escrow account holding the escrow info
+    #[cfg(feature = "debug")]
+    DumpEscrow {},
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The initializer of the escrow
+    /// 1. `[writable]` The escrow account holding the escrow info
+    SetAllowedTaker {
+        /// The new allowed taker, `None` opens the escrow to anyone
+        taker: Option<Pubkey>,
+    },
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The initializer that is cancelling the escrows, receives their rent
+    /// 1. `[]` The token program
+    /// 2. `[]` The PDA account
+    /// 3. `[writable]` The initializer's stats PDA
+    /// 4.. For every escrow, in this order:
+    ///     `[writable]` The escrow account holding the escrow info
+    ///     `[writable]` The PDA's temp token account to get tokens from and eventually close
+    ///     `[writable]` The initializer's token account that will receive tokens
+    CancelAll {},
+    /// Creates the config account, the signer becomes its admin. Only the program's upgrade
+    /// authority may send it, anyone else would get to pause the program.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The admin, the program's upgrade authority, pays for the config account
+    /// 1. `[writable]` The config account PDA, created by this instruction
+    /// 2. `[]` The system program
+    /// 3. `[]` The program's ProgramData account, holding its upgrade authority
+    InitConfig {},
+    /// Pausing blocks new escrows and exchanges, cancelling still works
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin stored in the config account
+    /// 1. `[writable]` The config account
+    SetPaused { paused: bool },
+    /// Returns a single byte through the return data, 1 when `taker` could exchange the escrow right
+    /// now given its allowed taker and whether it is frozen, 0 otherwise
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The escrow account
+    CheckTaker { taker: Pubkey },
+    /// Points the escrow at another token account of the initializer to receive the taker's tokens
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The initializer of the escrow
+    /// 1. `[writable]` The escrow account
+    /// 2. `[]` The token account the escrow currently pays into
+    /// 3. `[]` The new token account, owned by the initializer and of the same mint
+    SetReceiveAccount { new_account: Pubkey },
+    /// Returns a single byte through the return data, the `HEALTH_*` bits of every invariant the
+    /// escrow breaks, 0 for a healthy escrow. Changes nothing, meant for off-chain monitors.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[]` The escrow account
+    /// 1. `[]` The PDA's temp token account
+    HealthCheck {},
+    /// Moves the tokens of escrow B into escrow A, which then asks for both amounts, and closes
+    /// escrow B. Both escrows must be the signer's and pay into the same receive account.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The initializer of both escrows, receives escrow B's rent
+    /// 1. `[writable]` Escrow A, the one kept
+    /// 2. `[writable]` Escrow A's temp token account
+    /// 3. `[writable]` Escrow B, closed by this instruction
+    /// 4. `[writable]` Escrow B's temp token account, closed by this instruction
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[writable]` The initializer's stats PDA, counts escrow B as closed
+    MergeEscrows {},
+    /// Pauses the listing without cancelling it, exchange fails until [EscrowInstruction::Unfreeze]
+    /// while cancel still works
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The initializer of the escrow
+    /// 1. `[writable]` The escrow account
+    Freeze {},
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The initializer of the escrow
+    /// 1. `[writable]` The escrow account
+    Unfreeze {},
+    /// Moves part of an escrow's tokens into a new escrow of the same initializer and receive
+    /// account. The new escrow asks for the same share of the expected amount, rounded down, and
+    /// the original asks for the rest.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The initializer of the escrow
+    /// 1. `[writable]` The escrow account being split
+    /// 2. `[writable]` Its temp token account
+    /// 3. `[writable]` The new escrow account, rent exempt, owned by this program and not yet initialized
+    /// 4. `[writable]` The new temp token account, of the same mint and owned by the escrow PDA
+    /// 5. `[]` The token program
+    /// 6. `[]` The PDA account
+    /// 7. `[writable]` The initializer's stats PDA, counts the new escrow as open
+    SplitEscrow {
+        /// The number of temp tokens moved to the new escrow
+        amount: u64,
+    },
+    /// Hands the escrow over to another initializer, who then receives the taker's tokens and the
+    /// rent of an exchange and is the only one able to cancel or update the escrow
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The current initializer of the escrow
+    /// 1. `[writable]` The escrow account
+    /// 2. `[]` The token account the escrow currently pays into
+    /// 3. `[]` The new initializer's token account, of the same mint, the escrow pays into it from now on
+    /// 4. `[writable]` The current initializer's stats PDA, no longer counts the escrow as open
+    /// 5. `[writable]` The new initializer's stats PDA, counts the escrow as open
+    TransferEscrow { new_initializer: Pubkey },
+    /// Creates the signer's stats PDA, which counts their open escrows. Creating an escrow fails
+    /// with [crate::error::EscrowError::TooManyEscrows] once [crate::state::MAX_OPEN_ESCROWS] are
+    /// open, until some are cancelled or exchanged.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The initializer, pays for the stats account
+    /// 1. `[writable]` The initializer's stats PDA, created by this instruction
+    /// 2. `[]` The system program
+    InitInitializerStats {},
+    /// Returns the program's [crate::PROGRAM_VERSION] through the return data, as 4 little endian
+    /// bytes, so a client can confirm which build is deployed
+    ///
+    /// No accounts expected
+    GetProgramVersion {},
+    /// Sets how far a taker's amount may be from the escrowed amount for an exchange to go
+    /// through, 0 requires an exact match
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin stored in the config account
+    /// 1. `[writable]` The config account
+    SetAmountTolerance { amount_tolerance: u64 },
 }
 
 impl EscrowInstruction {
@@ -55,13 +243,64 @@ impl EscrowInstruction {
         Ok(match tag {
             0 => Self::InitEscrow {
                 amount: Self::unpack_amount(rest)?,
+                offered_mint: Self::unpack_pubkey(rest.get(8..8 + 32).ok_or(InvalidInstruction)?)?,
+                allowed_taker: Self::unpack_optional_pubkey(rest.get(8 + 32..).unwrap_or(&[]))?,
             },
             1 => Self::Exchange {
                 amount: Self::unpack_amount(rest)?,
+                nonce: Self::unpack_amount(rest.get(8..).ok_or(InvalidInstruction)?)?,
+            },
+            2 => Self::Cancel {
+                nonce: Self::unpack_amount(rest)?,
             },
-            2 => Self::Cancel {},
             3 => Self::ResetTimeLock {},
-            _ => return Err(InvalidInstruction.into()),
+            4 => Self::InitEscrowPda {
+                amount: Self::unpack_amount(rest)?,
+                nonce: Self::unpack_amount(rest.get(8..).ok_or(InvalidInstruction)?)?,
+                offered_mint: Self::unpack_pubkey(
+                    rest.get(8 + 8..8 + 8 + 32).ok_or(InvalidInstruction)?,
+                )?,
+                allowed_taker: Self::unpack_optional_pubkey(rest.get(8 + 8 + 32..).unwrap_or(&[]))?,
+            },
+            #[cfg(feature = "debug")]
+            5 => Self::DumpEscrow {},
+            6 => Self::SetAllowedTaker {
+                taker: Self::unpack_optional_pubkey(rest)?,
+            },
+            7 => Self::CancelAll {},
+            8 => Self::InitConfig {},
+            9 => Self::SetPaused {
+                paused: match rest.first() {
+                    Some(0) => false,
+                    Some(1) => true,
+                    _ => return Err(InvalidInstruction.into()),
+                },
+            },
+            10 => Self::CheckTaker {
+                taker: Self::unpack_pubkey(rest)?,
+            },
+            11 => Self::SetReceiveAccount {
+                new_account: Self::unpack_pubkey(rest)?,
+            },
+            12 => Self::HealthCheck {},
+            13 => Self::MergeEscrows {},
+            14 => Self::Freeze {},
+            15 => Self::Unfreeze {},
+            16 => Self::SplitEscrow {
+                amount: Self::unpack_amount(rest)?,
+            },
+            17 => Self::TransferEscrow {
+                new_initializer: Self::unpack_pubkey(rest)?,
+            },
+            18 => Self::InitInitializerStats {},
+            19 => Self::GetProgramVersion {},
+            20 => Self::SetAmountTolerance {
+                amount_tolerance: Self::unpack_amount(rest)?,
+            },
+            _ => {
+                msg!("unknown escrow instruction tag: {}", tag);
+                return Err(InvalidInstruction.into());
+            }
         })
     }
 
@@ -74,28 +313,356 @@ impl EscrowInstruction {
         Ok(amount)
     }
 
-    fn pack(&self) -> Vec<u8> {
-        let mut buf: Vec<u8> = Vec::with_capacity(size_of::<Self>());
+    fn unpack_pubkey(input: &[u8]) -> Result<Pubkey, ProgramError> {
+        let key = input
+            .get(..32)
+            .and_then(|slice| slice.try_into().ok())
+            .map(Pubkey::new_from_array)
+            .ok_or(InvalidInstruction)?;
+        Ok(key)
+    }
+
+    /// an absent pubkey is `None`, keeping the shorter encoding valid
+    fn unpack_optional_pubkey(input: &[u8]) -> Result<Option<Pubkey>, ProgramError> {
+        if input.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self::unpack_pubkey(input)?))
+    }
+
+    /// the exact length of the buffer [EscrowInstruction::pack] produces
+    pub fn packed_len(&self) -> usize {
+        match self {
+            Self::InitEscrow { allowed_taker, .. } => 1 + 8 + 32 + allowed_taker.map_or(0, |_| 32),
+            Self::Exchange { .. } => 1 + 8 + 8,
+            Self::Cancel { .. } | Self::SplitEscrow { .. } | Self::SetAmountTolerance { .. } => {
+                1 + 8
+            }
+            Self::ResetTimeLock {}
+            | Self::CancelAll {}
+            | Self::InitConfig {}
+            | Self::HealthCheck {}
+            | Self::MergeEscrows {}
+            | Self::Freeze {}
+            | Self::Unfreeze {}
+            | Self::InitInitializerStats {}
+            | Self::GetProgramVersion {} => 1,
+            Self::SetPaused { .. } => 1 + 1,
+            Self::InitEscrowPda { allowed_taker, .. } => {
+                1 + 8 + 8 + 32 + allowed_taker.map_or(0, |_| 32)
+            }
+            #[cfg(feature = "debug")]
+            Self::DumpEscrow {} => 1,
+            Self::SetAllowedTaker { taker } => 1 + taker.map_or(0, |_| 32),
+            Self::CheckTaker { .. }
+            | Self::SetReceiveAccount { .. }
+            | Self::TransferEscrow { .. } => 1 + 32,
+        }
+    }
+
+    pub(crate) fn pack(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::with_capacity(self.packed_len());
         match &*self {
-            Self::InitEscrow { amount } => {
+            Self::InitEscrow {
+                amount,
+                offered_mint,
+                allowed_taker,
+            } => {
                 buf.push(0);
                 buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(offered_mint.as_ref());
+                if let Some(allowed_taker) = allowed_taker {
+                    buf.extend_from_slice(allowed_taker.as_ref());
+                }
             }
-            Self::Exchange { amount } => {
+            Self::Exchange { amount, nonce } => {
                 buf.push(1);
                 buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&nonce.to_le_bytes());
             }
-            Self::Cancel {} => {
+            Self::Cancel { nonce } => {
                 buf.push(2);
+                buf.extend_from_slice(&nonce.to_le_bytes());
             }
             Self::ResetTimeLock {} => {
                 buf.push(3);
             }
+            Self::InitEscrowPda {
+                amount,
+                nonce,
+                offered_mint,
+                allowed_taker,
+            } => {
+                buf.push(4);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(&nonce.to_le_bytes());
+                buf.extend_from_slice(offered_mint.as_ref());
+                if let Some(allowed_taker) = allowed_taker {
+                    buf.extend_from_slice(allowed_taker.as_ref());
+                }
+            }
+            #[cfg(feature = "debug")]
+            Self::DumpEscrow {} => {
+                buf.push(5);
+            }
+            Self::SetAllowedTaker { taker } => {
+                buf.push(6);
+                if let Some(taker) = taker {
+                    buf.extend_from_slice(taker.as_ref());
+                }
+            }
+            Self::CancelAll {} => {
+                buf.push(7);
+            }
+            Self::InitConfig {} => {
+                buf.push(8);
+            }
+            Self::SetPaused { paused } => {
+                buf.push(9);
+                buf.push(*paused as u8);
+            }
+            Self::CheckTaker { taker } => {
+                buf.push(10);
+                buf.extend_from_slice(taker.as_ref());
+            }
+            Self::SetReceiveAccount { new_account } => {
+                buf.push(11);
+                buf.extend_from_slice(new_account.as_ref());
+            }
+            Self::HealthCheck {} => {
+                buf.push(12);
+            }
+            Self::MergeEscrows {} => {
+                buf.push(13);
+            }
+            Self::Freeze {} => {
+                buf.push(14);
+            }
+            Self::Unfreeze {} => {
+                buf.push(15);
+            }
+            Self::SplitEscrow { amount } => {
+                buf.push(16);
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::TransferEscrow { new_initializer } => {
+                buf.push(17);
+                buf.extend_from_slice(new_initializer.as_ref());
+            }
+            Self::InitInitializerStats {} => buf.push(18),
+            Self::GetProgramVersion {} => buf.push(19),
+            Self::SetAmountTolerance { amount_tolerance } => {
+                buf.push(20);
+                buf.extend_from_slice(&amount_tolerance.to_le_bytes());
+            }
         }
         buf
     }
 }
 
+/// The name of the [EscrowInstruction] variant packed with `tag`, "Unknown" for any other tag.
+/// Labels a transaction without unpacking it, `DumpEscrow` is named even without the `debug` feature.
+pub fn tag_name(tag: u8) -> &'static str {
+    match tag {
+        0 => "InitEscrow",
+        1 => "Exchange",
+        2 => "Cancel",
+        3 => "ResetTimeLock",
+        4 => "InitEscrowPda",
+        5 => "DumpEscrow",
+        6 => "SetAllowedTaker",
+        7 => "CancelAll",
+        8 => "InitConfig",
+        9 => "SetPaused",
+        10 => "CheckTaker",
+        11 => "SetReceiveAccount",
+        12 => "HealthCheck",
+        13 => "MergeEscrows",
+        14 => "Freeze",
+        15 => "Unfreeze",
+        16 => "SplitEscrow",
+        17 => "TransferEscrow",
+        18 => "InitInitializerStats",
+        19 => "GetProgramVersion",
+        20 => "SetAmountTolerance",
+        _ => "Unknown",
+    }
+}
+
+/// Whether `data` has a known tag followed by exactly the payload length of that variant, a cheap
+/// check for a router that multiplexes several programs. Payload bytes are not validated, so
+/// [EscrowInstruction::unpack] may still reject the data.
+pub fn looks_like_escrow(data: &[u8]) -> bool {
+    let (tag, rest) = match data.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+    let payload_lens: &[usize] = match tag {
+        0 => &[8 + 32, 8 + 32 + 32],
+        1 => &[8 + 8],
+        4 => &[8 + 8 + 32, 8 + 8 + 32 + 32],
+        2 | 16 | 20 => &[8],
+        3 | 7 | 8 | 12 | 13 | 14 | 15 | 18 | 19 => &[0],
+        #[cfg(feature = "debug")]
+        5 => &[0],
+        6 => &[0, 32],
+        9 => &[1],
+        10 | 11 | 17 => &[32],
+        _ => return false,
+    };
+    payload_lens.contains(&rest.len())
+}
+
+/// Decodes the escrow instruction carried by `instr`, for inspecting a transaction
+pub fn decode(instr: &Instruction) -> Result<EscrowInstruction, ProgramError> {
+    EscrowInstruction::unpack(&instr.data)
+}
+
+/// Packs each instruction into its own data buffer, in order, for clients assembling several
+/// escrow instructions (e.g. an init followed by an exchange) into one atomic `Message`
+pub fn pack_many(instrs: &[EscrowInstruction]) -> Vec<Vec<u8>> {
+    instrs.iter().map(EscrowInstruction::pack).collect()
+}
+
+/// Rough compute units an instruction consumes, a hint for setting a compute budget and not a
+/// guarantee. Exchange makes three token program CPIs, init one, cancel two cheaper ones.
+/// [EscrowInstruction::CancelAll] costs this much per escrow cancelled.
+pub fn estimated_cu(instr: &EscrowInstruction) -> u32 {
+    match instr {
+        EscrowInstruction::InitEscrow { .. } => 20_000,
+        EscrowInstruction::InitEscrowPda { .. } => 27_000,
+        EscrowInstruction::Exchange { .. } => 35_000,
+        EscrowInstruction::Cancel { .. }
+        | EscrowInstruction::CancelAll {}
+        | EscrowInstruction::MergeEscrows {}
+        | EscrowInstruction::SplitEscrow { .. } => 17_000,
+        EscrowInstruction::InitConfig {} | EscrowInstruction::InitInitializerStats {} => 10_000,
+        EscrowInstruction::ResetTimeLock {}
+        | EscrowInstruction::SetAllowedTaker { .. }
+        | EscrowInstruction::SetPaused { .. }
+        | EscrowInstruction::SetAmountTolerance { .. }
+        | EscrowInstruction::CheckTaker { .. }
+        | EscrowInstruction::SetReceiveAccount { .. }
+        | EscrowInstruction::TransferEscrow { .. }
+        | EscrowInstruction::HealthCheck {}
+        | EscrowInstruction::Freeze {}
+        | EscrowInstruction::Unfreeze {}
+        | EscrowInstruction::GetProgramVersion {} => 5_000,
+        #[cfg(feature = "debug")]
+        EscrowInstruction::DumpEscrow {} => 10_000,
+    }
+}
+
+/// Indices of the accounts the program writes for an instruction, itself or through its token
+/// program CPIs, for clients that lock accounts precisely. [EscrowInstruction::CancelAll] only
+/// lists the initializer, every account of its trailing escrow triples is written as well.
+pub fn writable_accounts(instr: &EscrowInstruction) -> Vec<usize> {
+    match instr {
+        EscrowInstruction::InitEscrow { .. } => vec![1, 3, 7],
+        EscrowInstruction::Exchange { .. } => vec![1, 2, 3, 4, 5, 6, 10],
+        EscrowInstruction::Cancel { .. } => vec![0, 1, 2, 3, 5],
+        EscrowInstruction::InitEscrowPda { .. } => vec![0, 1, 3, 7],
+        EscrowInstruction::CancelAll {} => vec![0, 3],
+        EscrowInstruction::InitConfig {} | EscrowInstruction::InitInitializerStats {} => {
+            vec![0, 1]
+        }
+        EscrowInstruction::MergeEscrows {} => vec![0, 1, 2, 3, 4, 7],
+        EscrowInstruction::SplitEscrow { .. } => vec![1, 2, 3, 4, 7],
+        EscrowInstruction::TransferEscrow { .. } => vec![1, 4, 5],
+        EscrowInstruction::ResetTimeLock {}
+        | EscrowInstruction::SetAllowedTaker { .. }
+        | EscrowInstruction::SetPaused { .. }
+        | EscrowInstruction::SetAmountTolerance { .. }
+        | EscrowInstruction::SetReceiveAccount { .. }
+        | EscrowInstruction::Freeze {}
+        | EscrowInstruction::Unfreeze {} => vec![1],
+        EscrowInstruction::CheckTaker { .. }
+        | EscrowInstruction::HealthCheck {}
+        | EscrowInstruction::GetProgramVersion {} => vec![],
+        #[cfg(feature = "debug")]
+        EscrowInstruction::DumpEscrow {} => vec![],
+    }
+}
+
+/// Derives the escrow account address for an initializer and nonce
+pub fn find_escrow_address(program_id: &Pubkey, initializer: &Pubkey, nonce: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"escrow", initializer.as_ref(), &nonce.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// A stable 32 byte key for the escrow of an initializer and nonce, for client side indexes and
+/// lookup tables. It is a plain hash and not the escrow's address, see [find_escrow_address].
+pub fn escrow_id(initializer: &Pubkey, nonce: u64) -> [u8; 32] {
+    hashv(&[b"escrow_id", initializer.as_ref(), &nonce.to_le_bytes()]).to_bytes()
+}
+
+/// Whether `candidate` is the PDA that owns every escrow's temp token account
+pub fn is_escrow_authority(program_id: &Pubkey, candidate: &Pubkey) -> bool {
+    Pubkey::find_program_address(&[b"escrow"], program_id).0 == *candidate
+}
+
+/// Derives the address of the ProgramData account the upgradeable loader keeps for the program
+pub fn find_program_data_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+/// Derives the address of the program's config account
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"config"], program_id)
+}
+
+/// Derives the address of the stats account counting an initializer's open escrows
+pub fn find_initializer_stats_address(program_id: &Pubkey, initializer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"initializer_stats", initializer.as_ref()], program_id)
+}
+
+/// [EscrowInstruction::HealthCheck] bit: the escrow account is not owned by this program
+pub const HEALTH_ESCROW_OWNER: u8 = 1 << 0;
+/// [EscrowInstruction::HealthCheck] bit: the escrow account doesn't hold an initialized escrow
+pub const HEALTH_ESCROW_DATA: u8 = 1 << 1;
+/// [EscrowInstruction::HealthCheck] bit: the temp account is not the escrow's or not a token account
+pub const HEALTH_TEMP_ACCOUNT: u8 = 1 << 2;
+/// [EscrowInstruction::HealthCheck] bit: the temp account is not owned by the escrow authority PDA
+pub const HEALTH_TEMP_AUTHORITY: u8 = 1 << 3;
+/// [EscrowInstruction::HealthCheck] bit: the temp account holds no tokens to hand to a taker
+pub const HEALTH_TEMP_BALANCE: u8 = 1 << 4;
+
+/// An amount in the token's base units, built explicitly so `5` can't be mistaken for 5 tokens
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub fn from_base_units(base_units: u64) -> Self {
+        Amount(base_units)
+    }
+
+    /// Converts a UI amount, e.g. 5.0 tokens of a 6 decimals mint is 5_000_000 base units
+    pub fn from_ui(ui_amount: f64, decimals: u8) -> Self {
+        Amount((ui_amount * 10f64.powi(decimals as i32)).round() as u64)
+    }
+
+    pub fn base_units(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The amount the builders take, the `strict-amounts` feature requires an [Amount]
+#[cfg(feature = "strict-amounts")]
+pub type BuilderAmount = Amount;
+#[cfg(not(feature = "strict-amounts"))]
+pub type BuilderAmount = u64;
+
+#[cfg(feature = "strict-amounts")]
+fn base_units(amount: BuilderAmount) -> u64 {
+    amount.base_units()
+}
+#[cfg(not(feature = "strict-amounts"))]
+fn base_units(amount: BuilderAmount) -> u64 {
+    amount
+}
+
 pub fn init_escrow(
     program_id: &Pubkey,
     initiator: &Pubkey,
@@ -103,15 +670,28 @@ pub fn init_escrow(
     initializer_token_account: &Pubkey,
     escrow_account: &Pubkey,
     token_program: &Pubkey,
-    amount: u64,
+    offered_mint: &Pubkey,
+    amount: BuilderAmount,
+    allowed_taker: Option<Pubkey>,
 ) -> Result<Instruction, ProgramError> {
-    let data = EscrowInstruction::InitEscrow { amount }.pack();
+    let data = EscrowInstruction::InitEscrow {
+        amount: base_units(amount),
+        offered_mint: *offered_mint,
+        allowed_taker,
+    }
+    .pack();
     let accounts = vec![
         AccountMeta::new(*initiator, true),
         AccountMeta::new(*temp_token_account, false),
         AccountMeta::new_readonly(*initializer_token_account, false),
         AccountMeta::new(*escrow_account, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
         AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, initiator).0,
+            false,
+        ),
     ];
     Ok(Instruction {
         program_id: *program_id,
@@ -120,6 +700,27 @@ pub fn init_escrow(
     })
 }
 
+/// Checks an account list against the layout documented on [EscrowInstruction::InitEscrow]
+/// before it is sent
+pub fn validate_init_escrow_accounts(metas: &[AccountMeta]) -> Result<(), ProgramError> {
+    if metas.len() < 8 {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if metas.len() > 9 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if !metas[0].is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !metas[1].is_writable || !metas[3].is_writable || !metas[7].is_writable {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if metas[4].pubkey != sysvar::rent::id() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
 pub fn exchange(
     program_id: &Pubkey,
     tacker: &Pubkey,
@@ -130,18 +731,30 @@ pub fn exchange(
     initializer_token_account: &Pubkey,
     escrow_account: &Pubkey,
     token_program: &Pubkey,
-    amount: u64,
+    amount: BuilderAmount,
+    nonce: u64,
 ) -> Result<Instruction, ProgramError> {
-    let data = EscrowInstruction::Exchange { amount }.pack();
+    let data = EscrowInstruction::Exchange {
+        amount: base_units(amount),
+        nonce,
+    }
+    .pack();
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
     let accounts = vec![
         AccountMeta::new(*tacker, true),
         AccountMeta::new(*tacker_token_account, false),
         AccountMeta::new(*tacker_token_account2, false),
-        AccountMeta::new(*initiator, false),
         AccountMeta::new(*temp_token_account, false),
+        AccountMeta::new(*initiator, false),
         AccountMeta::new(*initializer_token_account, false),
         AccountMeta::new(*escrow_account, false),
         AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, initiator).0,
+            false,
+        ),
     ];
     Ok(Instruction {
         program_id: *program_id,
@@ -150,6 +763,61 @@ pub fn exchange(
     })
 }
 
+/// Creates `owner`'s associated token account for `mint`, nothing when the caller knows it exists
+pub fn ensure_ata(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    ata_exists: bool,
+) -> Option<Instruction> {
+    if ata_exists {
+        return None;
+    }
+    Some(
+        spl_associated_token_account::instruction::create_associated_token_account(
+            payer,
+            owner,
+            mint,
+            &spl_token::id(),
+        ),
+    )
+}
+
+/// [exchange] paying the taker into their associated token account for `receive_mint`, creating
+/// it first unless `ata_exists`
+pub fn exchange_with_ata(
+    program_id: &Pubkey,
+    tacker: &Pubkey,
+    tacker_token_account: &Pubkey,
+    receive_mint: &Pubkey,
+    initiator: &Pubkey,
+    temp_token_account: &Pubkey,
+    initializer_token_account: &Pubkey,
+    escrow_account: &Pubkey,
+    amount: BuilderAmount,
+    nonce: u64,
+    ata_exists: bool,
+) -> Result<Vec<Instruction>, ProgramError> {
+    let ata = spl_associated_token_account::get_associated_token_address(tacker, receive_mint);
+    let mut instructions: Vec<Instruction> = ensure_ata(tacker, tacker, receive_mint, ata_exists)
+        .into_iter()
+        .collect();
+    instructions.push(exchange(
+        program_id,
+        tacker,
+        tacker_token_account,
+        &ata,
+        initiator,
+        temp_token_account,
+        initializer_token_account,
+        escrow_account,
+        &spl_token::id(),
+        amount,
+        nonce,
+    )?);
+    Ok(instructions)
+}
+
 pub fn cancel(
     program_id: &Pubkey,
     initiator: &Pubkey,
@@ -157,14 +825,19 @@ pub fn cancel(
     initializer_token_account: &Pubkey,
     escrow_account: &Pubkey,
     token_program: &Pubkey,
+    nonce: u64,
 ) -> Result<Instruction, ProgramError> {
-    let data = EscrowInstruction::Cancel {}.pack();
+    let data = EscrowInstruction::Cancel { nonce }.pack();
     let accounts = vec![
         AccountMeta::new(*initiator, true),
         AccountMeta::new(*temp_token_account, false),
-        AccountMeta::new_readonly(*initializer_token_account, false),
+        AccountMeta::new(*initializer_token_account, false),
         AccountMeta::new(*escrow_account, false),
         AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, initiator).0,
+            false,
+        ),
     ];
     Ok(Instruction {
         program_id: *program_id,
@@ -178,9 +851,66 @@ pub fn reset_time_lock(
     initiator: &Pubkey,
     escrow_account: &Pubkey,
 ) -> Result<Instruction, ProgramError> {
-    let data = EscrowInstruction::Cancel {}.pack();
+    let data = EscrowInstruction::ResetTimeLock {}.pack();
+    let accounts = vec![
+        AccountMeta::new(*initiator, true),
+        AccountMeta::new(*escrow_account, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn init_escrow_pda(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    temp_token_account: &Pubkey,
+    initializer_token_account: &Pubkey,
+    token_program: &Pubkey,
+    offered_mint: &Pubkey,
+    amount: BuilderAmount,
+    nonce: u64,
+    allowed_taker: Option<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitEscrowPda {
+        amount: base_units(amount),
+        nonce,
+        offered_mint: *offered_mint,
+        allowed_taker,
+    }
+    .pack();
+    let (escrow_account, _bump_seed) = find_escrow_address(program_id, initiator, nonce);
     let accounts = vec![
         AccountMeta::new(*initiator, true),
+        AccountMeta::new(*temp_token_account, false),
+        AccountMeta::new_readonly(*initializer_token_account, false),
+        AccountMeta::new(escrow_account, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, initiator).0,
+            false,
+        ),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_allowed_taker(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    escrow_account: &Pubkey,
+    taker: Option<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::SetAllowedTaker { taker }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*initiator, true),
         AccountMeta::new(*escrow_account, false),
     ];
     Ok(Instruction {
@@ -189,3 +919,808 @@ pub fn reset_time_lock(
         data,
     })
 }
+
+/// `escrows` holds the escrow account, temp token account and initializer token account of
+/// every escrow to cancel
+pub fn cancel_all(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    token_program: &Pubkey,
+    escrows: &[(Pubkey, Pubkey, Pubkey)],
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::CancelAll {}.pack();
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+    let mut accounts = vec![
+        AccountMeta::new(*initiator, true),
+        AccountMeta::new_readonly(*token_program, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, initiator).0,
+            false,
+        ),
+    ];
+    for (escrow_account, temp_token_account, initializer_token_account) in escrows {
+        accounts.push(AccountMeta::new(*escrow_account, false));
+        accounts.push(AccountMeta::new(*temp_token_account, false));
+        accounts.push(AccountMeta::new(*initializer_token_account, false));
+    }
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn init_config(program_id: &Pubkey, admin: &Pubkey) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitConfig {}.pack();
+    let accounts = vec![
+        AccountMeta::new(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(find_program_data_address(program_id), false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn init_initializer_stats(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitInitializerStats {}.pack();
+    let accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, initializer).0,
+            false,
+        ),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_paused(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    paused: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::SetPaused { paused }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_amount_tolerance(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    amount_tolerance: BuilderAmount,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::SetAmountTolerance {
+        amount_tolerance: base_units(amount_tolerance),
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(find_config_address(program_id).0, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn check_taker(
+    program_id: &Pubkey,
+    escrow_account: &Pubkey,
+    taker: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::CheckTaker { taker: *taker }.pack();
+    let accounts = vec![AccountMeta::new_readonly(*escrow_account, false)];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn health_check(
+    program_id: &Pubkey,
+    escrow_account: &Pubkey,
+    temp_token_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::HealthCheck {}.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*escrow_account, false),
+        AccountMeta::new_readonly(*temp_token_account, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn get_program_version(program_id: &Pubkey) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::GetProgramVersion {}.pack();
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: vec![],
+        data,
+    })
+}
+
+pub fn freeze(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    escrow_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Freeze {}.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*initiator, true),
+        AccountMeta::new(*escrow_account, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn unfreeze(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    escrow_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Unfreeze {}.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*initiator, true),
+        AccountMeta::new(*escrow_account, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn merge_escrows(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    escrow_a: &Pubkey,
+    temp_token_account_a: &Pubkey,
+    escrow_b: &Pubkey,
+    temp_token_account_b: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::MergeEscrows {}.pack();
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+    let accounts = vec![
+        AccountMeta::new(*initiator, true),
+        AccountMeta::new(*escrow_a, false),
+        AccountMeta::new(*temp_token_account_a, false),
+        AccountMeta::new(*escrow_b, false),
+        AccountMeta::new(*temp_token_account_b, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, initiator).0,
+            false,
+        ),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn split_escrow(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    escrow_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    new_escrow_account: &Pubkey,
+    new_temp_token_account: &Pubkey,
+    amount: BuilderAmount,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::SplitEscrow {
+        amount: base_units(amount),
+    }
+    .pack();
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+    let accounts = vec![
+        AccountMeta::new_readonly(*initiator, true),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new(*temp_token_account, false),
+        AccountMeta::new(*new_escrow_account, false),
+        AccountMeta::new(*new_temp_token_account, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, initiator).0,
+            false,
+        ),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_receive_account(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    escrow_account: &Pubkey,
+    current_receive_account: &Pubkey,
+    new_receive_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::SetReceiveAccount {
+        new_account: *new_receive_account,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*initiator, true),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new_readonly(*current_receive_account, false),
+        AccountMeta::new_readonly(*new_receive_account, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Hands the escrow over to `new_initializer`, who is paid into `new_receive_account` from now on
+pub fn transfer_escrow(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    escrow_account: &Pubkey,
+    current_receive_account: &Pubkey,
+    new_initializer: &Pubkey,
+    new_receive_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::TransferEscrow {
+        new_initializer: *new_initializer,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*initiator, true),
+        AccountMeta::new(*escrow_account, false),
+        AccountMeta::new_readonly(*current_receive_account, false),
+        AccountMeta::new_readonly(*new_receive_account, false),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, initiator).0,
+            false,
+        ),
+        AccountMeta::new(
+            find_initializer_stats_address(program_id, new_initializer).0,
+            false,
+        ),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// The instructions an initializer can send for an existing escrow: cancel, reset its time lock
+/// and clear its allowed taker, in that order. `nonce` is the escrow's current nonce.
+pub fn management_instructions(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    escrow_account: &Pubkey,
+    temp_token_account: &Pubkey,
+    initializer_token_account: &Pubkey,
+    nonce: u64,
+) -> Result<Vec<Instruction>, ProgramError> {
+    Ok(vec![
+        cancel(
+            program_id,
+            initializer,
+            temp_token_account,
+            initializer_token_account,
+            escrow_account,
+            &spl_token::id(),
+            nonce,
+        )?,
+        reset_time_lock(program_id, initializer, escrow_account)?,
+        set_allowed_taker(program_id, initializer, escrow_account, None)?,
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::log_recorder;
+
+    #[cfg(feature = "strict-amounts")]
+    fn builder_amount(base_units: u64) -> BuilderAmount {
+        Amount::from_base_units(base_units)
+    }
+    #[cfg(not(feature = "strict-amounts"))]
+    fn builder_amount(base_units: u64) -> BuilderAmount {
+        base_units
+    }
+
+    #[test]
+    fn test_decode_exchange() {
+        let ix = exchange(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &spl_token::id(),
+            builder_amount(42),
+            3,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            decode(&ix),
+            Ok(EscrowInstruction::Exchange {
+                amount: 42,
+                nonce: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_split_escrow() {
+        let ix = split_escrow(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            builder_amount(4),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            decode(&ix),
+            Ok(EscrowInstruction::SplitEscrow { amount: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_exchange_with_ata_prepends_create() {
+        let program_id = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let build = |ata_exists| {
+            exchange_with_ata(
+                &program_id,
+                &taker,
+                &Pubkey::new_unique(),
+                &mint,
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                builder_amount(5),
+                0,
+                ata_exists,
+            )
+            .unwrap()
+        };
+        let ata = spl_associated_token_account::get_associated_token_address(&taker, &mint);
+
+        let ixs = build(false);
+        assert_eq!(ixs.len(), 2);
+        assert_eq!(ixs[0].program_id, spl_associated_token_account::id());
+        assert_eq!(ixs[1].program_id, program_id);
+        assert_eq!(ixs[1].accounts[2].pubkey, ata);
+
+        let ixs = build(true);
+        assert_eq!(ixs.len(), 1);
+        assert_eq!(ixs[0].program_id, program_id);
+    }
+
+    #[test]
+    fn test_amount_from_ui() {
+        assert_eq!(Amount::from_ui(5.0, 6).base_units(), 5_000_000);
+        assert_eq!(Amount::from_ui(0.5, 9).base_units(), 500_000_000);
+        assert_eq!(Amount::from_ui(5.0, 0), Amount::from_base_units(5));
+    }
+
+    #[test]
+    fn test_unknown_tag_is_logged() {
+        log_recorder::install();
+
+        assert!(matches!(
+            EscrowInstruction::unpack(&[99]),
+            Err(ProgramError::Custom(code)) if code == InvalidInstruction as u32
+        ));
+        assert!(log_recorder::logged("unknown escrow instruction tag: 99"));
+    }
+
+    #[test]
+    fn test_management_instructions() {
+        let program_id = Pubkey::new_unique();
+        let initializer = Pubkey::new_unique();
+        let escrow_account = Pubkey::new_unique();
+        let ixs = management_instructions(
+            &program_id,
+            &initializer,
+            &escrow_account,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            0,
+        )
+        .unwrap();
+
+        let tags: Vec<u8> = ixs.iter().map(|ix| ix.data[0]).collect();
+        assert_eq!(tags, vec![2, 3, 6]);
+        for ix in &ixs {
+            assert_eq!(ix.program_id, program_id);
+            assert_eq!(ix.accounts[0].pubkey, initializer);
+            assert!(ix.accounts[0].is_signer);
+            assert!(ix.accounts.iter().any(|meta| meta.pubkey == escrow_account));
+        }
+    }
+
+    #[test]
+    fn test_is_escrow_authority() {
+        let program_id = Pubkey::new_unique();
+        let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+
+        assert!(is_escrow_authority(&program_id, &pda));
+        assert!(!is_escrow_authority(&program_id, &Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_escrow_address_is_deterministic() {
+        let program_id = Pubkey::new_unique();
+        let initializer = Pubkey::new_unique();
+
+        let first = find_escrow_address(&program_id, &initializer, 7);
+        let second = find_escrow_address(&program_id, &initializer, 7);
+        assert_eq!(first, second);
+
+        let (other_nonce, _) = find_escrow_address(&program_id, &initializer, 8);
+        assert_ne!(first.0, other_nonce);
+    }
+
+    #[test]
+    fn test_tag_name() {
+        let names: Vec<&str> = (0..=20).map(tag_name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "InitEscrow",
+                "Exchange",
+                "Cancel",
+                "ResetTimeLock",
+                "InitEscrowPda",
+                "DumpEscrow",
+                "SetAllowedTaker",
+                "CancelAll",
+                "InitConfig",
+                "SetPaused",
+                "CheckTaker",
+                "SetReceiveAccount",
+                "HealthCheck",
+                "MergeEscrows",
+                "Freeze",
+                "Unfreeze",
+                "SplitEscrow",
+                "TransferEscrow",
+                "InitInitializerStats",
+                "GetProgramVersion",
+                "SetAmountTolerance",
+            ]
+        );
+        assert_eq!(tag_name(21), "Unknown");
+        assert_eq!(tag_name(u8::MAX), "Unknown");
+    }
+
+    #[test]
+    fn test_escrow_id() {
+        let initializer = Pubkey::new_unique();
+
+        assert_eq!(escrow_id(&initializer, 1), escrow_id(&initializer, 1));
+        assert_ne!(escrow_id(&initializer, 1), escrow_id(&initializer, 2));
+        assert_ne!(
+            escrow_id(&initializer, 1),
+            escrow_id(&Pubkey::new_unique(), 1)
+        );
+        assert_ne!(
+            escrow_id(&initializer, 1),
+            find_escrow_address(&Pubkey::new_unique(), &initializer, 1)
+                .0
+                .to_bytes()
+        );
+    }
+
+    #[test]
+    fn test_packed_len_matches_pack() {
+        let instructions = [
+            EscrowInstruction::InitEscrow {
+                amount: 1,
+                offered_mint: Pubkey::new_unique(),
+                allowed_taker: None,
+            },
+            EscrowInstruction::InitEscrow {
+                amount: 1,
+                offered_mint: Pubkey::new_unique(),
+                allowed_taker: Some(Pubkey::new_unique()),
+            },
+            EscrowInstruction::Exchange {
+                amount: u64::MAX,
+                nonce: 1,
+            },
+            EscrowInstruction::Cancel { nonce: 1 },
+            EscrowInstruction::ResetTimeLock {},
+            EscrowInstruction::InitEscrowPda {
+                amount: 5,
+                nonce: 9,
+                offered_mint: Pubkey::new_unique(),
+                allowed_taker: None,
+            },
+            EscrowInstruction::InitEscrowPda {
+                amount: 5,
+                nonce: 9,
+                offered_mint: Pubkey::new_unique(),
+                allowed_taker: Some(Pubkey::new_unique()),
+            },
+            EscrowInstruction::SetAllowedTaker { taker: None },
+            EscrowInstruction::SetAllowedTaker {
+                taker: Some(Pubkey::new_unique()),
+            },
+            EscrowInstruction::CancelAll {},
+            EscrowInstruction::InitConfig {},
+            EscrowInstruction::SetPaused { paused: true },
+            EscrowInstruction::CheckTaker {
+                taker: Pubkey::new_unique(),
+            },
+            EscrowInstruction::SetReceiveAccount {
+                new_account: Pubkey::new_unique(),
+            },
+            EscrowInstruction::HealthCheck {},
+            EscrowInstruction::MergeEscrows {},
+            EscrowInstruction::Freeze {},
+            EscrowInstruction::Unfreeze {},
+            EscrowInstruction::SplitEscrow { amount: u64::MAX },
+            EscrowInstruction::TransferEscrow {
+                new_initializer: Pubkey::new_unique(),
+            },
+            EscrowInstruction::InitInitializerStats {},
+            EscrowInstruction::GetProgramVersion {},
+            EscrowInstruction::SetAmountTolerance {
+                amount_tolerance: u64::MAX,
+            },
+        ];
+
+        for instruction in instructions.iter() {
+            assert_eq!(instruction.pack().len(), instruction.packed_len());
+            assert!(looks_like_escrow(&instruction.pack()));
+        }
+    }
+
+    #[test]
+    fn test_looks_like_escrow_rejects_foreign_data() {
+        let exchange = EscrowInstruction::Exchange {
+            amount: 1,
+            nonce: 0,
+        }
+        .pack();
+
+        assert!(!looks_like_escrow(&[]));
+        assert!(!looks_like_escrow(&[17]));
+        assert!(!looks_like_escrow(&[255, 0, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(!looks_like_escrow(&exchange[..9]));
+        assert!(!looks_like_escrow(&[exchange.as_slice(), &[0]].concat()));
+        assert!(!looks_like_escrow(&[0; 10]));
+        assert!(!looks_like_escrow(&[3, 0]));
+        assert!(!looks_like_escrow(&[6; 17]));
+    }
+
+    #[test]
+    fn test_pack_many_round_trips() {
+        let instructions = [
+            EscrowInstruction::InitEscrow {
+                amount: 7,
+                offered_mint: Pubkey::new_unique(),
+                allowed_taker: Some(Pubkey::new_unique()),
+            },
+            EscrowInstruction::Exchange {
+                amount: 7,
+                nonce: 0,
+            },
+            EscrowInstruction::Cancel { nonce: 0 },
+            EscrowInstruction::SetPaused { paused: false },
+        ];
+
+        let packed = pack_many(&instructions);
+        assert_eq!(packed.len(), instructions.len());
+        for (buf, source) in packed.iter().zip(instructions.iter()) {
+            let unpacked = EscrowInstruction::unpack(buf).unwrap();
+            assert_eq!(
+                std::mem::discriminant(&unpacked),
+                std::mem::discriminant(source)
+            );
+            assert_eq!(unpacked.pack(), *buf);
+        }
+    }
+
+    #[test]
+    fn test_validate_init_escrow_accounts() {
+        let ix = init_escrow(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &spl_token::id(),
+            &Pubkey::new_unique(),
+            builder_amount(10),
+            None,
+        )
+        .unwrap();
+        assert_eq!(validate_init_escrow_accounts(&ix.accounts), Ok(()));
+
+        assert_eq!(
+            validate_init_escrow_accounts(&ix.accounts[..6]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+
+        let mut unsigned = ix.accounts.clone();
+        unsigned[0].is_signer = false;
+        assert_eq!(
+            validate_init_escrow_accounts(&unsigned),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        let mut readonly_escrow = ix.accounts.clone();
+        readonly_escrow[3].is_writable = false;
+        assert_eq!(
+            validate_init_escrow_accounts(&readonly_escrow),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_estimated_cu_ordering() {
+        let init = estimated_cu(&EscrowInstruction::InitEscrow {
+            amount: 1,
+            offered_mint: Pubkey::new_unique(),
+            allowed_taker: None,
+        });
+        let exchange = estimated_cu(&EscrowInstruction::Exchange {
+            amount: 1,
+            nonce: 0,
+        });
+        let cancel = estimated_cu(&EscrowInstruction::Cancel { nonce: 0 });
+
+        assert!(exchange > init);
+        assert!(init > cancel);
+    }
+
+    #[test]
+    fn test_writable_accounts() {
+        let cases = [
+            (
+                EscrowInstruction::InitEscrow {
+                    amount: 1,
+                    offered_mint: Pubkey::new_unique(),
+                    allowed_taker: None,
+                },
+                vec![1, 3, 7],
+            ),
+            (
+                EscrowInstruction::Exchange {
+                    amount: 1,
+                    nonce: 0,
+                },
+                vec![1, 2, 3, 4, 5, 6, 10],
+            ),
+            (EscrowInstruction::Cancel { nonce: 0 }, vec![0, 1, 2, 3, 5]),
+            (EscrowInstruction::ResetTimeLock {}, vec![1]),
+            (
+                EscrowInstruction::InitEscrowPda {
+                    amount: 1,
+                    nonce: 0,
+                    offered_mint: Pubkey::new_unique(),
+                    allowed_taker: None,
+                },
+                vec![0, 1, 3, 7],
+            ),
+            (EscrowInstruction::SetAllowedTaker { taker: None }, vec![1]),
+            (EscrowInstruction::CancelAll {}, vec![0, 3]),
+            (EscrowInstruction::InitConfig {}, vec![0, 1]),
+            (EscrowInstruction::SetPaused { paused: true }, vec![1]),
+            (
+                EscrowInstruction::CheckTaker {
+                    taker: Pubkey::new_unique(),
+                },
+                vec![],
+            ),
+            (
+                EscrowInstruction::SetReceiveAccount {
+                    new_account: Pubkey::new_unique(),
+                },
+                vec![1],
+            ),
+            (EscrowInstruction::HealthCheck {}, vec![]),
+            (EscrowInstruction::MergeEscrows {}, vec![0, 1, 2, 3, 4, 7]),
+            (EscrowInstruction::Freeze {}, vec![1]),
+            (EscrowInstruction::Unfreeze {}, vec![1]),
+            (
+                EscrowInstruction::SplitEscrow { amount: 1 },
+                vec![1, 2, 3, 4, 7],
+            ),
+            (
+                EscrowInstruction::TransferEscrow {
+                    new_initializer: Pubkey::new_unique(),
+                },
+                vec![1, 4, 5],
+            ),
+            (EscrowInstruction::InitInitializerStats {}, vec![0, 1]),
+            (EscrowInstruction::GetProgramVersion {}, vec![]),
+            (
+                EscrowInstruction::SetAmountTolerance {
+                    amount_tolerance: 1,
+                },
+                vec![1],
+            ),
+        ];
+
+        for (instruction, expected) in cases.iter() {
+            assert_eq!(&writable_accounts(instruction), expected);
+        }
+    }
+
+    #[test]
+    fn test_writable_accounts_are_writable_in_builders() {
+        let key = Pubkey::new_unique();
+        let builders = [
+            cancel(&key, &key, &key, &key, &key, &key, 0).unwrap(),
+            exchange(
+                &key,
+                &key,
+                &key,
+                &key,
+                &key,
+                &key,
+                &key,
+                &key,
+                &key,
+                builder_amount(1),
+                0,
+            )
+            .unwrap(),
+            init_initializer_stats(&key, &key).unwrap(),
+            set_amount_tolerance(&key, &key, builder_amount(1)).unwrap(),
+            merge_escrows(&key, &key, &key, &key, &key, &key).unwrap(),
+            split_escrow(&key, &key, &key, &key, &key, &key, builder_amount(1)).unwrap(),
+            transfer_escrow(&key, &key, &key, &key, &key, &key).unwrap(),
+        ];
+
+        for ix in builders.iter() {
+            for index in writable_accounts(&decode(ix).unwrap()) {
+                assert!(ix.accounts[index].is_writable);
+            }
+        }
+    }
+}
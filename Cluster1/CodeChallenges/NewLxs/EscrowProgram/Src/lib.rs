@@ -1,5 +1,53 @@
+pub mod accounts;
+#[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
 pub mod error;
+pub mod event;
 pub mod instruction;
+pub mod math;
 pub mod processor;
 pub mod state;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// The crate version `major.minor.patch` this program was built from, as
+/// `major * 1_000_000 + minor * 1_000 + patch`, so clients can tell which build is deployed
+pub const PROGRAM_VERSION: u32 = number(env!("CARGO_PKG_VERSION_MAJOR")) * 1_000_000
+    + number(env!("CARGO_PKG_VERSION_MINOR")) * 1_000
+    + number(env!("CARGO_PKG_VERSION_PATCH"));
+
+/// Cargo hands out each version part as a string of decimal digits
+const fn number(digits: &str) -> u32 {
+    let bytes = digits.as_bytes();
+    let mut value = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0') as u32;
+        i += 1;
+    }
+    value
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_program_version() {
+        let version = format!(
+            "{}.{}.{}",
+            PROGRAM_VERSION / 1_000_000,
+            PROGRAM_VERSION / 1_000 % 1_000,
+            PROGRAM_VERSION % 1_000
+        );
+        // a pre-release or build suffix doesn't change the version number
+        assert_eq!(
+            env!("CARGO_PKG_VERSION").split(['-', '+']).next(),
+            Some(version.as_str())
+        );
+        assert_eq!(number("12"), 12);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::error::EscrowError;
+
+/// `a + b`, failing with [EscrowError::AmountOverflow] instead of wrapping
+pub fn add(a: u64, b: u64) -> Result<u64, EscrowError> {
+    a.checked_add(b).ok_or(EscrowError::AmountOverflow)
+}
+
+/// `a - b`, failing with [EscrowError::AmountOverflow] instead of wrapping
+pub fn sub(a: u64, b: u64) -> Result<u64, EscrowError> {
+    a.checked_sub(b).ok_or(EscrowError::AmountOverflow)
+}
+
+/// `a * b`, failing with [EscrowError::AmountOverflow] instead of wrapping
+pub fn mul(a: u64, b: u64) -> Result<u64, EscrowError> {
+    a.checked_mul(b).ok_or(EscrowError::AmountOverflow)
+}
+
+/// Basis points in a whole, a fee of [BPS_DENOMINATOR] bps takes everything
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// What a taker is left with of `gross` after a fee of `fee_bps` basis points, the fee rounded
+/// down. Fails with [EscrowError::AmountOverflow] if `gross * fee_bps` overflows or the fee is
+/// above 100%.
+///
+/// Only clients call this: the program stores no fee rate and Exchange pays the taker the whole
+/// temp account, so there is nothing for the processor to deduct.
+pub fn net_receive(gross: u64, fee_bps: u16) -> Result<u64, EscrowError> {
+    let fee = mul(gross, fee_bps as u64)? / BPS_DENOMINATOR;
+    sub(gross, fee)
+}
+
+/// Whether `amount` is at most `tolerance` away from `expected`, in either direction
+pub fn within_tolerance(amount: u64, expected: u64, tolerance: u64) -> bool {
+    amount.abs_diff(expected) <= tolerance
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add() {
+        assert_eq!(add(0, 0).unwrap(), 0);
+        assert_eq!(add(u64::MAX - 1, 1).unwrap(), u64::MAX);
+        assert!(matches!(add(u64::MAX, 1), Err(EscrowError::AmountOverflow)));
+    }
+
+    #[test]
+    fn test_sub() {
+        assert_eq!(sub(u64::MAX, u64::MAX).unwrap(), 0);
+        assert_eq!(sub(1, 0).unwrap(), 1);
+        assert!(matches!(sub(0, 1), Err(EscrowError::AmountOverflow)));
+    }
+
+    #[test]
+    fn test_net_receive() {
+        assert_eq!(net_receive(1_000_000, 0).unwrap(), 1_000_000);
+        assert_eq!(net_receive(1_000_000, 30).unwrap(), 997_000);
+        assert_eq!(net_receive(999, 30).unwrap(), 997);
+        assert_eq!(net_receive(1_000, 10_000).unwrap(), 0);
+        assert!(matches!(
+            net_receive(1_000, 10_001),
+            Err(EscrowError::AmountOverflow)
+        ));
+
+        let largest = u64::MAX / 30;
+        assert!(net_receive(largest, 30).is_ok());
+        assert!(matches!(
+            net_receive(largest + 1, 30),
+            Err(EscrowError::AmountOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_mul() {
+        assert_eq!(mul(u64::MAX, 1).unwrap(), u64::MAX);
+        assert_eq!(mul(u64::MAX, 0).unwrap(), 0);
+        assert_eq!(
+            mul(1 << 32, (1 << 32) - 1).unwrap(),
+            u64::MAX - ((1 << 32) - 1)
+        );
+        assert!(matches!(
+            mul(1 << 32, 1 << 32),
+            Err(EscrowError::AmountOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_within_tolerance() {
+        assert!(within_tolerance(100, 100, 0));
+        assert!(!within_tolerance(99, 100, 0));
+        assert!(!within_tolerance(101, 100, 0));
+
+        assert!(within_tolerance(98, 100, 2));
+        assert!(within_tolerance(102, 100, 2));
+        assert!(!within_tolerance(97, 100, 2));
+        assert!(!within_tolerance(103, 100, 2));
+
+        assert!(within_tolerance(0, 1, u64::MAX));
+        assert!(within_tolerance(u64::MAX, 0, u64::MAX));
+        assert!(!within_tolerance(u64::MAX, 0, u64::MAX - 1));
+    }
+}
@@ -1,17 +1,38 @@
-use crate::{error::EscrowError, instruction::EscrowInstruction, state::Escrow};
+use crate::{
+    accounts::{
+        CancelAccounts, ExchangeAccounts, InitEscrowAccounts, InitEscrowPdaAccounts,
+        MergeEscrowsAccounts, SetReceiveAccountAccounts, SplitEscrowAccounts,
+        TransferEscrowAccounts, UpdateEscrowAccounts,
+    },
+    error::EscrowError,
+    event::Initialized,
+    instruction::{
+        find_config_address, find_escrow_address, find_initializer_stats_address,
+        is_escrow_authority, EscrowInstruction, HEALTH_ESCROW_DATA, HEALTH_ESCROW_OWNER,
+        HEALTH_TEMP_ACCOUNT, HEALTH_TEMP_AUTHORITY, HEALTH_TEMP_BALANCE,
+    },
+    math,
+    state::{pack_in_progress, Config, Escrow, InitializerStats},
+    utils::{
+        admin_config, assert_not_escrow_authority, assert_not_paused, assert_rent_exempt,
+        assert_token_program, assert_writable, escrow_signer_seeds, initializer_stats, next_named,
+        token_amount, upgrade_authority,
+    },
+};
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
+    account_info::AccountInfo,
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
     rent::Rent,
+    system_instruction,
     sysvar::Sysvar,
 };
-use spl_token::state::Account as TokenAccount;
+use spl_token::state::{Account as TokenAccount, Mint};
 
 pub struct Processor;
 
@@ -21,49 +42,225 @@ impl Processor {
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
+        if instruction_data.is_empty() {
+            return Err(EscrowError::InvalidInstruction.into());
+        }
         let instruction = EscrowInstruction::unpack(instruction_data)?;
 
         match instruction {
-            EscrowInstruction::InitEscrow { amount } => {
+            EscrowInstruction::InitEscrow {
+                amount,
+                offered_mint,
+                allowed_taker,
+            } => {
                 msg!("Instruction: InitEscrow");
-                Self::process_init_escrow(accounts, amount, program_id)
+                Self::process_init_escrow(accounts, amount, offered_mint, allowed_taker, program_id)
             }
-            EscrowInstruction::Exchange { amount } => {
+            EscrowInstruction::Exchange { amount, nonce } => {
                 msg!("Instruction: Exchange");
-                Self::process_exchange(accounts, amount, program_id)
+                Self::process_exchange(accounts, amount, nonce, program_id)
             }
-            EscrowInstruction::Cancel {} => {
+            EscrowInstruction::Cancel { nonce } => {
                 msg!("Instruction: Cancel");
-                Self::process_cancel(accounts, program_id)
+                Self::process_cancel(accounts, nonce, program_id)
             }
             EscrowInstruction::ResetTimeLock {} => {
                 msg!("Instruction: ResetTimeLock");
                 Self::process_reset_time_lock(accounts, program_id)
             }
+            EscrowInstruction::InitEscrowPda {
+                amount,
+                nonce,
+                offered_mint,
+                allowed_taker,
+            } => {
+                msg!("Instruction: InitEscrowPda");
+                Self::process_init_escrow_pda(
+                    accounts,
+                    amount,
+                    nonce,
+                    offered_mint,
+                    allowed_taker,
+                    program_id,
+                )
+            }
+            #[cfg(feature = "debug")]
+            EscrowInstruction::DumpEscrow {} => {
+                msg!("Instruction: DumpEscrow");
+                Self::process_dump_escrow(accounts)
+            }
+            EscrowInstruction::SetAllowedTaker { taker } => {
+                msg!("Instruction: SetAllowedTaker");
+                Self::process_set_allowed_taker(accounts, taker, program_id)
+            }
+            EscrowInstruction::CancelAll {} => {
+                msg!("Instruction: CancelAll");
+                Self::process_cancel_all(accounts, program_id)
+            }
+            EscrowInstruction::InitConfig {} => {
+                msg!("Instruction: InitConfig");
+                Self::process_init_config(accounts, program_id)
+            }
+            EscrowInstruction::SetPaused { paused } => {
+                msg!("Instruction: SetPaused");
+                Self::process_set_paused(accounts, paused, program_id)
+            }
+            EscrowInstruction::CheckTaker { taker } => {
+                msg!("Instruction: CheckTaker");
+                Self::process_check_taker(accounts, &taker, program_id)
+            }
+            EscrowInstruction::SetReceiveAccount { new_account } => {
+                msg!("Instruction: SetReceiveAccount");
+                Self::process_set_receive_account(accounts, &new_account, program_id)
+            }
+            EscrowInstruction::HealthCheck {} => {
+                msg!("Instruction: HealthCheck");
+                Self::process_health_check(accounts, program_id)
+            }
+            EscrowInstruction::MergeEscrows {} => {
+                msg!("Instruction: MergeEscrows");
+                Self::process_merge_escrows(accounts, program_id)
+            }
+            EscrowInstruction::Freeze {} => {
+                msg!("Instruction: Freeze");
+                Self::process_set_frozen(accounts, true, program_id)
+            }
+            EscrowInstruction::Unfreeze {} => {
+                msg!("Instruction: Unfreeze");
+                Self::process_set_frozen(accounts, false, program_id)
+            }
+            EscrowInstruction::SplitEscrow { amount } => {
+                msg!("Instruction: SplitEscrow");
+                Self::process_split_escrow(accounts, amount, program_id)
+            }
+            EscrowInstruction::TransferEscrow { new_initializer } => {
+                msg!("Instruction: TransferEscrow");
+                Self::process_transfer_escrow(accounts, &new_initializer, program_id)
+            }
+            EscrowInstruction::InitInitializerStats {} => {
+                msg!("Instruction: InitInitializerStats");
+                Self::process_init_initializer_stats(accounts, program_id)
+            }
+            EscrowInstruction::SetAmountTolerance { amount_tolerance } => {
+                msg!("Instruction: SetAmountTolerance");
+                Self::process_set_amount_tolerance(accounts, amount_tolerance, program_id)
+            }
+            EscrowInstruction::GetProgramVersion {} => {
+                msg!("Instruction: GetProgramVersion");
+                set_return_data(&crate::PROGRAM_VERSION.to_le_bytes());
+                Ok(())
+            }
         }
     }
 
     fn process_init_escrow(
         accounts: &[AccountInfo],
         amount: u64,
+        offered_mint: Pubkey,
+        allowed_taker: Option<Pubkey>,
         program_id: &Pubkey,
     ) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let initializer = next_account_info(account_info_iter)?;
+        let InitEscrowAccounts {
+            initializer,
+            temp_token_account,
+            token_to_receive_account,
+            escrow_account,
+            token_program,
+            config,
+            initializer_stats: stats_account,
+            mint,
+            ..
+        } = InitEscrowAccounts::from_slice(accounts)?;
 
         if !initializer.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
-        let temp_token_account = next_account_info(account_info_iter)?;
-        let token_to_receive_account = next_account_info(account_info_iter)?;
+        assert_not_escrow_authority(program_id, escrow_account)?;
+        assert_not_paused(program_id, config)?;
+        Self::check_init(
+            initializer,
+            temp_token_account,
+            token_to_receive_account,
+            mint,
+            amount,
+            &offered_mint,
+        )?;
+        let mut stats = initializer_stats(program_id, initializer.key, stats_account)?;
+        stats.open_escrow()?;
+        InitializerStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+        assert_rent_exempt(escrow_account)?;
+        Self::write_escrow(
+            program_id,
+            initializer,
+            temp_token_account,
+            token_to_receive_account,
+            escrow_account,
+            token_program,
+            amount,
+            allowed_taker,
+        )
+    }
+
+    /// an escrow can never be filled if it asks for more than the mint's whole supply
+    pub fn check_amount_within_supply(amount: u64, mint: &Mint) -> ProgramResult {
+        if amount > mint.supply {
+            return Err(EscrowError::ExpectedAmountMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// The checks both init instructions make on the token accounts before anything is written
+    fn check_init(
+        initializer: &AccountInfo,
+        temp_token_account: &AccountInfo,
+        token_to_receive_account: &AccountInfo,
+        mint: Option<&AccountInfo>,
+        amount: u64,
+        offered_mint: &Pubkey,
+    ) -> ProgramResult {
+        // an escrow asking for nothing would hand its tokens to any taker for free
+        if amount == 0 {
+            return Err(EscrowError::ExpectedAmountMismatch.into());
+        }
         if *token_to_receive_account.owner != spl_token::id() {
             return Err(ProgramError::IncorrectProgramId);
         }
-        let escrow_account = next_account_info(account_info_iter)?;
-        let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
-        if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
-            return Err(EscrowError::NotRentExempt.into());
+        let token_to_receive_account_info =
+            TokenAccount::unpack(&token_to_receive_account.try_borrow_data()?)?;
+        if token_to_receive_account_info.owner != *initializer.key {
+            return Err(EscrowError::InvalidOwner.into());
         }
+        let temp_token_account_info = TokenAccount::unpack(&temp_token_account.try_borrow_data()?)?;
+        if temp_token_account_info.mint != *offered_mint {
+            return Err(EscrowError::InvalidTokenMint.into());
+        }
+
+        if let Some(mint_account) = mint {
+            if *mint_account.owner != spl_token::id() {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            if token_to_receive_account_info.mint != *mint_account.key {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            let mint_info = Mint::unpack(&mint_account.try_borrow_data()?)?;
+            Self::check_amount_within_supply(amount, &mint_info)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a new escrow into its allocated account and hands the temp token account to the
+    /// escrow PDA, the last step of both init instructions
+    #[allow(clippy::too_many_arguments)]
+    fn write_escrow(
+        program_id: &Pubkey,
+        initializer: &AccountInfo,
+        temp_token_account: &AccountInfo,
+        token_to_receive_account: &AccountInfo,
+        escrow_account: &AccountInfo,
+        token_program: &AccountInfo,
+        amount: u64,
+        allowed_taker: Option<Pubkey>,
+    ) -> ProgramResult {
         let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
         if escrow_info.is_initialized() {
             return Err(ProgramError::AccountAlreadyInitialized);
@@ -73,9 +270,9 @@ impl Processor {
         escrow_info.temp_token_account_pubkey = *temp_token_account.key;
         escrow_info.initializer_token_to_receive_account_pubkey = *token_to_receive_account.key;
         escrow_info.expected_amount = amount;
+        escrow_info.allowed_taker = allowed_taker;
         Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
         let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
-        let token_program = next_account_info(account_info_iter)?;
         let owner_change_ix = spl_token::instruction::set_authority(
             token_program.key,
             temp_token_account.key,
@@ -93,44 +290,208 @@ impl Processor {
                 token_program.clone(),
             ],
         )?;
+
+        Initialized {
+            escrow: *escrow_account.key,
+            initializer: *initializer.key,
+            expected_amount: amount,
+        }
+        .emit();
+        Ok(())
+    }
+
+    fn process_init_escrow_pda(
+        accounts: &[AccountInfo],
+        amount: u64,
+        nonce: u64,
+        offered_mint: Pubkey,
+        allowed_taker: Option<Pubkey>,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let InitEscrowPdaAccounts {
+            initializer,
+            temp_token_account,
+            token_to_receive_account,
+            escrow_account,
+            system_program,
+            token_program,
+            config,
+            initializer_stats: stats_account,
+            mint,
+        } = InitEscrowPdaAccounts::from_slice(accounts)?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        assert_not_paused(program_id, config)?;
+        Self::check_init(
+            initializer,
+            temp_token_account,
+            token_to_receive_account,
+            mint,
+            amount,
+            &offered_mint,
+        )?;
+
+        let (escrow_pda, escrow_bump_seed) =
+            find_escrow_address(program_id, initializer.key, nonce);
+        if escrow_pda != *escrow_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let mut stats = initializer_stats(program_id, initializer.key, stats_account)?;
+        stats.open_escrow()?;
+        InitializerStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+
+        let rent = Rent::get()?;
+        let create_escrow_ix = system_instruction::create_account(
+            initializer.key,
+            escrow_account.key,
+            rent.minimum_balance(Escrow::LEN),
+            Escrow::LEN as u64,
+            program_id,
+        );
+        msg!("Calling the system program to create the escrow account...");
+        invoke_signed(
+            &create_escrow_ix,
+            &[
+                initializer.clone(),
+                escrow_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                &b"escrow"[..],
+                initializer.key.as_ref(),
+                &nonce.to_le_bytes(),
+                &[escrow_bump_seed],
+            ]],
+        )?;
+
+        Self::write_escrow(
+            program_id,
+            initializer,
+            temp_token_account,
+            token_to_receive_account,
+            escrow_account,
+            token_program,
+            amount,
+            allowed_taker,
+        )
+    }
+
+    #[cfg(feature = "debug")]
+    fn process_dump_escrow(accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_named(account_info_iter, "escrow_account")?;
+
+        let escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
+
+        msg!("{}", crate::state::summarize(&escrow_info));
+        msg!("is_initialized: {}", escrow_info.is_initialized);
+        msg!("initializer_pubkey: {}", escrow_info.initializer_pubkey);
+        msg!(
+            "temp_token_account_pubkey: {}",
+            escrow_info.temp_token_account_pubkey
+        );
+        msg!(
+            "initializer_token_to_receive_account_pubkey: {}",
+            escrow_info.initializer_token_to_receive_account_pubkey
+        );
+        msg!("expected_amount: {}", escrow_info.expected_amount);
+        msg!("unlock_time: {}", escrow_info.unlock_time);
+        msg!("time_out: {}", escrow_info.time_out);
+        msg!("allowed_taker: {:?}", escrow_info.allowed_taker);
+        msg!("nonce: {}", escrow_info.nonce);
+        msg!("frozen: {}", escrow_info.frozen);
         Ok(())
     }
 
     fn process_exchange(
         accounts: &[AccountInfo],
         amount_expected_by_taker: u64,
+        nonce: u64,
         program_id: &Pubkey,
     ) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let taker = next_account_info(account_info_iter)?;
+        let ExchangeAccounts {
+            taker,
+            takers_sending_token_account,
+            takers_token_to_receive_account,
+            pdas_temp_token_account,
+            initializers_main_account,
+            initializers_token_to_receive_account,
+            escrow_account,
+            token_program,
+            pda_account,
+            config,
+            initializer_stats: stats_account,
+        } = ExchangeAccounts::from_slice(accounts)?;
 
         if !taker.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
+        assert_not_escrow_authority(program_id, escrow_account)?;
+        assert_writable(takers_sending_token_account, "takers_sending_token_account")?;
+        assert_writable(
+            takers_token_to_receive_account,
+            "takers_token_to_receive_account",
+        )?;
+        assert_writable(pdas_temp_token_account, "pdas_temp_token_account")?;
+        assert_writable(initializers_main_account, "initializers_main_account")?;
+        assert_writable(
+            initializers_token_to_receive_account,
+            "initializers_token_to_receive_account",
+        )?;
+        assert_writable(escrow_account, "escrow_account")?;
+        assert_not_paused(program_id, config)?;
 
-        let takers_sending_token_account = next_account_info(account_info_iter)?;
-
-        let takers_token_to_receive_account = next_account_info(account_info_iter)?;
+        // a taker losing the race to another one finds the escrow closed or its temp account empty
+        if escrow_account.data_is_empty()
+            || escrow_account.lamports() == 0
+            || pdas_temp_token_account.data_is_empty()
+        {
+            return Err(EscrowError::AlreadyFilled.into());
+        }
+        // a funded escrow account with zeroed data was never initialized
+        let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
+        if !escrow_info.is_initialized() {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        escrow_info.check_taker(taker.key)?;
+        escrow_info.check_nonce(nonce)?;
+        // init rejects a zero amount, a zero here means the escrow data is corrupt
+        if escrow_info.expected_amount == 0 {
+            return Err(EscrowError::ExpectedAmountMismatch.into());
+        }
+        let pdas_temp_token_amount = token_amount(pdas_temp_token_account)?;
+        if pdas_temp_token_amount == 0 {
+            return Err(EscrowError::AlreadyFilled.into());
+        }
 
-        let pdas_temp_token_account = next_account_info(account_info_iter)?;
-        let pdas_temp_token_account_info =
-            TokenAccount::unpack(&pdas_temp_token_account.try_borrow_data()?)?;
         let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        let bump = [bump_seed];
+        let signer_seeds = escrow_signer_seeds(&bump);
 
-        if amount_expected_by_taker != pdas_temp_token_account_info.amount {
+        let amount_tolerance = Config::unpack(&config.try_borrow_data()?)?.amount_tolerance;
+        if !math::within_tolerance(
+            amount_expected_by_taker,
+            pdas_temp_token_amount,
+            amount_tolerance,
+        ) {
             return Err(EscrowError::ExpectedAmountMismatch.into());
         }
 
-        let initializers_main_account = next_account_info(account_info_iter)?;
-        let initializers_token_to_receive_account = next_account_info(account_info_iter)?;
-        let escrow_account = next_account_info(account_info_iter)?;
-
-        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
-
         if escrow_info.temp_token_account_pubkey != *pdas_temp_token_account.key {
             return Err(ProgramError::InvalidAccountData);
         }
 
+        // the taker must be paid in the token their receiving account holds
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.try_borrow_data()?)?;
+        let takers_token_to_receive_account_info =
+            TokenAccount::unpack(&takers_token_to_receive_account.try_borrow_data()?)?;
+        if pdas_temp_token_account_info.mint != takers_token_to_receive_account_info.mint {
+            return Err(EscrowError::InvalidTokenMint.into());
+        }
+
         if escrow_info.initializer_pubkey != *initializers_main_account.key {
             return Err(ProgramError::InvalidAccountData);
         }
@@ -141,7 +502,13 @@ impl Processor {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        let token_program = next_account_info(account_info_iter)?;
+        let mut stats =
+            initializer_stats(program_id, &escrow_info.initializer_pubkey, stats_account)?;
+        stats.close_escrow();
+        InitializerStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+
+        escrow_info.begin_exchange()?;
+        pack_in_progress(&mut escrow_account.try_borrow_mut_data()?, true)?;
 
         let transfer_to_initializer_ix = spl_token::instruction::transfer(
             token_program.key,
@@ -161,14 +528,13 @@ impl Processor {
                 token_program.clone(),
             ],
         )?;
-        let pda_account = next_account_info(account_info_iter)?;
         let transfer_to_taker_ix = spl_token::instruction::transfer(
             token_program.key,
             pdas_temp_token_account.key,
             takers_token_to_receive_account.key,
             &pda,
             &[&pda],
-            pdas_temp_token_account_info.amount,
+            pdas_temp_token_amount,
         )?;
         msg!("Calling the token program to transfer tokens to the taker...");
         invoke_signed(
@@ -179,7 +545,7 @@ impl Processor {
                 pda_account.clone(),
                 token_program.clone(),
             ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
+            &[&signer_seeds[..]],
         )?;
 
         let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
@@ -198,15 +564,16 @@ impl Processor {
                 pda_account.clone(),
                 token_program.clone(),
             ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
+            &[&signer_seeds[..]],
         )?;
         msg!("Closing the escrow account...");
-        **initializers_main_account.try_borrow_mut_lamports()? = initializers_main_account
-            .lamports()
-            .checked_add(escrow_account.lamports())
-            .ok_or(EscrowError::AmountOverflow)?;
+        **initializers_main_account.try_borrow_mut_lamports()? = math::add(
+            initializers_main_account.lamports(),
+            escrow_account.lamports(),
+        )?;
         **escrow_account.try_borrow_mut_lamports()? = 0;
-        *escrow_account.try_borrow_mut_data()? = &mut [];
+        // zeroing the data also clears the in_progress flag
+        escrow_account.try_borrow_mut_data()?.fill(0);
         // **initializers_main_account.lamports.borrow_mut() = initializers_main_account
         //     .lamports()
         //     .checked_add(escrow_account.lamports())
@@ -216,25 +583,27 @@ impl Processor {
         Ok(())
     }
 
-    fn process_cancel(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let initializer = next_account_info(account_info_iter)?;
+    fn process_cancel(accounts: &[AccountInfo], nonce: u64, program_id: &Pubkey) -> ProgramResult {
+        let CancelAccounts {
+            initializer,
+            temp_token_account,
+            initializer_token_account,
+            escrow_account,
+            token_program,
+            initializer_stats: stats_account,
+        } = CancelAccounts::from_slice(accounts)?;
 
         if !initializer.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
-
-        let temp_token_account = next_account_info(account_info_iter)?;
-        let initializer_token_account = next_account_info(account_info_iter)?;
-        let escrow_account = next_account_info(account_info_iter)?;
+        assert_not_escrow_authority(program_id, escrow_account)?;
 
         if escrow_account.owner != program_id {
             return Err(ProgramError::IllegalOwner);
         }
 
-        if !escrow_account.is_writable {
-            return Err(ProgramError::InvalidAccountData);
-        }
+        assert_writable(escrow_account, "escrow_account")?;
+        assert_writable(temp_token_account, "temp_token_account")?;
 
         let escrow_info = Escrow::unpack(&escrow_account.data.borrow_mut())?;
 
@@ -246,9 +615,16 @@ impl Processor {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        let token_program = next_account_info(account_info_iter)?;
-        let pda_token_account_info = TokenAccount::unpack(&temp_token_account.try_borrow_data()?)?;
+        escrow_info.check_nonce(nonce)?;
+
+        let mut stats = initializer_stats(program_id, initializer.key, stats_account)?;
+        stats.close_escrow();
+        InitializerStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+
+        let pda_token_amount = token_amount(temp_token_account)?;
         let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        let bump = [bump_seed];
+        let signer_seeds = escrow_signer_seeds(&bump);
 
         let transfer_to_initializer_ix = spl_token::instruction::transfer(
             token_program.key,
@@ -256,7 +632,7 @@ impl Processor {
             initializer_token_account.key,
             &pda,
             &[&pda],
-            pda_token_account_info.amount,
+            pda_token_amount,
         )?;
 
         msg!("Invoking token program to transfer back rent");
@@ -268,7 +644,7 @@ impl Processor {
                 temp_token_account.clone(),
                 token_program.clone(),
             ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
+            &[&signer_seeds[..]],
         )?;
 
         let close_escrow_token_account = spl_token::instruction::close_account(
@@ -288,14 +664,12 @@ impl Processor {
                 temp_token_account.clone(),
                 token_program.clone(),
             ],
-            &[&[&b"escrow"[..], &[bump_seed]]],
+            &[&signer_seeds[..]],
         )?;
 
         msg!("Closing  the escrow account...");
-        **initializer.try_borrow_mut_lamports()? = initializer
-            .lamports()
-            .checked_add(escrow_account.lamports())
-            .ok_or(EscrowError::AmountOverflow)?;
+        **initializer.try_borrow_mut_lamports()? =
+            math::add(initializer.lamports(), escrow_account.lamports())?;
         **escrow_account.try_borrow_mut_lamports()? = 0;
         *escrow_account.try_borrow_mut_data()? = &mut [];
         // **initializer.lamports.borrow_mut() = initializer
@@ -307,18 +681,177 @@ impl Processor {
         Ok(())
     }
 
-    fn process_reset_time_lock(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    fn process_cancel_all(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let initializer = next_account_info(account_info_iter)?;
-        let slot = Clock::get()?.slot;
-        let unlock_time = slot.checked_add(100).unwrap();
-        let time_out = unlock_time.checked_add(1000).unwrap();
+        let initializer = next_named(account_info_iter, "initializer")?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let token_program = next_named(account_info_iter, "token_program")?;
+        assert_token_program(token_program)?;
+        let pda_account = next_named(account_info_iter, "pda_account")?;
+        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        if pda != *pda_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let bump = [bump_seed];
+        let signer_seeds = escrow_signer_seeds(&bump);
+        let stats_account = next_named(account_info_iter, "initializer_stats")?;
+        let mut stats = initializer_stats(program_id, initializer.key, stats_account)?;
+
+        let escrows = account_info_iter.as_slice();
+        if escrows.is_empty() || escrows.len() % 3 != 0 {
+            return Err(EscrowError::InvalidInstruction.into());
+        }
+
+        for escrow in escrows.chunks(3) {
+            let (escrow_account, temp_token_account, initializer_token_account) =
+                (&escrow[0], &escrow[1], &escrow[2]);
+
+            if escrow_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+
+            let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+            if escrow_info.initializer_pubkey != *initializer.key
+                || escrow_info.temp_token_account_pubkey != *temp_token_account.key
+            {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            let temp_token_amount = token_amount(temp_token_account)?;
+
+            let transfer_to_initializer_ix = spl_token::instruction::transfer(
+                token_program.key,
+                temp_token_account.key,
+                initializer_token_account.key,
+                &pda,
+                &[&pda],
+                temp_token_amount,
+            )?;
+            msg!("Invoking token program to transfer tokens back to the initializer");
+            invoke_signed(
+                &transfer_to_initializer_ix,
+                &[
+                    temp_token_account.clone(),
+                    initializer_token_account.clone(),
+                    pda_account.clone(),
+                    token_program.clone(),
+                ],
+                &[&signer_seeds[..]],
+            )?;
+
+            let close_temp_token_account_ix = spl_token::instruction::close_account(
+                token_program.key,
+                temp_token_account.key,
+                initializer.key,
+                &pda,
+                &[&pda],
+            )?;
+            msg!("Invoking token program to close escrow token account");
+            invoke_signed(
+                &close_temp_token_account_ix,
+                &[
+                    temp_token_account.clone(),
+                    initializer.clone(),
+                    pda_account.clone(),
+                    token_program.clone(),
+                ],
+                &[&signer_seeds[..]],
+            )?;
+
+            msg!("Closing the escrow account...");
+            **initializer.try_borrow_mut_lamports()? =
+                math::add(initializer.lamports(), escrow_account.lamports())?;
+            **escrow_account.try_borrow_mut_lamports()? = 0;
+            *escrow_account.try_borrow_mut_data()? = &mut [];
+            stats.close_escrow();
+        }
+        InitializerStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+
+    fn process_set_allowed_taker(
+        accounts: &[AccountInfo],
+        taker: Option<Pubkey>,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let UpdateEscrowAccounts {
+            initializer,
+            escrow_account,
+        } = UpdateEscrowAccounts::from_slice(accounts)?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if escrow_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+
+        if !escrow_account.is_writable {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        escrow_info.allowed_taker = taker;
+        escrow_info.bump_nonce();
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+
+    fn process_set_frozen(
+        accounts: &[AccountInfo],
+        frozen: bool,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let UpdateEscrowAccounts {
+            initializer,
+            escrow_account,
+        } = UpdateEscrowAccounts::from_slice(accounts)?;
 
         if !initializer.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
 
-        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+
+        assert_writable(escrow_account, "escrow_account")?;
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        escrow_info.frozen = frozen;
+        escrow_info.bump_nonce();
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+
+    fn process_reset_time_lock(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let UpdateEscrowAccounts {
+            initializer,
+            escrow_account,
+        } = UpdateEscrowAccounts::from_slice(accounts)?;
+        let slot = Clock::get()?.slot;
+        let unlock_time = math::add(slot, 100)?;
+        let time_out = math::add(unlock_time, 1000)?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
 
         if escrow_account.owner != program_id {
             return Err(ProgramError::IllegalOwner);
@@ -328,7 +861,7 @@ impl Processor {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        let escrow_info = &mut Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
 
         if escrow_info.initializer_pubkey != *initializer.key {
             return Err(ProgramError::InvalidAccountData);
@@ -336,6 +869,3591 @@ impl Processor {
 
         escrow_info.unlock_time = unlock_time;
         escrow_info.time_out = time_out;
+        escrow_info.bump_nonce();
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+
+    fn process_check_taker(
+        accounts: &[AccountInfo],
+        taker: &Pubkey,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_named(account_info_iter, "escrow_account")?;
+
+        if escrow_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        let can_take = escrow_info.check_taker(taker).is_ok();
+        set_return_data(&[can_take as u8]);
+        Ok(())
+    }
+
+    fn process_health_check(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let escrow_account = next_named(account_info_iter, "escrow_account")?;
+        let temp_token_account = next_named(account_info_iter, "temp_token_account")?;
+
+        let mask = Self::health_mask(escrow_account, temp_token_account, program_id)?;
+        set_return_data(&[mask]);
+        Ok(())
+    }
+
+    /// the `HEALTH_*` bits of every invariant the escrow and its temp account break, read only
+    pub fn health_mask(
+        escrow_account: &AccountInfo,
+        temp_token_account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<u8, ProgramError> {
+        let mut mask = 0;
+        if escrow_account.owner != program_id {
+            mask |= HEALTH_ESCROW_OWNER;
+        }
+        match Escrow::unpack(&escrow_account.try_borrow_data()?) {
+            Ok(escrow_info) => {
+                if escrow_info.temp_token_account_pubkey != *temp_token_account.key {
+                    mask |= HEALTH_TEMP_ACCOUNT;
+                }
+            }
+            Err(_) => mask |= HEALTH_ESCROW_DATA,
+        }
+        if *temp_token_account.owner != spl_token::id() {
+            mask |= HEALTH_TEMP_ACCOUNT;
+        }
+        match TokenAccount::unpack(&temp_token_account.try_borrow_data()?) {
+            Ok(temp_info) => {
+                if !is_escrow_authority(program_id, &temp_info.owner) {
+                    mask |= HEALTH_TEMP_AUTHORITY;
+                }
+                if temp_info.amount == 0 {
+                    mask |= HEALTH_TEMP_BALANCE;
+                }
+            }
+            Err(_) => mask |= HEALTH_TEMP_ACCOUNT,
+        }
+        Ok(mask)
+    }
+
+    fn process_merge_escrows(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let MergeEscrowsAccounts {
+            initializer,
+            escrow_a,
+            temp_token_account_a,
+            escrow_b,
+            temp_token_account_b,
+            token_program,
+            pda_account,
+            initializer_stats: stats_account,
+        } = MergeEscrowsAccounts::from_slice(accounts)?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        assert_token_program(token_program)?;
+        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        if pda != *pda_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if escrow_a.key == escrow_b.key {
+            return Err(ProgramError::InvalidArgument);
+        }
+        for escrow_account in [escrow_a, escrow_b] {
+            if escrow_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+            assert_writable(escrow_account, "escrow_account")?;
+        }
+        assert_writable(temp_token_account_a, "temp_token_account_a")?;
+        assert_writable(temp_token_account_b, "temp_token_account_b")?;
+
+        let mut escrow_a_info = Escrow::unpack(&escrow_a.try_borrow_data()?)?;
+        let escrow_b_info = Escrow::unpack(&escrow_b.try_borrow_data()?)?;
+        if escrow_a_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if escrow_a_info.temp_token_account_pubkey != *temp_token_account_a.key
+            || escrow_b_info.temp_token_account_pubkey != *temp_token_account_b.key
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let temp_a_info = TokenAccount::unpack(&temp_token_account_a.try_borrow_data()?)?;
+        let temp_b_info = TokenAccount::unpack(&temp_token_account_b.try_borrow_data()?)?;
+        if temp_a_info.mint != temp_b_info.mint {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        escrow_a_info.absorb(&escrow_b_info)?;
+        let mut stats = initializer_stats(program_id, initializer.key, stats_account)?;
+        stats.close_escrow();
+        InitializerStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+        Escrow::pack(escrow_a_info, &mut escrow_a.try_borrow_mut_data()?)?;
+
+        let bump = [bump_seed];
+        let signer_seeds = escrow_signer_seeds(&bump);
+
+        let transfer_to_escrow_a_ix = spl_token::instruction::transfer(
+            token_program.key,
+            temp_token_account_b.key,
+            temp_token_account_a.key,
+            &pda,
+            &[&pda],
+            temp_b_info.amount,
+        )?;
+        msg!("Calling the token program to move escrow B's tokens to escrow A...");
+        invoke_signed(
+            &transfer_to_escrow_a_ix,
+            &[
+                temp_token_account_b.clone(),
+                temp_token_account_a.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&signer_seeds[..]],
+        )?;
+
+        let close_temp_b_ix = spl_token::instruction::close_account(
+            token_program.key,
+            temp_token_account_b.key,
+            initializer.key,
+            &pda,
+            &[&pda],
+        )?;
+        msg!("Calling the token program to close escrow B's temp account...");
+        invoke_signed(
+            &close_temp_b_ix,
+            &[
+                temp_token_account_b.clone(),
+                initializer.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&signer_seeds[..]],
+        )?;
+
+        msg!("Closing escrow B...");
+        **initializer.try_borrow_mut_lamports()? =
+            math::add(initializer.lamports(), escrow_b.lamports())?;
+        **escrow_b.try_borrow_mut_lamports()? = 0;
+        escrow_b.try_borrow_mut_data()?.fill(0);
         Ok(())
     }
+
+    fn process_split_escrow(
+        accounts: &[AccountInfo],
+        amount: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let SplitEscrowAccounts {
+            initializer,
+            escrow_account,
+            temp_token_account,
+            new_escrow_account,
+            new_temp_token_account,
+            token_program,
+            pda_account,
+            initializer_stats: stats_account,
+        } = SplitEscrowAccounts::from_slice(accounts)?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        assert_token_program(token_program)?;
+        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        if pda != *pda_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if escrow_account.key == new_escrow_account.key {
+            return Err(ProgramError::InvalidArgument);
+        }
+        for escrow_account in [escrow_account, new_escrow_account] {
+            if escrow_account.owner != program_id {
+                return Err(ProgramError::IllegalOwner);
+            }
+            assert_writable(escrow_account, "escrow_account")?;
+        }
+        assert_writable(temp_token_account, "temp_token_account")?;
+        assert_writable(new_temp_token_account, "new_temp_token_account")?;
+        assert_rent_exempt(new_escrow_account)?;
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if escrow_info.temp_token_account_pubkey != *temp_token_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if Escrow::unpack_unchecked(&new_escrow_account.try_borrow_data()?)?.is_initialized() {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let temp_info = TokenAccount::unpack(&temp_token_account.try_borrow_data()?)?;
+        let new_temp_info = TokenAccount::unpack(&new_temp_token_account.try_borrow_data()?)?;
+        if new_temp_info.mint != temp_info.mint || new_temp_info.owner != pda {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let new_escrow_info =
+            escrow_info.split_off(amount, temp_info.amount, *new_temp_token_account.key)?;
+        let mut stats = initializer_stats(program_id, initializer.key, stats_account)?;
+        stats.open_escrow()?;
+        InitializerStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+        Escrow::pack(
+            new_escrow_info,
+            &mut new_escrow_account.try_borrow_mut_data()?,
+        )?;
+
+        let bump = [bump_seed];
+        let signer_seeds = escrow_signer_seeds(&bump);
+        let transfer_to_new_escrow_ix = spl_token::instruction::transfer(
+            token_program.key,
+            temp_token_account.key,
+            new_temp_token_account.key,
+            &pda,
+            &[&pda],
+            amount,
+        )?;
+        msg!("Calling the token program to move the split tokens to the new escrow...");
+        invoke_signed(
+            &transfer_to_new_escrow_ix,
+            &[
+                temp_token_account.clone(),
+                new_temp_token_account.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&signer_seeds[..]],
+        )?;
+        Ok(())
+    }
+
+    fn process_set_receive_account(
+        accounts: &[AccountInfo],
+        new_account: &Pubkey,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let SetReceiveAccountAccounts {
+            initializer,
+            escrow_account,
+            current_receive_account,
+            new_receive_account,
+        } = SetReceiveAccountAccounts::from_slice(accounts)?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if escrow_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+
+        if !escrow_account.is_writable {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if new_receive_account.key != new_account {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if escrow_info.initializer_token_to_receive_account_pubkey != *current_receive_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if *current_receive_account.owner != spl_token::id()
+            || *new_receive_account.owner != spl_token::id()
+        {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let current_receive_account_info =
+            TokenAccount::unpack(&current_receive_account.try_borrow_data()?)?;
+        let new_receive_account_info =
+            TokenAccount::unpack(&new_receive_account.try_borrow_data()?)?;
+
+        if new_receive_account_info.owner != *initializer.key {
+            return Err(EscrowError::InvalidOwner.into());
+        }
+
+        if new_receive_account_info.mint != current_receive_account_info.mint {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        escrow_info.initializer_token_to_receive_account_pubkey = *new_account;
+        escrow_info.bump_nonce();
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+
+    fn process_transfer_escrow(
+        accounts: &[AccountInfo],
+        new_initializer: &Pubkey,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let TransferEscrowAccounts {
+            initializer,
+            escrow_account,
+            current_receive_account,
+            new_receive_account,
+            initializer_stats: stats_account,
+            new_initializer_stats: new_stats_account,
+        } = TransferEscrowAccounts::from_slice(accounts)?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if escrow_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+
+        assert_writable(escrow_account, "escrow_account")?;
+
+        let mut escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        if escrow_info.initializer_pubkey != *initializer.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if escrow_info.initializer_token_to_receive_account_pubkey != *current_receive_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if *current_receive_account.owner != spl_token::id()
+            || *new_receive_account.owner != spl_token::id()
+        {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let current_receive_account_info =
+            TokenAccount::unpack(&current_receive_account.try_borrow_data()?)?;
+        let new_receive_account_info =
+            TokenAccount::unpack(&new_receive_account.try_borrow_data()?)?;
+
+        if new_receive_account_info.owner != *new_initializer {
+            return Err(EscrowError::InvalidOwner.into());
+        }
+
+        if new_receive_account_info.mint != current_receive_account_info.mint {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut stats = initializer_stats(program_id, initializer.key, stats_account)?;
+        let mut new_stats = initializer_stats(program_id, new_initializer, new_stats_account)?;
+        // handing the escrow to yourself leaves both counts as they are
+        if new_initializer != initializer.key {
+            new_stats.open_escrow()?;
+            stats.close_escrow();
+            InitializerStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+            InitializerStats::pack(new_stats, &mut new_stats_account.try_borrow_mut_data()?)?;
+        }
+
+        escrow_info.initializer_pubkey = *new_initializer;
+        escrow_info.initializer_token_to_receive_account_pubkey = *new_receive_account.key;
+        escrow_info.bump_nonce();
+        Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+
+    fn process_init_config(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let admin = next_named(account_info_iter, "admin")?;
+        let config_account = next_named(account_info_iter, "config_account")?;
+        let system_program = next_named(account_info_iter, "system_program")?;
+        let program_data = next_named(account_info_iter, "program_data")?;
+
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if upgrade_authority(program_id, program_data)? != Some(*admin.key) {
+            return Err(EscrowError::Unauthorized.into());
+        }
+
+        let (config_pda, config_bump_seed) = find_config_address(program_id);
+        if config_pda != *config_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        let rent = Rent::get()?;
+        let create_config_ix = system_instruction::create_account(
+            admin.key,
+            config_account.key,
+            rent.minimum_balance(Config::LEN),
+            Config::LEN as u64,
+            program_id,
+        );
+        msg!("Calling the system program to create the config account...");
+        invoke_signed(
+            &create_config_ix,
+            &[
+                admin.clone(),
+                config_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[&b"config"[..], &[config_bump_seed]]],
+        )?;
+
+        let config = Config {
+            is_initialized: true,
+            admin: *admin.key,
+            paused: false,
+            amount_tolerance: 0,
+        };
+        Config::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+
+    fn process_init_initializer_stats(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let initializer = next_named(account_info_iter, "initializer")?;
+        let stats_account = next_named(account_info_iter, "stats_account")?;
+        let system_program = next_named(account_info_iter, "system_program")?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let (stats_pda, stats_bump_seed) =
+            find_initializer_stats_address(program_id, initializer.key);
+        if stats_pda != *stats_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        let rent = Rent::get()?;
+        let create_stats_ix = system_instruction::create_account(
+            initializer.key,
+            stats_account.key,
+            rent.minimum_balance(InitializerStats::LEN),
+            InitializerStats::LEN as u64,
+            program_id,
+        );
+        msg!("Calling the system program to create the stats account...");
+        invoke_signed(
+            &create_stats_ix,
+            &[
+                initializer.clone(),
+                stats_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                &b"initializer_stats"[..],
+                initializer.key.as_ref(),
+                &[stats_bump_seed],
+            ]],
+        )?;
+
+        let stats = InitializerStats {
+            is_initialized: true,
+            escrows_created: 0,
+        };
+        InitializerStats::pack(stats, &mut stats_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+
+    fn process_set_paused(
+        accounts: &[AccountInfo],
+        paused: bool,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let admin = next_named(account_info_iter, "admin")?;
+        let config_account = next_named(account_info_iter, "config_account")?;
+
+        let mut config = admin_config(program_id, admin, config_account)?;
+        config.paused = paused;
+        Config::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+
+    fn process_set_amount_tolerance(
+        accounts: &[AccountInfo],
+        amount_tolerance: u64,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let admin = next_named(account_info_iter, "admin")?;
+        let config_account = next_named(account_info_iter, "config_account")?;
+
+        let mut config = admin_config(program_id, admin, config_account)?;
+        config.amount_tolerance = amount_tolerance;
+        Config::pack(config, &mut config_account.try_borrow_mut_data()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::MAX_OPEN_ESCROWS;
+
+    #[test]
+    fn test_empty_instruction_data_is_rejected() {
+        assert_eq!(
+            Processor::process(&Pubkey::new_unique(), &[], &[]),
+            Err(EscrowError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_init_escrow_rejects_receive_account_of_another_wallet() {
+        let program_id = Pubkey::new_unique();
+        let initializer = Pubkey::new_unique();
+        let temp = Pubkey::new_unique();
+        let receive = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin: Pubkey::new_unique(),
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+
+        let mut receive_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                owner: Pubkey::new_unique(),
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut receive_data,
+        )
+        .unwrap();
+
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &token_program,
+            false,
+            0,
+        );
+        let receive_info = AccountInfo::new(
+            &receive,
+            false,
+            false,
+            &mut lamports_c,
+            &mut receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports_d,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts = vec![
+            initializer_info,
+            temp_info.clone(),
+            receive_info,
+            temp_info.clone(),
+            temp_info.clone(),
+            temp_info.clone(),
+            config_info,
+            temp_info,
+        ];
+
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &accounts,
+                &EscrowInstruction::InitEscrow {
+                    amount: 1,
+                    offered_mint: Pubkey::new_unique(),
+                    allowed_taker: None,
+                }
+                .pack()
+            ),
+            Err(EscrowError::InvalidOwner.into())
+        );
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &accounts,
+                &EscrowInstruction::InitEscrow {
+                    amount: 0,
+                    offered_mint: Pubkey::new_unique(),
+                    allowed_taker: None,
+                }
+                .pack()
+            ),
+            Err(EscrowError::ExpectedAmountMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_init_escrow_rejects_mismatched_offered_mint() {
+        let program_id = Pubkey::new_unique();
+        let initializer = Pubkey::new_unique();
+        let temp = Pubkey::new_unique();
+        let receive = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin: Pubkey::new_unique(),
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+
+        let mut receive_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                owner: initializer,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut receive_data,
+        )
+        .unwrap();
+
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                owner: initializer,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut temp_data,
+        )
+        .unwrap();
+
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_b,
+            &mut temp_data,
+            &token_program,
+            false,
+            0,
+        );
+        let receive_info = AccountInfo::new(
+            &receive,
+            false,
+            false,
+            &mut lamports_c,
+            &mut receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports_d,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts = vec![
+            initializer_info,
+            temp_info.clone(),
+            receive_info,
+            temp_info.clone(),
+            temp_info.clone(),
+            temp_info.clone(),
+            config_info,
+            temp_info,
+        ];
+
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &accounts,
+                &EscrowInstruction::InitEscrow {
+                    amount: 1,
+                    offered_mint: Pubkey::new_unique(),
+                    allowed_taker: None,
+                }
+                .pack()
+            ),
+            Err(EscrowError::InvalidTokenMint.into())
+        );
+    }
+
+    #[test]
+    fn test_init_escrow_pda_checks_the_token_accounts() {
+        let program_id = Pubkey::new_unique();
+        let initializer = Pubkey::new_unique();
+        let temp = Pubkey::new_unique();
+        let receive = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin: Pubkey::new_unique(),
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let receive_account = |owner| TokenAccount {
+            mint: Pubkey::new_unique(),
+            owner,
+            state: spl_token::state::AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        let mut receive_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(receive_account(Pubkey::new_unique()), &mut receive_data).unwrap();
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(receive_account(initializer), &mut temp_data).unwrap();
+
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_b,
+            &mut temp_data,
+            &token_program,
+            false,
+            0,
+        );
+        let receive_info = AccountInfo::new(
+            &receive,
+            false,
+            false,
+            &mut lamports_c,
+            &mut receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports_d,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts = vec![
+            initializer_info,
+            temp_info.clone(),
+            receive_info,
+            temp_info.clone(),
+            temp_info.clone(),
+            temp_info.clone(),
+            config_info,
+            temp_info,
+        ];
+        let init_data = EscrowInstruction::InitEscrowPda {
+            amount: 1,
+            nonce: 0,
+            offered_mint: Pubkey::new_unique(),
+            allowed_taker: None,
+        }
+        .pack();
+
+        // a zero amount is refused before the accounts are looked at
+        let zero_amount_data = EscrowInstruction::InitEscrowPda {
+            amount: 0,
+            nonce: 0,
+            offered_mint: Pubkey::new_unique(),
+            allowed_taker: None,
+        }
+        .pack();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &zero_amount_data),
+            Err(EscrowError::ExpectedAmountMismatch.into())
+        );
+
+        // the receive account belongs to another wallet
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &init_data),
+            Err(EscrowError::InvalidOwner.into())
+        );
+
+        // the temp account holds another mint than the one offered
+        TokenAccount::pack(
+            receive_account(initializer),
+            &mut accounts[2].try_borrow_mut_data().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &init_data),
+            Err(EscrowError::InvalidTokenMint.into())
+        );
+    }
+
+    #[test]
+    fn test_open_escrow_limit_recovers_after_cancel() {
+        crate::utils::log_recorder::install();
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (initializer, temp, receive, new_escrow_key, open_escrow_key, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (stats_key, _) = find_initializer_stats_address(&program_id, &initializer);
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+        let (mut lamports_e, mut lamports_f, mut lamports_g) =
+            (Rent::default().minimum_balance(Escrow::LEN), 1, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin: Pubkey::new_unique(),
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let mut stats_data = vec![0; InitializerStats::LEN];
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: MAX_OPEN_ESCROWS,
+            },
+            &mut stats_data,
+        )
+        .unwrap();
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner: initializer,
+                amount: 10,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut temp_data,
+        )
+        .unwrap();
+        let mut receive_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                owner: initializer,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut receive_data,
+        )
+        .unwrap();
+        let mut new_escrow_data = vec![0; Escrow::LEN];
+        let mut open_escrow_data = crate::test_utils::packed_sample(initializer, 10);
+
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_b,
+            &mut temp_data,
+            &token_program,
+            false,
+            0,
+        );
+        let receive_info = AccountInfo::new(
+            &receive,
+            false,
+            true,
+            &mut lamports_c,
+            &mut receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports_d,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let new_escrow_info = AccountInfo::new(
+            &new_escrow_key,
+            false,
+            true,
+            &mut lamports_e,
+            &mut new_escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let open_escrow_info = AccountInfo::new(
+            &open_escrow_key,
+            false,
+            true,
+            &mut lamports_f,
+            &mut open_escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let stats_info = AccountInfo::new(
+            &stats_key,
+            false,
+            true,
+            &mut lamports_g,
+            &mut stats_data,
+            &program_id,
+            false,
+            0,
+        );
+        let init_accounts = vec![
+            initializer_info.clone(),
+            temp_info.clone(),
+            receive_info.clone(),
+            new_escrow_info,
+            temp_info.clone(),
+            temp_info.clone(),
+            config_info,
+            stats_info.clone(),
+        ];
+        let cancel_accounts = vec![
+            initializer_info,
+            temp_info.clone(),
+            receive_info,
+            open_escrow_info,
+            temp_info,
+            stats_info,
+        ];
+        let init_data = EscrowInstruction::InitEscrow {
+            amount: 10,
+            offered_mint: mint,
+            allowed_taker: None,
+        }
+        .pack();
+
+        assert_eq!(
+            Processor::process(&program_id, &init_accounts, &init_data),
+            Err(EscrowError::TooManyEscrows.into())
+        );
+
+        // the token program CPIs are no-ops off-chain, cancelling only closes the escrow
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &cancel_accounts,
+                &EscrowInstruction::Cancel { nonce: 0 }.pack()
+            ),
+            Ok(())
+        );
+        let stats =
+            InitializerStats::unpack(&cancel_accounts[5].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(stats.escrows_created, MAX_OPEN_ESCROWS - 1);
+
+        assert_eq!(
+            Processor::process(&program_id, &init_accounts, &init_data),
+            Ok(())
+        );
+        let stats = InitializerStats::unpack(&init_accounts[7].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(stats.escrows_created, MAX_OPEN_ESCROWS);
+    }
+
+    #[test]
+    fn test_init_config_needs_the_upgrade_authority() {
+        crate::utils::log_recorder::install();
+        let program_id = Pubkey::new_unique();
+        let loader = solana_program::bpf_loader_upgradeable::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let program_data_key = crate::instruction::find_program_data_address(&program_id);
+        let (authority, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        let mut program_data = crate::test_utils::program_data(Some(authority));
+        let authority_info = AccountInfo::new(
+            &authority,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            true,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            true,
+            &mut lamports_c,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let program_data_info = AccountInfo::new(
+            &program_data_key,
+            false,
+            false,
+            &mut lamports_d,
+            &mut program_data,
+            &loader,
+            false,
+            0,
+        );
+        let init_config_data = EscrowInstruction::InitConfig {}.pack();
+
+        // whoever sends InitConfig first must not become the admin
+        let accounts = vec![
+            other_info.clone(),
+            config_info.clone(),
+            other_info,
+            program_data_info.clone(),
+        ];
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &init_config_data),
+            Err(EscrowError::Unauthorized.into())
+        );
+        assert!(
+            !Config::unpack_unchecked(&config_info.try_borrow_data().unwrap())
+                .unwrap()
+                .is_initialized
+        );
+
+        // the system program CPI is a no-op off-chain, the config account is already allocated
+        let accounts = vec![
+            authority_info.clone(),
+            config_info.clone(),
+            authority_info,
+            program_data_info,
+        ];
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &init_config_data),
+            Ok(())
+        );
+        let config = Config::unpack(&config_info.try_borrow_data().unwrap()).unwrap();
+        assert_eq!(config.admin, authority);
+    }
+
+    #[test]
+    fn test_pause_blocks_init_and_exchange_but_not_cancel() {
+        crate::utils::log_recorder::install();
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (initializer, admin, temp, receive, new_escrow_key, open_escrow_key, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (stats_key, _) = find_initializer_stats_address(&program_id, &initializer);
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+        let (mut lamports_e, mut lamports_f, mut lamports_g, mut lamports_h) =
+            (Rent::default().minimum_balance(Escrow::LEN), 1, 0, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin,
+                paused: true,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let mut stats_data = vec![0; InitializerStats::LEN];
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: 1,
+            },
+            &mut stats_data,
+        )
+        .unwrap();
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner: initializer,
+                amount: 10,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut temp_data,
+        )
+        .unwrap();
+        let mut receive_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                owner: initializer,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut receive_data,
+        )
+        .unwrap();
+        let mut new_escrow_data = vec![0; Escrow::LEN];
+        let mut open_escrow_data = crate::test_utils::packed_sample(initializer, 10);
+
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_b,
+            &mut temp_data,
+            &token_program,
+            false,
+            0,
+        );
+        let receive_info = AccountInfo::new(
+            &receive,
+            false,
+            true,
+            &mut lamports_c,
+            &mut receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            true,
+            &mut lamports_d,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let new_escrow_info = AccountInfo::new(
+            &new_escrow_key,
+            false,
+            true,
+            &mut lamports_e,
+            &mut new_escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let open_escrow_info = AccountInfo::new(
+            &open_escrow_key,
+            false,
+            true,
+            &mut lamports_f,
+            &mut open_escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let stats_info = AccountInfo::new(
+            &stats_key,
+            false,
+            true,
+            &mut lamports_g,
+            &mut stats_data,
+            &program_id,
+            false,
+            0,
+        );
+        let admin_info = AccountInfo::new(
+            &admin,
+            true,
+            false,
+            &mut lamports_h,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let init_accounts = vec![
+            initializer_info.clone(),
+            temp_info.clone(),
+            receive_info.clone(),
+            new_escrow_info,
+            temp_info.clone(),
+            temp_info.clone(),
+            config_info.clone(),
+            stats_info.clone(),
+        ];
+        // Exchange checks the pause before it reads any escrow or token data
+        let exchange_accounts = vec![
+            initializer_info.clone(),
+            temp_info.clone(),
+            receive_info.clone(),
+            temp_info.clone(),
+            initializer_info.clone(),
+            receive_info.clone(),
+            open_escrow_info.clone(),
+            temp_info.clone(),
+            temp_info.clone(),
+            config_info.clone(),
+            stats_info.clone(),
+        ];
+        let cancel_accounts = vec![
+            initializer_info,
+            temp_info.clone(),
+            receive_info,
+            open_escrow_info,
+            temp_info,
+            stats_info,
+        ];
+        let init_data = EscrowInstruction::InitEscrow {
+            amount: 10,
+            offered_mint: mint,
+            allowed_taker: None,
+        }
+        .pack();
+
+        assert_eq!(
+            Processor::process(&program_id, &init_accounts, &init_data),
+            Err(EscrowError::Paused.into())
+        );
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &exchange_accounts,
+                &EscrowInstruction::Exchange {
+                    amount: 10,
+                    nonce: 0
+                }
+                .pack()
+            ),
+            Err(EscrowError::Paused.into())
+        );
+
+        // initializers can still get their tokens back while the program is paused
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &cancel_accounts,
+                &EscrowInstruction::Cancel { nonce: 0 }.pack()
+            ),
+            Ok(())
+        );
+        assert_eq!(cancel_accounts[3].lamports(), 0);
+
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[admin_info, init_accounts[6].clone()],
+                &EscrowInstruction::SetPaused { paused: false }.pack()
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            Processor::process(&program_id, &init_accounts, &init_data),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_cancel_all_refunds_every_escrow() {
+        crate::utils::log_recorder::install();
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+        let (initializer, fake_token_program, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (stats_key, _) = find_initializer_stats_address(&program_id, &initializer);
+        let escrow_keys = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let temp_keys = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let receive_keys = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let amounts = [10, 20];
+        let escrow_rent = Rent::default().minimum_balance(Escrow::LEN);
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d, mut lamports_e) =
+            (0, 0, 0, 0, 0);
+        let mut escrow_lamports = [escrow_rent; 2];
+        let mut temp_lamports = [0; 2];
+        let mut receive_lamports = [0; 2];
+
+        let mut stats_data = vec![0; InitializerStats::LEN];
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: 2,
+            },
+            &mut stats_data,
+        )
+        .unwrap();
+        let mut escrow_data: Vec<Vec<u8>> = temp_keys
+            .iter()
+            .zip(amounts)
+            .map(|(temp, amount)| {
+                let mut escrow = crate::test_utils::sample_escrow(initializer, amount);
+                escrow.temp_token_account_pubkey = *temp;
+                let mut data = vec![0; Escrow::LEN];
+                Escrow::pack(escrow, &mut data).unwrap();
+                data
+            })
+            .collect();
+        let mut temp_data: Vec<Vec<u8>> = amounts
+            .iter()
+            .map(|amount| {
+                let mut data = vec![0; TokenAccount::LEN];
+                TokenAccount::pack(
+                    TokenAccount {
+                        mint,
+                        owner: pda,
+                        amount: *amount,
+                        state: spl_token::state::AccountState::Initialized,
+                        ..TokenAccount::default()
+                    },
+                    &mut data,
+                )
+                .unwrap();
+                data
+            })
+            .collect();
+
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let token_program_info = AccountInfo::new(
+            &token_program,
+            false,
+            false,
+            &mut lamports_b,
+            &mut [],
+            &token_program,
+            true,
+            0,
+        );
+        let fake_token_program_info = AccountInfo::new(
+            &fake_token_program,
+            false,
+            false,
+            &mut lamports_c,
+            &mut [],
+            &fake_token_program,
+            true,
+            0,
+        );
+        let pda_info = AccountInfo::new(
+            &pda,
+            false,
+            false,
+            &mut lamports_d,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let stats_info = AccountInfo::new(
+            &stats_key,
+            false,
+            true,
+            &mut lamports_e,
+            &mut stats_data,
+            &program_id,
+            false,
+            0,
+        );
+        let escrow_infos: Vec<AccountInfo> = escrow_keys
+            .iter()
+            .zip(escrow_lamports.iter_mut())
+            .zip(escrow_data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, true, lamports, data, &program_id, false, 0)
+            })
+            .collect();
+        let temp_infos: Vec<AccountInfo> = temp_keys
+            .iter()
+            .zip(temp_lamports.iter_mut())
+            .zip(temp_data.iter_mut())
+            .map(|((key, lamports), data)| {
+                AccountInfo::new(key, false, true, lamports, data, &token_program, false, 0)
+            })
+            .collect();
+        let receive_infos: Vec<AccountInfo> = receive_keys
+            .iter()
+            .zip(receive_lamports.iter_mut())
+            .map(|(key, lamports)| {
+                AccountInfo::new(
+                    key,
+                    false,
+                    true,
+                    lamports,
+                    &mut [],
+                    &token_program,
+                    false,
+                    0,
+                )
+            })
+            .collect();
+        let mut accounts = vec![initializer_info, token_program_info, pda_info, stats_info];
+        for ((escrow, temp), receive) in escrow_infos.iter().zip(&temp_infos).zip(&receive_infos) {
+            accounts.extend([escrow.clone(), temp.clone(), receive.clone()]);
+        }
+        let data = EscrowInstruction::CancelAll {}.pack();
+
+        // the escrow authority only signs for the real token program
+        let mut fake_accounts = accounts.clone();
+        fake_accounts[1] = fake_token_program_info;
+        assert_eq!(
+            Processor::process(&program_id, &fake_accounts, &data),
+            Err(ProgramError::IncorrectProgramId)
+        );
+
+        assert_eq!(Processor::process(&program_id, &accounts, &data), Ok(()));
+        let invoked = crate::utils::log_recorder::invoked();
+        for (((escrow, temp), receive), expected) in escrow_infos
+            .iter()
+            .zip(temp_keys)
+            .zip(receive_keys)
+            .zip(amounts)
+        {
+            let refund = invoked
+                .iter()
+                .find(|ix| {
+                    ix.program_id == token_program
+                        && ix.accounts[0].pubkey == temp
+                        && ix.accounts[1].pubkey == receive
+                })
+                .unwrap();
+            assert!(matches!(
+                spl_token::instruction::TokenInstruction::unpack(&refund.data).unwrap(),
+                spl_token::instruction::TokenInstruction::Transfer { amount } if amount == expected
+            ));
+            assert_eq!(escrow.lamports(), 0);
+            assert!(escrow.data_is_empty());
+        }
+        assert_eq!(accounts[0].lamports(), 2 * escrow_rent);
+        let stats = InitializerStats::unpack(&accounts[3].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(stats.escrows_created, 0);
+    }
+
+    #[test]
+    fn test_get_program_version() {
+        crate::utils::log_recorder::install();
+        let data = EscrowInstruction::GetProgramVersion {}.pack();
+
+        assert_eq!(
+            Processor::process(&Pubkey::new_unique(), &[], &data),
+            Ok(())
+        );
+        let returned = crate::utils::log_recorder::returned();
+        assert_eq!(
+            u32::from_le_bytes(returned.as_slice().try_into().unwrap()),
+            crate::PROGRAM_VERSION
+        );
+    }
+
+    #[test]
+    fn test_escrow_authority_is_rejected_as_escrow_account() {
+        let program_id = Pubkey::new_unique();
+        let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+        let (signer, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports_a, mut lamports_b, mut lamports_c) = (0, 0, 1);
+        let mut pda_data = crate::test_utils::packed_sample(signer, 10);
+
+        let signer_info = AccountInfo::new(
+            &signer,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &spl_token::id(),
+            false,
+            0,
+        );
+        let pda_info = AccountInfo::new(
+            &pda,
+            false,
+            true,
+            &mut lamports_c,
+            &mut pda_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts_with_escrow_at = |index, len| {
+            let mut accounts = vec![other_info.clone(); len];
+            accounts[0] = signer_info.clone();
+            accounts[index] = pda_info.clone();
+            accounts
+        };
+        let cases = [
+            (
+                accounts_with_escrow_at(3, 8),
+                EscrowInstruction::InitEscrow {
+                    amount: 10,
+                    offered_mint: Pubkey::new_unique(),
+                    allowed_taker: None,
+                }
+                .pack(),
+            ),
+            (
+                accounts_with_escrow_at(6, 11),
+                EscrowInstruction::Exchange {
+                    amount: 10,
+                    nonce: 0,
+                }
+                .pack(),
+            ),
+            (
+                accounts_with_escrow_at(3, 6),
+                EscrowInstruction::Cancel { nonce: 0 }.pack(),
+            ),
+        ];
+
+        for (accounts, data) in cases.iter() {
+            assert_eq!(
+                Processor::process(&program_id, accounts, data),
+                Err(EscrowError::InvalidInstruction.into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_receive_account() {
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let initializer = Pubkey::new_unique();
+        let escrow_key = Pubkey::new_unique();
+        let current_receive = Pubkey::new_unique();
+        let new_receive = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+
+        let mut escrow_data = vec![0; Escrow::LEN];
+        let mut escrow_info = Escrow::unpack_unchecked(&escrow_data).unwrap();
+        escrow_info.is_initialized = true;
+        escrow_info.initializer_pubkey = initializer;
+        escrow_info.initializer_token_to_receive_account_pubkey = current_receive;
+        Escrow::pack(escrow_info, &mut escrow_data).unwrap();
+
+        let token_account_data = |owner| {
+            let mut data = vec![0; TokenAccount::LEN];
+            TokenAccount::pack(
+                TokenAccount {
+                    mint,
+                    owner,
+                    state: spl_token::state::AccountState::Initialized,
+                    ..TokenAccount::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            data
+        };
+        let mut current_receive_data = token_account_data(initializer);
+        let mut new_receive_data = token_account_data(initializer);
+
+        let accounts = vec![
+            AccountInfo::new(
+                &initializer,
+                true,
+                false,
+                &mut lamports_a,
+                &mut [],
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &escrow_key,
+                false,
+                true,
+                &mut lamports_b,
+                &mut escrow_data,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &current_receive,
+                false,
+                false,
+                &mut lamports_c,
+                &mut current_receive_data,
+                &token_program,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &new_receive,
+                false,
+                false,
+                &mut lamports_d,
+                &mut new_receive_data,
+                &token_program,
+                false,
+                0,
+            ),
+        ];
+        let data = EscrowInstruction::SetReceiveAccount {
+            new_account: new_receive,
+        };
+        let mut instruction_data = vec![11];
+        instruction_data.extend_from_slice(new_receive.as_ref());
+        assert_eq!(data.packed_len(), instruction_data.len());
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction_data),
+            Ok(())
+        );
+        // exchange now only accepts the new account as the initializer's receive account
+        let escrow_info = Escrow::unpack(&accounts[1].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(
+            escrow_info.initializer_token_to_receive_account_pubkey,
+            new_receive
+        );
+    }
+
+    #[test]
+    fn test_transfer_escrow() {
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (initializer, new_initializer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (escrow_key, current_receive, new_receive) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mint = Pubkey::new_unique();
+        let (stats_key, _) = find_initializer_stats_address(&program_id, &initializer);
+        let (new_stats_key, _) = find_initializer_stats_address(&program_id, &new_initializer);
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 1, 0, 0);
+        let (mut lamports_e, mut lamports_f, mut lamports_g) = (0, 0, 0);
+
+        let mut escrow_info = crate::test_utils::sample_escrow(initializer, 10);
+        escrow_info.initializer_token_to_receive_account_pubkey = current_receive;
+        let temp = escrow_info.temp_token_account_pubkey;
+        let mut escrow_data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow_info, &mut escrow_data).unwrap();
+
+        let token_account_data = |owner| {
+            let mut data = vec![0; TokenAccount::LEN];
+            TokenAccount::pack(
+                TokenAccount {
+                    mint,
+                    owner,
+                    state: spl_token::state::AccountState::Initialized,
+                    ..TokenAccount::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            data
+        };
+        let mut current_receive_data = token_account_data(initializer);
+        let mut new_receive_data = token_account_data(new_initializer);
+        let stats_data = |escrows_created| {
+            let mut data = vec![0; InitializerStats::LEN];
+            InitializerStats::pack(
+                InitializerStats {
+                    is_initialized: true,
+                    escrows_created,
+                },
+                &mut data,
+            )
+            .unwrap();
+            data
+        };
+        let mut old_stats_data = stats_data(1);
+        let mut new_stats_data = stats_data(MAX_OPEN_ESCROWS);
+
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            false,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let escrow_account = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut lamports_b,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let current_receive_info = AccountInfo::new(
+            &current_receive,
+            false,
+            false,
+            &mut lamports_c,
+            &mut current_receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let accounts = vec![
+            initializer_info.clone(),
+            escrow_account.clone(),
+            current_receive_info.clone(),
+            AccountInfo::new(
+                &new_receive,
+                false,
+                false,
+                &mut lamports_d,
+                &mut new_receive_data,
+                &token_program,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &stats_key,
+                false,
+                true,
+                &mut lamports_f,
+                &mut old_stats_data,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &new_stats_key,
+                false,
+                true,
+                &mut lamports_g,
+                &mut new_stats_data,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+        let stats = |index: usize| {
+            InitializerStats::unpack(&accounts[index].try_borrow_data().unwrap())
+                .unwrap()
+                .escrows_created
+        };
+        let transfer_data = EscrowInstruction::TransferEscrow { new_initializer }.pack();
+
+        // the new initializer's receive account must be theirs
+        let mut wrong_owner_data = transfer_data.clone();
+        wrong_owner_data[1..].copy_from_slice(Pubkey::new_unique().as_ref());
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &wrong_owner_data),
+            Err(EscrowError::InvalidOwner.into())
+        );
+
+        // the new initializer is held to the same limit as if they had opened the escrow
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &transfer_data),
+            Err(EscrowError::TooManyEscrows.into())
+        );
+        assert_eq!(
+            Escrow::unpack(&accounts[1].try_borrow_data().unwrap())
+                .unwrap()
+                .initializer_pubkey,
+            initializer
+        );
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: MAX_OPEN_ESCROWS - 1,
+            },
+            &mut accounts[5].try_borrow_mut_data().unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &transfer_data),
+            Ok(())
+        );
+        assert_eq!(stats(4), 0);
+        assert_eq!(stats(5), MAX_OPEN_ESCROWS);
+        let escrow_info = Escrow::unpack(&accounts[1].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(escrow_info.initializer_pubkey, new_initializer);
+        assert_eq!(
+            escrow_info.initializer_token_to_receive_account_pubkey,
+            new_receive
+        );
+        assert_eq!(escrow_info.nonce, 1);
+
+        // the old initializer can no longer cancel
+        let cancel_accounts = vec![
+            initializer_info,
+            AccountInfo::new(
+                &temp,
+                false,
+                true,
+                &mut lamports_e,
+                &mut [],
+                &token_program,
+                false,
+                0,
+            ),
+            current_receive_info,
+            escrow_account,
+            accounts[3].clone(),
+        ];
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &cancel_accounts,
+                &EscrowInstruction::Cancel { nonce: 1 }.pack()
+            ),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_exchange_against_emptied_escrow() {
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (taker, escrow_key, temp, other) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d, mut lamports_e) =
+            (0, 0, 0, 1, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin: Pubkey::new_unique(),
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let mut escrow_data = crate::test_utils::packed_sample(Pubkey::new_unique(), 10);
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                owner: Pubkey::new_unique(),
+                amount: 0,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut temp_data,
+        )
+        .unwrap();
+
+        let taker_info = AccountInfo::new(
+            &taker,
+            true,
+            false,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &token_program,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_c,
+            &mut temp_data,
+            &token_program,
+            false,
+            0,
+        );
+        let escrow_info = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut lamports_d,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports_e,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts = vec![
+            taker_info,
+            other_info.clone(),
+            other_info.clone(),
+            temp_info,
+            other_info.clone(),
+            other_info.clone(),
+            escrow_info,
+            other_info.clone(),
+            other_info.clone(),
+            config_info,
+            other_info,
+        ];
+        let exchange_data = [1, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        // the temp account was drained by the winning taker
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &exchange_data),
+            Err(EscrowError::AlreadyFilled.into())
+        );
+
+        // the escrow was updated after the exchange was signed for nonce 0
+        let mut escrow = Escrow::unpack(&accounts[6].try_borrow_data().unwrap()).unwrap();
+        escrow.bump_nonce();
+        Escrow::pack(escrow, &mut accounts[6].try_borrow_mut_data().unwrap()).unwrap();
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &exchange_data),
+            Err(EscrowError::InvalidInstruction.into())
+        );
+
+        // the escrow was closed by the winning taker
+        accounts[6].try_borrow_mut_data().unwrap().fill(0);
+        **accounts[6].try_borrow_mut_lamports().unwrap() = 0;
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &exchange_data),
+            Err(EscrowError::AlreadyFilled.into())
+        );
+    }
+
+    #[test]
+    fn test_exchange_against_zero_amount_escrow() {
+        let program_id = Pubkey::new_unique();
+        let (config_key, _) = find_config_address(&program_id);
+        let (taker, escrow_key, other) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 1, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin: Pubkey::new_unique(),
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let mut escrow_data = crate::test_utils::packed_sample(Pubkey::new_unique(), 0);
+        let mut temp_data = vec![0; TokenAccount::LEN];
+
+        let taker_info = AccountInfo::new(
+            &taker,
+            true,
+            false,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            true,
+            &mut lamports_b,
+            &mut temp_data,
+            &spl_token::id(),
+            false,
+            0,
+        );
+        let escrow_info = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut lamports_c,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports_d,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts = vec![
+            taker_info,
+            other_info.clone(),
+            other_info.clone(),
+            other_info.clone(),
+            other_info.clone(),
+            other_info.clone(),
+            escrow_info,
+            other_info.clone(),
+            other_info.clone(),
+            config_info,
+            other_info,
+        ];
+        let exchange_data = EscrowInstruction::Exchange {
+            amount: 0,
+            nonce: 0,
+        }
+        .pack();
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &exchange_data),
+            Err(EscrowError::ExpectedAmountMismatch.into())
+        );
+
+        // a funded escrow account that was never initialized
+        accounts[6].try_borrow_mut_data().unwrap().fill(0);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &exchange_data),
+            Err(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn test_exchange_rejects_temp_account_of_another_mint() {
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (taker, receive, temp, escrow_key, other) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d, mut lamports_e) =
+            (0, 0, 0, 0, 1);
+        let mut lamports_f = 0;
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin: Pubkey::new_unique(),
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let mut escrow = crate::test_utils::sample_escrow(Pubkey::new_unique(), 10);
+        escrow.temp_token_account_pubkey = temp;
+        let mut escrow_data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow, &mut escrow_data).unwrap();
+
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                owner: Pubkey::new_unique(),
+                amount: 10,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut temp_data,
+        )
+        .unwrap();
+        let mut receive_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                owner: taker,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut receive_data,
+        )
+        .unwrap();
+
+        let taker_info = AccountInfo::new(
+            &taker,
+            true,
+            false,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &token_program,
+            false,
+            0,
+        );
+        let receive_info = AccountInfo::new(
+            &receive,
+            false,
+            true,
+            &mut lamports_c,
+            &mut receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_d,
+            &mut temp_data,
+            &token_program,
+            false,
+            0,
+        );
+        let escrow_info = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut lamports_e,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports_f,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts = vec![
+            taker_info,
+            other_info.clone(),
+            receive_info,
+            temp_info,
+            other_info.clone(),
+            other_info.clone(),
+            escrow_info,
+            other_info.clone(),
+            other_info.clone(),
+            config_info,
+            other_info,
+        ];
+
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &accounts,
+                &[1, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+            ),
+            Err(EscrowError::InvalidTokenMint.into())
+        );
+    }
+
+    #[test]
+    fn test_cancel_rejects_read_only_escrow() {
+        let program_id = Pubkey::new_unique();
+        let initializer = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let escrow_key = Pubkey::new_unique();
+        let (mut lamports_a, mut lamports_b, mut lamports_c) = (0, 0, 0);
+        let mut escrow_data = crate::test_utils::packed_sample(initializer, 10);
+
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &spl_token::id(),
+            false,
+            0,
+        );
+        let escrow_info = AccountInfo::new(
+            &escrow_key,
+            false,
+            false,
+            &mut lamports_c,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts = vec![
+            initializer_info,
+            other_info.clone(),
+            other_info.clone(),
+            escrow_info,
+            other_info.clone(),
+            other_info,
+        ];
+        let cancel_data = EscrowInstruction::Cancel { nonce: 0 }.pack();
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &cancel_data),
+            Err(EscrowError::InvalidInstruction.into())
+        );
+    }
+
+    #[test]
+    fn test_cancel_rejects_uninitialized_escrow() {
+        let program_id = Pubkey::new_unique();
+        let initializer = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let escrow_key = Pubkey::new_unique();
+        let (mut lamports_a, mut lamports_b, mut lamports_c) = (0, 0, 1);
+        let mut escrow_data = vec![0; Escrow::LEN];
+
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &spl_token::id(),
+            false,
+            0,
+        );
+        let escrow_info = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut lamports_c,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts = vec![
+            initializer_info,
+            other_info.clone(),
+            other_info.clone(),
+            escrow_info,
+            other_info.clone(),
+            other_info,
+        ];
+        let cancel_data = EscrowInstruction::Cancel { nonce: 0 }.pack();
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &cancel_data),
+            Err(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn test_health_mask() {
+        let program_id = Pubkey::new_unique();
+        let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+        let (escrow_key, temp_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports_a, mut lamports_b) = (0, 0);
+
+        let mut escrow = crate::test_utils::sample_escrow(Pubkey::new_unique(), 10);
+        escrow.temp_token_account_pubkey = temp_key;
+        let mut escrow_data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow, &mut escrow_data).unwrap();
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint: Pubkey::new_unique(),
+                owner: pda,
+                amount: 5,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut temp_data,
+        )
+        .unwrap();
+
+        let escrow_info = AccountInfo::new(
+            &escrow_key,
+            false,
+            false,
+            &mut lamports_a,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp_key,
+            false,
+            false,
+            &mut lamports_b,
+            &mut temp_data,
+            &spl_token::id(),
+            false,
+            0,
+        );
+        assert_eq!(
+            Processor::health_mask(&escrow_info, &temp_info, &program_id),
+            Ok(0)
+        );
+
+        // a tampered escrow: reassigned to another program and its temp account drained
+        let other_program = Pubkey::new_unique();
+        let escrow_info = AccountInfo {
+            owner: &other_program,
+            ..escrow_info
+        };
+        let mut temp = TokenAccount::unpack(&temp_info.try_borrow_data().unwrap()).unwrap();
+        temp.amount = 0;
+        TokenAccount::pack(temp, &mut temp_info.try_borrow_mut_data().unwrap()).unwrap();
+        assert_eq!(
+            Processor::health_mask(&escrow_info, &temp_info, &program_id),
+            Ok(HEALTH_ESCROW_OWNER | HEALTH_TEMP_BALANCE)
+        );
+    }
+
+    #[test]
+    fn test_merge_escrows() {
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+        let initializer = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (escrow_a, escrow_b, temp_a, temp_b) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d, mut lamports_e) =
+            (0, 100, 0, 50, 0);
+        let (mut lamports_f, mut lamports_g, mut lamports_h) = (0, 0, 0);
+        let (stats_key, _) = find_initializer_stats_address(&program_id, &initializer);
+        let mut stats_data = vec![0; InitializerStats::LEN];
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: 2,
+            },
+            &mut stats_data,
+        )
+        .unwrap();
+
+        let mut escrow_a_info = crate::test_utils::sample_escrow(initializer, 10);
+        escrow_a_info.temp_token_account_pubkey = temp_a;
+        let mut escrow_b_info = crate::test_utils::sample_escrow(initializer, 15);
+        escrow_b_info.temp_token_account_pubkey = temp_b;
+        escrow_b_info.initializer_token_to_receive_account_pubkey =
+            escrow_a_info.initializer_token_to_receive_account_pubkey;
+        let mut escrow_a_data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow_a_info, &mut escrow_a_data).unwrap();
+        let mut escrow_b_data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow_b_info, &mut escrow_b_data).unwrap();
+        let temp_data = |amount| {
+            let mut data = vec![0; TokenAccount::LEN];
+            TokenAccount::pack(
+                TokenAccount {
+                    mint,
+                    owner: pda,
+                    amount,
+                    state: spl_token::state::AccountState::Initialized,
+                    ..TokenAccount::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            data
+        };
+        let (mut temp_a_data, mut temp_b_data) = (temp_data(5), temp_data(7));
+
+        let accounts = vec![
+            AccountInfo::new(
+                &initializer,
+                true,
+                true,
+                &mut lamports_a,
+                &mut [],
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &escrow_a,
+                false,
+                true,
+                &mut lamports_b,
+                &mut escrow_a_data,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &temp_a,
+                false,
+                true,
+                &mut lamports_c,
+                &mut temp_a_data,
+                &token_program,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &escrow_b,
+                false,
+                true,
+                &mut lamports_d,
+                &mut escrow_b_data,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &temp_b,
+                false,
+                true,
+                &mut lamports_e,
+                &mut temp_b_data,
+                &token_program,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &token_program,
+                false,
+                false,
+                &mut lamports_f,
+                &mut [],
+                &program_id,
+                true,
+                0,
+            ),
+            AccountInfo::new(
+                &pda,
+                false,
+                false,
+                &mut lamports_g,
+                &mut [],
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &stats_key,
+                false,
+                true,
+                &mut lamports_h,
+                &mut stats_data,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+        let merge_data = EscrowInstruction::MergeEscrows {}.pack();
+
+        let mut wrong_token_program = accounts.clone();
+        wrong_token_program[5] = accounts[0].clone();
+        assert_eq!(
+            Processor::process(&program_id, &wrong_token_program, &merge_data),
+            Err(ProgramError::IncorrectProgramId)
+        );
+        let mut wrong_pda = accounts.clone();
+        wrong_pda[6] = accounts[0].clone();
+        assert_eq!(
+            Processor::process(&program_id, &wrong_pda, &merge_data),
+            Err(ProgramError::InvalidSeeds)
+        );
+        assert_eq!(accounts[3].lamports(), 50);
+
+        // token program CPIs are no-ops off-chain, only the escrow accounts change here
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &merge_data),
+            Ok(())
+        );
+        let merged = Escrow::unpack(&accounts[1].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(merged.expected_amount, 25);
+        assert!(accounts[3]
+            .try_borrow_data()
+            .unwrap()
+            .iter()
+            .all(|b| *b == 0));
+        assert_eq!(accounts[3].lamports(), 0);
+        assert_eq!(accounts[0].lamports(), 50);
+        let stats = InitializerStats::unpack(&accounts[7].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(stats.escrows_created, 1);
+    }
+
+    #[test]
+    fn test_split_escrow() {
+        crate::utils::log_recorder::install();
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+        let initializer = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (escrow_a, escrow_b, temp_a, temp_b) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_e) = (0, 100, 0, 0);
+        let mut lamports_d = Rent::default().minimum_balance(Escrow::LEN);
+        let (mut lamports_f, mut lamports_g, mut lamports_h) = (0, 0, 0);
+        let (stats_key, _) = find_initializer_stats_address(&program_id, &initializer);
+        let mut stats_data = vec![0; InitializerStats::LEN];
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: MAX_OPEN_ESCROWS,
+            },
+            &mut stats_data,
+        )
+        .unwrap();
+
+        let mut escrow_a_info = crate::test_utils::sample_escrow(initializer, 100);
+        escrow_a_info.temp_token_account_pubkey = temp_a;
+        let mut escrow_a_data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow_a_info, &mut escrow_a_data).unwrap();
+        let mut escrow_b_data = vec![0; Escrow::LEN];
+        let temp_data = |amount| {
+            let mut data = vec![0; TokenAccount::LEN];
+            TokenAccount::pack(
+                TokenAccount {
+                    mint,
+                    owner: pda,
+                    amount,
+                    state: spl_token::state::AccountState::Initialized,
+                    ..TokenAccount::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            data
+        };
+        let (mut temp_a_data, mut temp_b_data) = (temp_data(10), temp_data(0));
+
+        let accounts = vec![
+            AccountInfo::new(
+                &initializer,
+                true,
+                false,
+                &mut lamports_a,
+                &mut [],
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &escrow_a,
+                false,
+                true,
+                &mut lamports_b,
+                &mut escrow_a_data,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &temp_a,
+                false,
+                true,
+                &mut lamports_c,
+                &mut temp_a_data,
+                &token_program,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &escrow_b,
+                false,
+                true,
+                &mut lamports_d,
+                &mut escrow_b_data,
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &temp_b,
+                false,
+                true,
+                &mut lamports_e,
+                &mut temp_b_data,
+                &token_program,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &token_program,
+                false,
+                false,
+                &mut lamports_f,
+                &mut [],
+                &program_id,
+                true,
+                0,
+            ),
+            AccountInfo::new(
+                &pda,
+                false,
+                false,
+                &mut lamports_g,
+                &mut [],
+                &program_id,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &stats_key,
+                false,
+                true,
+                &mut lamports_h,
+                &mut stats_data,
+                &program_id,
+                false,
+                0,
+            ),
+        ];
+        let split_data = EscrowInstruction::SplitEscrow { amount: 4 }.pack();
+
+        let mut wrong_token_program = accounts.clone();
+        wrong_token_program[5] = accounts[0].clone();
+        assert_eq!(
+            Processor::process(&program_id, &wrong_token_program, &split_data),
+            Err(ProgramError::IncorrectProgramId)
+        );
+        let mut wrong_pda = accounts.clone();
+        wrong_pda[6] = accounts[0].clone();
+        assert_eq!(
+            Processor::process(&program_id, &wrong_pda, &split_data),
+            Err(ProgramError::InvalidSeeds)
+        );
+        assert!(
+            !Escrow::unpack_unchecked(&accounts[3].try_borrow_data().unwrap())
+                .unwrap()
+                .is_initialized
+        );
+
+        // the new escrow counts against the initializer's open escrow limit
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &split_data),
+            Err(EscrowError::TooManyEscrows.into())
+        );
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: 1,
+            },
+            &mut accounts[7].try_borrow_mut_data().unwrap(),
+        )
+        .unwrap();
+
+        // token program CPIs are no-ops off-chain, only the escrow accounts change here
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &split_data),
+            Ok(())
+        );
+        let kept = Escrow::unpack(&accounts[1].try_borrow_data().unwrap()).unwrap();
+        let split = Escrow::unpack(&accounts[3].try_borrow_data().unwrap()).unwrap();
+        assert_eq!((kept.expected_amount, split.expected_amount), (60, 40));
+        assert_eq!(kept.temp_token_account_pubkey, temp_a);
+        assert_eq!(split.temp_token_account_pubkey, temp_b);
+        assert_eq!(split.initializer_pubkey, initializer);
+        let stats = InitializerStats::unpack(&accounts[7].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(stats.escrows_created, 2);
+        assert_eq!(
+            split.initializer_token_to_receive_account_pubkey,
+            kept.initializer_token_to_receive_account_pubkey
+        );
+
+        // the new escrow is initialized now, it can't be split into again
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &split_data),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+    }
+
+    #[test]
+    fn test_freeze_blocks_exchange_until_unfrozen() {
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+        let (taker, initializer, escrow_key, other, takers_receive, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let escrow = crate::test_utils::sample_escrow(initializer, 10);
+        let (temp, receive) = (
+            escrow.temp_token_account_pubkey,
+            escrow.initializer_token_to_receive_account_pubkey,
+        );
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+        let (mut lamports_e, mut lamports_f, mut lamports_g, mut lamports_h) = (0, 0, 1, 0);
+        let (mut lamports_i, mut lamports_j) = (0, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin: Pubkey::new_unique(),
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let (stats_key, _) = find_initializer_stats_address(&program_id, &initializer);
+        let mut stats_data = vec![0; InitializerStats::LEN];
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: 1,
+            },
+            &mut stats_data,
+        )
+        .unwrap();
+        let mut escrow_data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow, &mut escrow_data).unwrap();
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner: pda,
+                amount: 10,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut temp_data,
+        )
+        .unwrap();
+        let mut takers_receive_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner: taker,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut takers_receive_data,
+        )
+        .unwrap();
+
+        let taker_info = AccountInfo::new(
+            &taker,
+            true,
+            false,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &token_program,
+            false,
+            0,
+        );
+        let takers_receive_info = AccountInfo::new(
+            &takers_receive,
+            false,
+            true,
+            &mut lamports_j,
+            &mut takers_receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_c,
+            &mut temp_data,
+            &token_program,
+            false,
+            0,
+        );
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_d,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let receive_info = AccountInfo::new(
+            &receive,
+            false,
+            true,
+            &mut lamports_e,
+            &mut [],
+            &token_program,
+            false,
+            0,
+        );
+        let escrow_info = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut lamports_g,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let token_program_info = AccountInfo::new(
+            &token_program,
+            false,
+            false,
+            &mut lamports_f,
+            &mut [],
+            &program_id,
+            true,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports_h,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let stats_info = AccountInfo::new(
+            &stats_key,
+            false,
+            true,
+            &mut lamports_i,
+            &mut stats_data,
+            &program_id,
+            false,
+            0,
+        );
+        let update_accounts = vec![initializer_info.clone(), escrow_info.clone()];
+        let accounts = vec![
+            taker_info,
+            other_info.clone(),
+            takers_receive_info,
+            temp_info,
+            initializer_info,
+            receive_info,
+            escrow_info,
+            token_program_info,
+            other_info,
+            config_info,
+            stats_info,
+        ];
+        let exchange_data = |nonce| EscrowInstruction::Exchange { amount: 10, nonce }.pack();
+
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &update_accounts,
+                &EscrowInstruction::Freeze {}.pack()
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &exchange_data(1)),
+            Err(EscrowError::Frozen.into())
+        );
+
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &update_accounts,
+                &EscrowInstruction::Unfreeze {}.pack()
+            ),
+            Ok(())
+        );
+        // the token program CPIs are no-ops off-chain, so the exchange runs through to the close
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &exchange_data(2)),
+            Ok(())
+        );
+        assert_eq!(accounts[6].lamports(), 0);
+        let stats = InitializerStats::unpack(&accounts[10].try_borrow_data().unwrap()).unwrap();
+        assert_eq!(stats.escrows_created, 0);
+    }
+
+    #[test]
+    fn test_reset_time_lock_invalidates_signed_exchanges() {
+        crate::utils::log_recorder::install();
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+        let (taker, initializer, escrow_key, other, takers_receive, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let escrow = crate::test_utils::sample_escrow(initializer, 10);
+        let (temp, receive) = (
+            escrow.temp_token_account_pubkey,
+            escrow.initializer_token_to_receive_account_pubkey,
+        );
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+        let (mut lamports_e, mut lamports_f, mut lamports_g, mut lamports_h) = (0, 0, 1, 0);
+        let (mut lamports_i, mut lamports_j) = (0, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin: Pubkey::new_unique(),
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let (stats_key, _) = find_initializer_stats_address(&program_id, &initializer);
+        let mut stats_data = vec![0; InitializerStats::LEN];
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: 1,
+            },
+            &mut stats_data,
+        )
+        .unwrap();
+        let mut escrow_data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow, &mut escrow_data).unwrap();
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner: pda,
+                amount: 10,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut temp_data,
+        )
+        .unwrap();
+        let mut takers_receive_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner: taker,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut takers_receive_data,
+        )
+        .unwrap();
+
+        let taker_info = AccountInfo::new(
+            &taker,
+            true,
+            false,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &token_program,
+            false,
+            0,
+        );
+        let takers_receive_info = AccountInfo::new(
+            &takers_receive,
+            false,
+            true,
+            &mut lamports_j,
+            &mut takers_receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_c,
+            &mut temp_data,
+            &token_program,
+            false,
+            0,
+        );
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_d,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let receive_info = AccountInfo::new(
+            &receive,
+            false,
+            true,
+            &mut lamports_e,
+            &mut [],
+            &token_program,
+            false,
+            0,
+        );
+        let escrow_info = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut lamports_g,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let token_program_info = AccountInfo::new(
+            &token_program,
+            false,
+            false,
+            &mut lamports_f,
+            &mut [],
+            &program_id,
+            true,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports_h,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let stats_info = AccountInfo::new(
+            &stats_key,
+            false,
+            true,
+            &mut lamports_i,
+            &mut stats_data,
+            &program_id,
+            false,
+            0,
+        );
+        let update_accounts = vec![initializer_info.clone(), escrow_info.clone()];
+        let accounts = vec![
+            taker_info,
+            other_info.clone(),
+            takers_receive_info,
+            temp_info,
+            initializer_info,
+            receive_info,
+            escrow_info,
+            token_program_info,
+            other_info,
+            config_info,
+            stats_info,
+        ];
+        let exchange_data = |nonce| EscrowInstruction::Exchange { amount: 10, nonce }.pack();
+
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &update_accounts,
+                &EscrowInstruction::ResetTimeLock {}.pack()
+            ),
+            Ok(())
+        );
+        let escrow = Escrow::unpack(&accounts[6].try_borrow_data().unwrap()).unwrap();
+        assert_eq!((escrow.unlock_time, escrow.time_out), (100, 1_100));
+        assert_eq!(escrow.nonce, 1);
+
+        // signed before the reset, against the old window
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &exchange_data(0)),
+            Err(EscrowError::InvalidInstruction.into())
+        );
+        assert_eq!(accounts[6].lamports(), 1);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &exchange_data(1)),
+            Ok(())
+        );
+        assert_eq!(accounts[6].lamports(), 0);
+    }
+
+    #[test]
+    fn test_exchange_within_amount_tolerance() {
+        let program_id = Pubkey::new_unique();
+        let token_program = spl_token::id();
+        let (config_key, _) = find_config_address(&program_id);
+        let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+        let (taker, initializer, escrow_key, other, takers_receive, mint, admin) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let escrow = crate::test_utils::sample_escrow(initializer, 10);
+        let (temp, receive) = (
+            escrow.temp_token_account_pubkey,
+            escrow.initializer_token_to_receive_account_pubkey,
+        );
+        let (mut lamports_a, mut lamports_b, mut lamports_c, mut lamports_d) = (0, 0, 0, 0);
+        let (mut lamports_e, mut lamports_f, mut lamports_g, mut lamports_h) = (0, 0, 1, 0);
+        let (mut lamports_i, mut lamports_j, mut lamports_k) = (0, 0, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin,
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let (stats_key, _) = find_initializer_stats_address(&program_id, &initializer);
+        let mut stats_data = vec![0; InitializerStats::LEN];
+        InitializerStats::pack(
+            InitializerStats {
+                is_initialized: true,
+                escrows_created: 1,
+            },
+            &mut stats_data,
+        )
+        .unwrap();
+        let mut escrow_data = vec![0; Escrow::LEN];
+        Escrow::pack(escrow, &mut escrow_data).unwrap();
+        let packed_escrow = escrow_data.clone();
+        let mut temp_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner: pda,
+                amount: 10,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut temp_data,
+        )
+        .unwrap();
+        let mut takers_receive_data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(
+            TokenAccount {
+                mint,
+                owner: taker,
+                state: spl_token::state::AccountState::Initialized,
+                ..TokenAccount::default()
+            },
+            &mut takers_receive_data,
+        )
+        .unwrap();
+
+        let taker_info = AccountInfo::new(
+            &taker,
+            true,
+            false,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            true,
+            &mut lamports_b,
+            &mut [],
+            &token_program,
+            false,
+            0,
+        );
+        let takers_receive_info = AccountInfo::new(
+            &takers_receive,
+            false,
+            true,
+            &mut lamports_j,
+            &mut takers_receive_data,
+            &token_program,
+            false,
+            0,
+        );
+        let temp_info = AccountInfo::new(
+            &temp,
+            false,
+            true,
+            &mut lamports_c,
+            &mut temp_data,
+            &token_program,
+            false,
+            0,
+        );
+        let initializer_info = AccountInfo::new(
+            &initializer,
+            true,
+            true,
+            &mut lamports_d,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let receive_info = AccountInfo::new(
+            &receive,
+            false,
+            true,
+            &mut lamports_e,
+            &mut [],
+            &token_program,
+            false,
+            0,
+        );
+        let escrow_info = AccountInfo::new(
+            &escrow_key,
+            false,
+            true,
+            &mut lamports_g,
+            &mut escrow_data,
+            &program_id,
+            false,
+            0,
+        );
+        let token_program_info = AccountInfo::new(
+            &token_program,
+            false,
+            false,
+            &mut lamports_f,
+            &mut [],
+            &program_id,
+            true,
+            0,
+        );
+        let admin_info = AccountInfo::new(
+            &admin,
+            true,
+            false,
+            &mut lamports_k,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            true,
+            &mut lamports_h,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let stats_info = AccountInfo::new(
+            &stats_key,
+            false,
+            true,
+            &mut lamports_i,
+            &mut stats_data,
+            &program_id,
+            false,
+            0,
+        );
+        let admin_accounts = vec![admin_info, config_info.clone()];
+        let accounts = vec![
+            taker_info,
+            other_info.clone(),
+            takers_receive_info,
+            temp_info,
+            initializer_info,
+            receive_info,
+            escrow_info,
+            token_program_info,
+            other_info,
+            config_info,
+            stats_info,
+        ];
+        let exchange_data = |amount| EscrowInstruction::Exchange { amount, nonce: 0 }.pack();
+
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &admin_accounts,
+                &EscrowInstruction::SetAmountTolerance {
+                    amount_tolerance: 2
+                }
+                .pack()
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            Config::unpack(&accounts[9].try_borrow_data().unwrap())
+                .unwrap()
+                .amount_tolerance,
+            2
+        );
+
+        // the temp account holds 10, just outside the band on either side
+        for amount in [7, 13] {
+            assert_eq!(
+                Processor::process(&program_id, &accounts, &exchange_data(amount)),
+                Err(EscrowError::ExpectedAmountMismatch.into())
+            );
+        }
+        // on both edges of the band, the token program CPIs are no-ops off-chain
+        for amount in [8, 12] {
+            accounts[6]
+                .try_borrow_mut_data()
+                .unwrap()
+                .copy_from_slice(&packed_escrow);
+            **accounts[6].try_borrow_mut_lamports().unwrap() = 1;
+            assert_eq!(
+                Processor::process(&program_id, &accounts, &exchange_data(amount)),
+                Ok(())
+            );
+            assert_eq!(accounts[6].lamports(), 0);
+        }
+    }
+
+    #[test]
+    fn test_set_amount_tolerance_needs_the_admin() {
+        let program_id = Pubkey::new_unique();
+        let (config_key, _) = find_config_address(&program_id);
+        let (admin, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports_a, mut lamports_b) = (0, 0);
+
+        let mut config_data = vec![0; Config::LEN];
+        Config::pack(
+            Config {
+                is_initialized: true,
+                admin,
+                paused: false,
+                amount_tolerance: 0,
+            },
+            &mut config_data,
+        )
+        .unwrap();
+        let other_info = AccountInfo::new(
+            &other,
+            true,
+            false,
+            &mut lamports_a,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let config_info = AccountInfo::new(
+            &config_key,
+            false,
+            true,
+            &mut lamports_b,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+        let accounts = vec![other_info, config_info];
+
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &accounts,
+                &EscrowInstruction::SetAmountTolerance {
+                    amount_tolerance: 2
+                }
+                .pack()
+            ),
+            Err(EscrowError::Unauthorized.into())
+        );
+        assert_eq!(
+            Config::unpack(&accounts[1].try_borrow_data().unwrap())
+                .unwrap()
+                .amount_tolerance,
+            0
+        );
+    }
+
+    #[test]
+    fn test_amount_above_supply_is_rejected() {
+        let mint = Mint {
+            supply: 1_000,
+            decimals: 6,
+            is_initialized: true,
+            ..Mint::default()
+        };
+
+        assert!(Processor::check_amount_within_supply(1_000, &mint).is_ok());
+        assert_eq!(
+            Processor::check_amount_within_supply(1_001, &mint),
+            Err(EscrowError::ExpectedAmountMismatch.into())
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use crate::{error::EscrowError, math};
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
     program_error::ProgramError,
@@ -13,6 +14,246 @@ pub struct Escrow {
     pub expected_amount: u64,
     pub unlock_time: u64,
     pub time_out: u64,
+    pub allowed_taker: Option<Pubkey>,
+    /// Bumped by every instruction that updates the escrow, exchange and cancel must name the
+    /// current value so they can't be replayed against an escrow that changed since they were signed
+    pub nonce: u64,
+    /// Set by the initializer to pause the listing, exchange fails while it is set
+    pub frozen: bool,
+    pub in_progress: bool,
+}
+
+impl Escrow {
+    /// Whether `taker` may exchange this escrow, every taker may when no allowed taker is set
+    pub fn can_be_taken_by(&self, taker: &Pubkey) -> bool {
+        self.allowed_taker
+            .map_or(true, |allowed_taker| allowed_taker == *taker)
+    }
+
+    /// Fails unless `taker` may exchange this escrow right now, with [EscrowError::Frozen] while
+    /// the initializer has it frozen and [EscrowError::Unauthorized] when another taker is allowed.
+    /// Exchange and CheckTaker both go through this, `unlock_time` and `time_out` are not enforced.
+    pub fn check_taker(&self, taker: &Pubkey) -> Result<(), EscrowError> {
+        if self.frozen {
+            return Err(EscrowError::Frozen);
+        }
+        if !self.can_be_taken_by(taker) {
+            return Err(EscrowError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    /// Fails with [EscrowError::InvalidInstruction] unless `expected` is the current nonce
+    pub fn check_nonce(&self, expected: u64) -> Result<(), EscrowError> {
+        if self.nonce != expected {
+            return Err(EscrowError::InvalidInstruction);
+        }
+        Ok(())
+    }
+
+    /// Records a state change, invalidating exchanges and cancels signed for the previous nonce
+    pub fn bump_nonce(&mut self) {
+        self.nonce = self.nonce.wrapping_add(1);
+    }
+
+    /// Adds what `other` asks for to this escrow, both must belong to the same initializer and pay
+    /// into the same receive account, i.e. trade the same mint pair
+    pub fn absorb(&mut self, other: &Escrow) -> Result<(), ProgramError> {
+        if self.initializer_pubkey != other.initializer_pubkey
+            || self.initializer_token_to_receive_account_pubkey
+                != other.initializer_token_to_receive_account_pubkey
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        self.expected_amount = math::add(self.expected_amount, other.expected_amount)?;
+        self.bump_nonce();
+        Ok(())
+    }
+
+    /// Moves `tokens` of the `temp_amount` tokens this escrow offers to a new escrow paid out of
+    /// `temp_token_account`, which asks for the same share of the expected amount rounded down.
+    /// Both escrows must keep a non-zero share.
+    pub fn split_off(
+        &mut self,
+        tokens: u64,
+        temp_amount: u64,
+        temp_token_account: Pubkey,
+    ) -> Result<Escrow, ProgramError> {
+        if tokens == 0 || tokens >= temp_amount {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let expected_amount = math::mul(self.expected_amount, tokens)? / temp_amount;
+        if expected_amount == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.expected_amount = math::sub(self.expected_amount, expected_amount)?;
+        self.bump_nonce();
+        Ok(Escrow {
+            is_initialized: true,
+            initializer_pubkey: self.initializer_pubkey,
+            temp_token_account_pubkey: temp_token_account,
+            initializer_token_to_receive_account_pubkey: self
+                .initializer_token_to_receive_account_pubkey,
+            expected_amount,
+            unlock_time: self.unlock_time,
+            time_out: self.time_out,
+            allowed_taker: self.allowed_taker,
+            nonce: 0,
+            frozen: false,
+            in_progress: false,
+        })
+    }
+
+    /// Flags an exchange as running, an exchange reentering through a token CPI finds the flag set
+    pub fn begin_exchange(&mut self) -> Result<(), EscrowError> {
+        if self.in_progress {
+            return Err(EscrowError::Unauthorized);
+        }
+        self.in_progress = true;
+        Ok(())
+    }
+}
+
+/// Offsets of the fields in packed escrow data, following the [Escrow] pack layout
+const TEMP_TOKEN_ACCOUNT_OFFSET: usize = 33;
+const RECEIVE_ACCOUNT_OFFSET: usize = 65;
+const EXPECTED_AMOUNT_OFFSET: usize = 97;
+const UNLOCK_TIME_OFFSET: usize = 105;
+const TIME_OUT_OFFSET: usize = 113;
+const ALLOWED_TAKER_OFFSET: usize = 121;
+const NONCE_OFFSET: usize = 154;
+const FROZEN_OFFSET: usize = 162;
+
+/// `data` if it is initialized escrow account data, the getters below read single fields from it
+fn initialized_data(data: &[u8]) -> Result<&[u8; Escrow::LEN], ProgramError> {
+    if data.len() != Escrow::LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if data[0] != 1 {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(array_ref![data, 0, Escrow::LEN])
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ProgramError> {
+    let data = initialized_data(data)?;
+    Ok(u64::from_le_bytes(*array_ref![data, offset, 8]))
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Result<Pubkey, ProgramError> {
+    let data = initialized_data(data)?;
+    Ok(Pubkey::new_from_array(*array_ref![data, offset, 32]))
+}
+
+/// Characters of the initializer's base58 address [summarize] keeps
+const SUMMARY_KEY_PREFIX: usize = 8;
+
+/// One log line describing an escrow, e.g. `escrow of 4uQeVj5t.. for 1000 nonce 2 [initialized,
+/// frozen]`. Only meant for debug logging, formatting costs compute.
+pub fn summarize(escrow: &Escrow) -> String {
+    let initializer = escrow.initializer_pubkey.to_string();
+    let flags: Vec<&str> = [
+        (escrow.is_initialized, "initialized"),
+        (escrow.allowed_taker.is_some(), "allowlisted"),
+        (escrow.frozen, "frozen"),
+        (escrow.in_progress, "in_progress"),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, name)| *name)
+    .collect();
+    format!(
+        "escrow of {}.. for {} nonce {} [{}]",
+        &initializer[..SUMMARY_KEY_PREFIX.min(initializer.len())],
+        escrow.expected_amount,
+        escrow.nonce,
+        flags.join(", ")
+    )
+}
+
+/// The amount of the other token the escrow still asks for, for clients rendering "X of Y
+/// remaining", 0 for an uninitialized escrow. An exchange fills the whole escrow at once, so the
+/// escrow doesn't track partial fills. A [Escrow::split_off] already leaves only the remainder in
+/// `expected_amount`.
+pub fn fillable(state: &Escrow) -> u64 {
+    if !state.is_initialized {
+        return 0;
+    }
+    state.expected_amount
+}
+
+/// Reads the initializer straight from escrow account data, without unpacking the whole escrow
+pub fn initializer(data: &[u8]) -> Result<Pubkey, ProgramError> {
+    read_pubkey(data, 1)
+}
+
+/// Reads the temp token account straight from escrow account data
+pub fn read_temp_token_account(data: &[u8]) -> Result<Pubkey, ProgramError> {
+    read_pubkey(data, TEMP_TOKEN_ACCOUNT_OFFSET)
+}
+
+/// Reads the initializer's receive account straight from escrow account data
+pub fn read_receive_account(data: &[u8]) -> Result<Pubkey, ProgramError> {
+    read_pubkey(data, RECEIVE_ACCOUNT_OFFSET)
+}
+
+/// Reads the expected amount straight from escrow account data
+pub fn read_expected_amount(data: &[u8]) -> Result<u64, ProgramError> {
+    read_u64(data, EXPECTED_AMOUNT_OFFSET)
+}
+
+/// Reads the unlock time straight from escrow account data
+pub fn read_unlock_time(data: &[u8]) -> Result<u64, ProgramError> {
+    read_u64(data, UNLOCK_TIME_OFFSET)
+}
+
+/// Reads the time out straight from escrow account data
+pub fn read_time_out(data: &[u8]) -> Result<u64, ProgramError> {
+    read_u64(data, TIME_OUT_OFFSET)
+}
+
+/// Reads the allowed taker straight from escrow account data
+pub fn read_allowed_taker(data: &[u8]) -> Result<Option<Pubkey>, ProgramError> {
+    let data = initialized_data(data)?;
+    match data[ALLOWED_TAKER_OFFSET] {
+        0 => Ok(None),
+        1 => Ok(Some(Pubkey::new_from_array(*array_ref![
+            data,
+            ALLOWED_TAKER_OFFSET + 1,
+            32
+        ]))),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Reads the nonce straight from escrow account data
+pub fn read_nonce(data: &[u8]) -> Result<u64, ProgramError> {
+    read_u64(data, NONCE_OFFSET)
+}
+
+/// Reads the frozen flag straight from escrow account data
+pub fn read_frozen(data: &[u8]) -> Result<bool, ProgramError> {
+    match initialized_data(data)?[FROZEN_OFFSET] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Offset of `in_progress` in packed escrow data, it is the last byte
+const IN_PROGRESS_OFFSET: usize = Escrow::LEN - 1;
+
+/// Writes only the `in_progress` byte, exchange changes nothing else before closing the escrow.
+///
+/// This is the only field-level write. Exchange fills an escrow whole, so there is no
+/// `remaining_amount` to write and `expected_amount` never changes in place. `in_progress` is the
+/// one field exchange updates, so it is the write that saves reserializing the whole escrow.
+pub fn pack_in_progress(data: &mut [u8], in_progress: bool) -> Result<(), ProgramError> {
+    if data.len() != Escrow::LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    data[IN_PROGRESS_OFFSET] = in_progress as u8;
+    Ok(())
 }
 
 impl Sealed for Escrow {}
@@ -24,7 +265,7 @@ impl IsInitialized for Escrow {
 }
 
 impl Pack for Escrow {
-    const LEN: usize = 105 + 8 + 8;
+    const LEN: usize = 105 + 8 + 8 + 33 + 8 + 1 + 1;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, Escrow::LEN];
         let (
@@ -35,12 +276,32 @@ impl Pack for Escrow {
             expected_amount,
             unlock_time,
             time_out,
-        ) = array_refs![src, 1, 32, 32, 32, 8, 8, 8];
+            allowed_taker_tag,
+            allowed_taker,
+            nonce,
+            frozen,
+            in_progress,
+        ) = array_refs![src, 1, 32, 32, 32, 8, 8, 8, 1, 32, 8, 1, 1];
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
             _ => return Err(ProgramError::InvalidAccountData),
         };
+        let allowed_taker = match allowed_taker_tag {
+            [0] => None,
+            [1] => Some(Pubkey::new_from_array(*allowed_taker)),
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let frozen = match frozen {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let in_progress = match in_progress {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
         Ok(Escrow {
             is_initialized,
             initializer_pubkey: Pubkey::new_from_array(*initializer_pubkey),
@@ -48,9 +309,13 @@ impl Pack for Escrow {
             initializer_token_to_receive_account_pubkey: Pubkey::new_from_array(
                 *initializer_token_to_receive_account_pubkey,
             ),
-            expected_amount: u64::from_be_bytes(*expected_amount),
+            expected_amount: u64::from_le_bytes(*expected_amount),
             unlock_time: u64::from_le_bytes(*unlock_time),
-            time_out: u64::from_be_bytes(*time_out),
+            time_out: u64::from_le_bytes(*time_out),
+            allowed_taker,
+            nonce: u64::from_le_bytes(*nonce),
+            frozen,
+            in_progress,
         })
     }
 
@@ -64,7 +329,12 @@ impl Pack for Escrow {
             expected_amount_dst,
             unlock_time_dst,
             time_out_dst,
-        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 8, 8];
+            allowed_taker_tag_dst,
+            allowed_taker_dst,
+            nonce_dst,
+            frozen_dst,
+            in_progress_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 8, 8, 8, 1, 32, 8, 1, 1];
 
         let Escrow {
             is_initialized,
@@ -74,6 +344,10 @@ impl Pack for Escrow {
             expected_amount,
             unlock_time,
             time_out,
+            allowed_taker,
+            nonce,
+            frozen,
+            in_progress,
         } = self;
 
         is_initialized_dst[0] = *is_initialized as u8;
@@ -82,7 +356,432 @@ impl Pack for Escrow {
         initializer_token_to_receive_account_pubkey_dst
             .copy_from_slice(initializer_token_to_receive_account_pubkey.as_ref());
         *expected_amount_dst = expected_amount.to_le_bytes();
-        *unlock_time_dst = unlock_time.to_be_bytes();
+        *unlock_time_dst = unlock_time.to_le_bytes();
         *time_out_dst = time_out.to_le_bytes();
+        match allowed_taker {
+            Some(allowed_taker) => {
+                allowed_taker_tag_dst[0] = 1;
+                allowed_taker_dst.copy_from_slice(allowed_taker.as_ref());
+            }
+            None => {
+                allowed_taker_tag_dst[0] = 0;
+                *allowed_taker_dst = [0; 32];
+            }
+        }
+        *nonce_dst = nonce.to_le_bytes();
+        frozen_dst[0] = *frozen as u8;
+        in_progress_dst[0] = *in_progress as u8;
+    }
+}
+
+pub struct Config {
+    pub is_initialized: bool,
+    pub admin: Pubkey,
+    pub paused: bool,
+    /// How far a taker's amount may be from the escrowed amount, 0 requires an exact match
+    pub amount_tolerance: u64,
+}
+
+impl Sealed for Config {}
+
+impl IsInitialized for Config {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Config {
+    const LEN: usize = 1 + 32 + 1 + 8;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Config::LEN];
+        let (is_initialized, admin, paused, amount_tolerance) = array_refs![src, 1, 32, 1, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let paused = match paused {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(Config {
+            is_initialized,
+            admin: Pubkey::new_from_array(*admin),
+            paused,
+            amount_tolerance: u64::from_le_bytes(*amount_tolerance),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Config::LEN];
+        let (is_initialized_dst, admin_dst, paused_dst, amount_tolerance_dst) =
+            mut_array_refs![dst, 1, 32, 1, 8];
+
+        let Config {
+            is_initialized,
+            admin,
+            paused,
+            amount_tolerance,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        admin_dst.copy_from_slice(admin.as_ref());
+        paused_dst[0] = *paused as u8;
+        *amount_tolerance_dst = amount_tolerance.to_le_bytes();
+    }
+}
+
+/// How many escrows an initializer may have open at once
+pub const MAX_OPEN_ESCROWS: u64 = 10;
+
+/// The per-initializer PDA counting their open escrows, to rate-limit escrow creation
+pub struct InitializerStats {
+    pub is_initialized: bool,
+    /// Incremented when an escrow is created, decremented when one is cancelled or exchanged
+    pub escrows_created: u64,
+}
+
+impl InitializerStats {
+    /// Counts a new escrow, failing with [EscrowError::TooManyEscrows] once [MAX_OPEN_ESCROWS]
+    /// are open
+    pub fn open_escrow(&mut self) -> Result<(), EscrowError> {
+        if self.escrows_created >= MAX_OPEN_ESCROWS {
+            return Err(EscrowError::TooManyEscrows);
+        }
+        self.escrows_created = math::add(self.escrows_created, 1)?;
+        Ok(())
+    }
+
+    /// Counts a closed escrow. Escrows opened before the counter existed were never counted, so
+    /// this stops at 0 instead of failing.
+    pub fn close_escrow(&mut self) {
+        self.escrows_created = self.escrows_created.saturating_sub(1);
+    }
+}
+
+impl Sealed for InitializerStats {}
+
+impl IsInitialized for InitializerStats {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for InitializerStats {
+    const LEN: usize = 1 + 8;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, InitializerStats::LEN];
+        let (is_initialized, escrows_created) = array_refs![src, 1, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        Ok(InitializerStats {
+            is_initialized,
+            escrows_created: u64::from_le_bytes(*escrows_created),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, InitializerStats::LEN];
+        let (is_initialized_dst, escrows_created_dst) = mut_array_refs![dst, 1, 8];
+        is_initialized_dst[0] = self.is_initialized as u8;
+        *escrows_created_dst = self.escrows_created.to_le_bytes();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_allowed_taker() {
+        let allowed_taker = Pubkey::new_unique();
+        let mut escrow = Escrow::unpack_unchecked(&[0; Escrow::LEN]).unwrap();
+        assert!(escrow.can_be_taken_by(&Pubkey::new_unique()));
+
+        escrow.allowed_taker = Some(allowed_taker);
+        assert!(escrow.can_be_taken_by(&allowed_taker));
+        assert!(!escrow.can_be_taken_by(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_check_taker() {
+        let allowed_taker = Pubkey::new_unique();
+        let mut escrow = crate::test_utils::sample_escrow(Pubkey::new_unique(), 1_000);
+        escrow.allowed_taker = Some(allowed_taker);
+        // the time lock fields play no part
+        escrow.unlock_time = u64::MAX;
+        escrow.time_out = 1;
+        assert!(escrow.check_taker(&allowed_taker).is_ok());
+        assert!(matches!(
+            escrow.check_taker(&Pubkey::new_unique()),
+            Err(EscrowError::Unauthorized)
+        ));
+
+        escrow.frozen = true;
+        assert!(matches!(
+            escrow.check_taker(&allowed_taker),
+            Err(EscrowError::Frozen)
+        ));
+    }
+
+    #[test]
+    fn test_initializer() {
+        let initializer_pubkey = Pubkey::new_unique();
+        let mut data = [0; Escrow::LEN];
+        assert_eq!(initializer(&data), Err(ProgramError::UninitializedAccount));
+
+        let mut escrow = Escrow::unpack_unchecked(&data).unwrap();
+        escrow.is_initialized = true;
+        escrow.initializer_pubkey = initializer_pubkey;
+        escrow.temp_token_account_pubkey = Pubkey::new_unique();
+        Escrow::pack(escrow, &mut data).unwrap();
+
+        assert_eq!(initializer(&data), Ok(initializer_pubkey));
+        assert_eq!(
+            initializer(&data[..32]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_field_getters_match_unpack() {
+        let mut escrow = crate::test_utils::sample_escrow(Pubkey::new_unique(), 1_000);
+        escrow.unlock_time = 100;
+        escrow.time_out = 200;
+        escrow.allowed_taker = Some(Pubkey::new_unique());
+        escrow.nonce = 7;
+        escrow.frozen = true;
+        let mut data = [0; Escrow::LEN];
+        Escrow::pack(escrow, &mut data).unwrap();
+        let escrow = Escrow::unpack(&data).unwrap();
+
+        assert_eq!(initializer(&data), Ok(escrow.initializer_pubkey));
+        assert_eq!(
+            read_temp_token_account(&data),
+            Ok(escrow.temp_token_account_pubkey)
+        );
+        assert_eq!(
+            read_receive_account(&data),
+            Ok(escrow.initializer_token_to_receive_account_pubkey)
+        );
+        assert_eq!(read_expected_amount(&data), Ok(escrow.expected_amount));
+        assert_eq!(read_unlock_time(&data), Ok(escrow.unlock_time));
+        assert_eq!(read_time_out(&data), Ok(escrow.time_out));
+        assert_eq!(read_allowed_taker(&data), Ok(escrow.allowed_taker));
+        assert_eq!(read_nonce(&data), Ok(escrow.nonce));
+        assert_eq!(read_frozen(&data), Ok(escrow.frozen));
+
+        let mut escrow = Escrow::unpack(&data).unwrap();
+        escrow.allowed_taker = None;
+        escrow.frozen = false;
+        Escrow::pack(escrow, &mut data).unwrap();
+        assert_eq!(read_allowed_taker(&data), Ok(None));
+        assert_eq!(read_frozen(&data), Ok(false));
+
+        assert_eq!(
+            read_expected_amount(&data[..100]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            read_nonce(&[0; Escrow::LEN]),
+            Err(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn test_summarize() {
+        let initializer = Pubkey::new_unique();
+        let mut escrow = crate::test_utils::sample_escrow(initializer, 1_000);
+        escrow.frozen = true;
+
+        let summary = summarize(&escrow);
+        assert!(summary.contains("1000"));
+        assert!(summary.contains(&initializer.to_string()[..SUMMARY_KEY_PREFIX]));
+        assert!(summary.ends_with("[initialized, frozen]"));
+        assert!(!summary.contains('\n'));
+    }
+
+    #[test]
+    fn test_fillable() {
+        let mut escrow = crate::test_utils::sample_escrow(Pubkey::new_unique(), 100);
+        assert_eq!(fillable(&escrow), 100);
+
+        // the split off escrow takes 30 of the 100, the original can only be filled for the rest
+        escrow.split_off(3, 10, Pubkey::new_unique()).unwrap();
+        assert_eq!(fillable(&escrow), 70);
+
+        escrow.is_initialized = false;
+        assert_eq!(fillable(&escrow), 0);
+    }
+
+    #[test]
+    fn test_can_be_taken_at() {
+        let allowed_taker = Pubkey::new_unique();
+        let mut escrow = Escrow::unpack_unchecked(&[0; Escrow::LEN]).unwrap();
+        escrow.allowed_taker = Some(allowed_taker);
+        escrow.unlock_time = 100;
+        escrow.time_out = 200;
+
+        assert!(escrow.can_be_taken_at(&allowed_taker, 150));
+        assert!(!escrow.can_be_taken_at(&Pubkey::new_unique(), 150));
+        assert!(!escrow.can_be_taken_at(&allowed_taker, 99));
+        assert!(!escrow.can_be_taken_at(&allowed_taker, 201));
+    }
+
+    #[test]
+    fn test_pack_in_progress_only_touches_its_byte() {
+        let mut escrow = Escrow::unpack_unchecked(&[0; Escrow::LEN]).unwrap();
+        escrow.is_initialized = true;
+        escrow.initializer_pubkey = Pubkey::new_unique();
+        escrow.allowed_taker = Some(Pubkey::new_unique());
+        escrow.expected_amount = u64::MAX;
+        let mut data = [0; Escrow::LEN];
+        Escrow::pack(escrow, &mut data).unwrap();
+        let before = data;
+
+        pack_in_progress(&mut data, true).unwrap();
+        assert!(Escrow::unpack(&data).unwrap().in_progress);
+        assert_eq!(data[..Escrow::LEN - 1], before[..Escrow::LEN - 1]);
+        assert_eq!(
+            pack_in_progress(&mut data[1..], true),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_reentrant_exchange_is_rejected() {
+        let mut escrow = Escrow::unpack_unchecked(&[0; Escrow::LEN]).unwrap();
+        assert!(escrow.begin_exchange().is_ok());
+
+        let mut data = [0; Escrow::LEN];
+        Escrow::pack_into_slice(&escrow, &mut data);
+        let mut reentered = Escrow::unpack_unchecked(&data).unwrap();
+        assert!(reentered.in_progress);
+        assert!(matches!(
+            reentered.begin_exchange(),
+            Err(EscrowError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_nonce_survives_pack() {
+        let mut escrow = Escrow::unpack_unchecked(&[0; Escrow::LEN]).unwrap();
+        assert!(escrow.check_nonce(0).is_ok());
+
+        escrow.bump_nonce();
+        let mut data = [0; Escrow::LEN];
+        Escrow::pack_into_slice(&escrow, &mut data);
+        let escrow = Escrow::unpack_unchecked(&data).unwrap();
+        assert_eq!(escrow.nonce, 1);
+        assert!(matches!(
+            escrow.check_nonce(0),
+            Err(EscrowError::InvalidInstruction)
+        ));
+    }
+
+    #[test]
+    fn test_amounts_round_trip() {
+        let mut escrow = Escrow::unpack_unchecked(&[0; Escrow::LEN]).unwrap();
+        escrow.expected_amount = 1_000;
+        escrow.unlock_time = 100;
+        escrow.time_out = 200;
+        let mut data = [0; Escrow::LEN];
+        Escrow::pack_into_slice(&escrow, &mut data);
+
+        let escrow = Escrow::unpack_unchecked(&data).unwrap();
+        assert_eq!(escrow.expected_amount, 1_000);
+        assert_eq!(escrow.unlock_time, 100);
+        assert_eq!(escrow.time_out, 200);
+    }
+
+    #[test]
+    fn test_absorb() {
+        let initializer = Pubkey::new_unique();
+        let mut a = crate::test_utils::sample_escrow(initializer, 10);
+        let mut b = crate::test_utils::sample_escrow(initializer, 15);
+        b.initializer_token_to_receive_account_pubkey =
+            a.initializer_token_to_receive_account_pubkey;
+
+        a.absorb(&b).unwrap();
+        assert_eq!(a.expected_amount, 25);
+        assert_eq!(a.nonce, 1);
+
+        let other_pair = crate::test_utils::sample_escrow(initializer, 5);
+        assert_eq!(a.absorb(&other_pair), Err(ProgramError::InvalidAccountData));
+        b.expected_amount = u64::MAX;
+        assert_eq!(a.absorb(&b), Err(EscrowError::AmountOverflow.into()));
+    }
+
+    #[test]
+    fn test_split_off() {
+        let initializer = Pubkey::new_unique();
+        let temp_token_account = Pubkey::new_unique();
+        let mut a = crate::test_utils::sample_escrow(initializer, 100);
+
+        let b = a.split_off(3, 10, temp_token_account).unwrap();
+        assert_eq!((a.expected_amount, b.expected_amount), (70, 30));
+        assert_eq!(a.nonce, 1);
+        assert_eq!(b.initializer_pubkey, initializer);
+        assert_eq!(b.temp_token_account_pubkey, temp_token_account);
+        assert_eq!(
+            b.initializer_token_to_receive_account_pubkey,
+            a.initializer_token_to_receive_account_pubkey
+        );
+
+        for tokens in [0, 10, 11] {
+            assert_eq!(
+                a.split_off(tokens, 10, temp_token_account).err(),
+                Some(ProgramError::InvalidArgument)
+            );
+        }
+        // 1 of 1_000 tokens is worth less than one unit of the 70 expected
+        assert_eq!(
+            a.split_off(1, 1_000, temp_token_account).err(),
+            Some(ProgramError::InvalidArgument)
+        );
+        assert_eq!(a.expected_amount, 70);
+    }
+
+    #[test]
+    fn test_escrow_len() {
+        // the allowed taker slot is always reserved, so Escrow::LEN is enough for any escrow
+        let mut data = vec![0; Escrow::LEN];
+        assert!(Escrow::unpack_unchecked(&data).is_ok());
+
+        // the longest escrow, with an allowed taker, fits as well
+        let taker = Pubkey::new_unique();
+        let mut escrow = crate::test_utils::sample_escrow(Pubkey::new_unique(), 10);
+        escrow.allowed_taker = Some(taker);
+        Escrow::pack(escrow, &mut data).unwrap();
+        assert_eq!(Escrow::unpack(&data).unwrap().allowed_taker, Some(taker));
+    }
+
+    #[test]
+    fn test_open_escrow_limit() {
+        let mut data = [0; InitializerStats::LEN];
+        let mut stats = InitializerStats::unpack_unchecked(&data).unwrap();
+        stats.is_initialized = true;
+
+        for _ in 0..MAX_OPEN_ESCROWS {
+            stats.open_escrow().unwrap();
+        }
+        assert!(matches!(
+            stats.open_escrow(),
+            Err(EscrowError::TooManyEscrows)
+        ));
+
+        stats.close_escrow();
+        stats.open_escrow().unwrap();
+        InitializerStats::pack(stats, &mut data).unwrap();
+        let mut stats = InitializerStats::unpack(&data).unwrap();
+        assert_eq!(stats.escrows_created, MAX_OPEN_ESCROWS);
+
+        stats.escrows_created = 0;
+        stats.close_escrow();
+        assert_eq!(stats.escrows_created, 0);
     }
 }
@@ -0,0 +1,55 @@
+use crate::state::Escrow;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+
+/// An initialized escrow of `initializer` asking for `amount`, the other accounts are random keys
+pub fn sample_escrow(initializer: Pubkey, amount: u64) -> Escrow {
+    Escrow {
+        is_initialized: true,
+        initializer_pubkey: initializer,
+        temp_token_account_pubkey: Pubkey::new_unique(),
+        initializer_token_to_receive_account_pubkey: Pubkey::new_unique(),
+        expected_amount: amount,
+        unlock_time: 0,
+        time_out: 0,
+        allowed_taker: None,
+        nonce: 0,
+        frozen: false,
+        in_progress: false,
+    }
+}
+
+/// [sample_escrow] packed into escrow account data
+pub fn packed_sample(initializer: Pubkey, amount: u64) -> Vec<u8> {
+    let mut data = vec![0; Escrow::LEN];
+    Escrow::pack(sample_escrow(initializer, amount), &mut data).unwrap();
+    data
+}
+
+/// The data of an upgradeable program's ProgramData account with `authority`, without the
+/// program bytes that follow it on chain
+pub fn program_data(authority: Option<Pubkey>) -> Vec<u8> {
+    let mut data = [3u32.to_le_bytes().as_slice(), &[0; 8]].concat();
+    match authority {
+        Some(authority) => {
+            data.push(1);
+            data.extend_from_slice(authority.as_ref());
+        }
+        None => data.push(0),
+    }
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packed_sample_unpacks() {
+        let initializer = Pubkey::new_unique();
+        let escrow = Escrow::unpack(&packed_sample(initializer, 1_000)).unwrap();
+
+        assert_eq!(escrow.initializer_pubkey, initializer);
+        assert_eq!(escrow.allowed_taker, None);
+        assert!(!escrow.in_progress);
+    }
+}
@@ -0,0 +1,482 @@
+use crate::{
+    error::EscrowError,
+    instruction::{
+        find_config_address, find_initializer_stats_address, find_program_data_address,
+        is_escrow_authority,
+    },
+    state::{Config, InitializerStats},
+};
+use solana_program::{
+    account_info::AccountInfo, bpf_loader_upgradeable, entrypoint::ProgramResult, msg,
+    program_error::ProgramError, program_pack::Pack, pubkey::Pubkey, rent::Rent, sysvar::Sysvar,
+};
+
+/// Like [solana_program::account_info::next_account_info], but logs the name of the missing account
+pub fn next_named<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    iter: &mut I,
+    name: &str,
+) -> Result<I::Item, ProgramError> {
+    iter.next().ok_or_else(|| {
+        msg!("missing account: {}", name);
+        ProgramError::NotEnoughAccountKeys
+    })
+}
+
+/// Fails with [EscrowError::InvalidInstruction] unless the client marked the account writable,
+/// logging its name instead of letting a later write or CPI fail obscurely
+pub fn assert_writable(account: &AccountInfo, name: &str) -> ProgramResult {
+    if !account.is_writable {
+        msg!("account must be writable: {}", name);
+        return Err(EscrowError::InvalidInstruction.into());
+    }
+    Ok(())
+}
+
+/// The seeds the escrow authority PDA signs with, `bump` being the bump seed found for `b"escrow"`
+pub fn escrow_signer_seeds(bump: &[u8; 1]) -> [&[u8]; 2] {
+    [b"escrow", bump]
+}
+
+/// Renders a raw token amount with `decimals` decimals, trailing zeros of the fraction dropped
+pub fn format_amount(raw: u64, decimals: u8) -> String {
+    if decimals == 0 {
+        return raw.to_string();
+    }
+    let digits = format!("{:0>width$}", raw, width = decimals as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+    match fraction.trim_end_matches('0') {
+        "" => whole.to_string(),
+        fraction => format!("{}.{}", whole, fraction),
+    }
+}
+
+/// Reads the amount held by an SPL token account
+pub fn token_amount(account: &AccountInfo) -> Result<u64, ProgramError> {
+    let token_account =
+        spl_token::state::Account::unpack(&account.try_borrow_data()?).map_err(|_| {
+            msg!("Account {} is not a token account", account.key);
+            ProgramError::InvalidAccountData
+        })?;
+    Ok(token_account.amount)
+}
+
+/// Fails with [EscrowError::Paused] while the admin has paused the program
+pub fn assert_not_paused(program_id: &Pubkey, config_account: &AccountInfo) -> ProgramResult {
+    if *config_account.key != find_config_address(program_id).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if config_account.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+    if Config::unpack(&config_account.try_borrow_data()?)?.paused {
+        return Err(EscrowError::Paused.into());
+    }
+    Ok(())
+}
+
+/// Fails with [EscrowError::InvalidInstruction] when the escrow account is the PDA that owns every
+/// temp token account, no escrow can live at that address
+pub fn assert_not_escrow_authority(
+    program_id: &Pubkey,
+    escrow_account: &AccountInfo,
+) -> ProgramResult {
+    if is_escrow_authority(program_id, escrow_account.key) {
+        msg!("escrow account is the escrow authority PDA");
+        return Err(EscrowError::InvalidInstruction.into());
+    }
+    Ok(())
+}
+
+/// Fails with [ProgramError::IncorrectProgramId] unless the account is the SPL token program, the
+/// escrow authority PDA must never sign a CPI into any other program
+pub fn assert_token_program(token_program: &AccountInfo) -> ProgramResult {
+    if *token_program.key != spl_token::id() {
+        msg!("token_program is not the SPL token program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Unpacks `initializer`'s [InitializerStats], checking the account is their writable stats PDA
+pub fn initializer_stats(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    stats_account: &AccountInfo,
+) -> Result<InitializerStats, ProgramError> {
+    if *stats_account.key != find_initializer_stats_address(program_id, initializer).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if stats_account.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+    assert_writable(stats_account, "initializer_stats")?;
+    InitializerStats::unpack(&stats_account.try_borrow_data()?)
+}
+
+/// Unpacks the [Config], checking the account is the writable config PDA and `admin` is its signing admin
+pub fn admin_config(
+    program_id: &Pubkey,
+    admin: &AccountInfo,
+    config_account: &AccountInfo,
+) -> Result<Config, ProgramError> {
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if config_account.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+    if *config_account.key != find_config_address(program_id).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let config = Config::unpack(&config_account.try_borrow_data()?)?;
+    if config.admin != *admin.key {
+        return Err(EscrowError::Unauthorized.into());
+    }
+    Ok(config)
+}
+
+/// The upgrade authority stored in the program's ProgramData account, `None` once the program is
+/// immutable. The account starts with the loader's `ProgramData` tag (3, as a u32), the slot of
+/// the last deploy (a u64) and the authority as an option.
+pub fn upgrade_authority(
+    program_id: &Pubkey,
+    program_data: &AccountInfo,
+) -> Result<Option<Pubkey>, ProgramError> {
+    if *program_data.key != find_program_data_address(program_id) {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *program_data.owner != bpf_loader_upgradeable::id() {
+        return Err(ProgramError::IllegalOwner);
+    }
+    let data = program_data.try_borrow_data()?;
+    if data.get(..4) != Some(&3u32.to_le_bytes()[..]) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    match data.get(12) {
+        Some(0) => Ok(None),
+        Some(1) => data
+            .get(13..13 + 32)
+            .map(|key| Some(Pubkey::new_from_array(key.try_into().unwrap())))
+            .ok_or(ProgramError::InvalidAccountData),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Fails with [EscrowError::NotRentExempt] unless the account holds enough lamports to be rent exempt
+pub fn assert_rent_exempt(account: &AccountInfo) -> ProgramResult {
+    check_rent_exempt(&Rent::get()?, account)
+}
+
+fn check_rent_exempt(rent: &Rent, account: &AccountInfo) -> ProgramResult {
+    if !rent.is_exempt(account.lamports(), account.data_len()) {
+        return Err(EscrowError::NotRentExempt.into());
+    }
+    Ok(())
+}
+
+/// Records every `msg!` of the test binary, syscall stubs are global so all tests share the one list.
+/// Also answers `Rent::get` with the default rent, keeps the last return data and records every
+/// CPI, which does nothing else off-chain.
+#[cfg(test)]
+pub(crate) mod log_recorder {
+    use solana_program::{
+        account_info::AccountInfo,
+        clock::Clock,
+        entrypoint::{ProgramResult, SUCCESS},
+        instruction::Instruction,
+        program_stubs::{set_syscall_stubs, SyscallStubs},
+        rent::Rent,
+    };
+    use std::sync::Mutex;
+
+    static LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static RETURN_DATA: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+    static INVOKED: Mutex<Vec<Instruction>> = Mutex::new(Vec::new());
+
+    struct LogRecorder;
+
+    impl SyscallStubs for LogRecorder {
+        fn sol_log(&self, message: &str) {
+            LOGS.lock().unwrap().push(message.to_string());
+        }
+
+        fn sol_set_return_data(&self, data: &[u8]) {
+            *RETURN_DATA.lock().unwrap() = data.to_vec();
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            SUCCESS
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Clock) = Clock::default() };
+            SUCCESS
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            _account_infos: &[AccountInfo],
+            _signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            INVOKED.lock().unwrap().push(instruction.clone());
+            Ok(())
+        }
+    }
+
+    pub fn install() {
+        set_syscall_stubs(Box::new(LogRecorder));
+    }
+
+    pub fn logged(line: &str) -> bool {
+        LOGS.lock().unwrap().iter().any(|log| log == line)
+    }
+
+    pub fn returned() -> Vec<u8> {
+        RETURN_DATA.lock().unwrap().clone()
+    }
+
+    pub fn invoked() -> Vec<Instruction> {
+        INVOKED.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_escrow_signer_seeds_regenerate_pda() {
+        let program_id = Pubkey::new_unique();
+        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+
+        let bump = [bump_seed];
+        let seeds = escrow_signer_seeds(&bump);
+        assert_eq!(
+            Pubkey::create_program_address(&seeds, &program_id).unwrap(),
+            pda
+        );
+    }
+
+    #[test]
+    fn test_check_rent_exempt() {
+        let rent = Rent::default();
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut data = vec![0; 105];
+        let mut lamports = rent.minimum_balance(data.len());
+        let account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert!(check_rent_exempt(&rent, &account).is_ok());
+
+        **account.try_borrow_mut_lamports().unwrap() -= 1;
+        assert_eq!(
+            check_rent_exempt(&rent, &account),
+            Err(EscrowError::NotRentExempt.into())
+        );
+    }
+
+    #[test]
+    fn test_assert_not_paused() {
+        let program_id = Pubkey::new_unique();
+        let (config_key, _) = find_config_address(&program_id);
+        let mut data = vec![0; Config::LEN];
+        let mut lamports = 0;
+        let config = Config {
+            is_initialized: true,
+            admin: Pubkey::new_unique(),
+            paused: true,
+            amount_tolerance: 0,
+        };
+        Config::pack(config, &mut data).unwrap();
+        let account = AccountInfo::new(
+            &config_key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+
+        assert_eq!(
+            assert_not_paused(&program_id, &account),
+            Err(EscrowError::Paused.into())
+        );
+
+        account.try_borrow_mut_data().unwrap()[1 + 32] = 0;
+        assert!(assert_not_paused(&program_id, &account).is_ok());
+    }
+
+    #[test]
+    fn test_token_amount() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(
+            spl_token::state::Account {
+                mint: Pubkey::new_unique(),
+                owner: Pubkey::new_unique(),
+                amount: 42,
+                state: spl_token::state::AccountState::Initialized,
+                ..spl_token::state::Account::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &spl_token::id(),
+            false,
+            0,
+        );
+        assert_eq!(token_amount(&account), Ok(42));
+
+        let mut lamports = 0;
+        let mut data = vec![0; 10];
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &spl_token::id(),
+            false,
+            0,
+        );
+        assert_eq!(
+            token_amount(&account),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_assert_writable() {
+        log_recorder::install();
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut [], &key, false, 0);
+
+        assert_eq!(
+            assert_writable(&account, "escrow_account"),
+            Err(EscrowError::InvalidInstruction.into())
+        );
+        assert!(log_recorder::logged(
+            "account must be writable: escrow_account"
+        ));
+    }
+
+    #[test]
+    fn test_assert_token_program() {
+        let (token_program, other) = (spl_token::id(), Pubkey::new_unique());
+        let (mut lamports_a, mut lamports_b) = (0, 0);
+        let token_program_info = AccountInfo::new(
+            &token_program,
+            false,
+            false,
+            &mut lamports_a,
+            &mut [],
+            &other,
+            true,
+            0,
+        );
+        let other_info = AccountInfo::new(
+            &other,
+            false,
+            false,
+            &mut lamports_b,
+            &mut [],
+            &other,
+            true,
+            0,
+        );
+
+        assert!(assert_token_program(&token_program_info).is_ok());
+        assert_eq!(
+            assert_token_program(&other_info),
+            Err(ProgramError::IncorrectProgramId)
+        );
+    }
+
+    #[test]
+    fn test_upgrade_authority() {
+        let program_id = Pubkey::new_unique();
+        let loader = bpf_loader_upgradeable::id();
+        let program_data_key = find_program_data_address(&program_id);
+        let authority = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = crate::test_utils::program_data(Some(authority));
+        let program_data = AccountInfo::new(
+            &program_data_key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &loader,
+            false,
+            0,
+        );
+
+        assert_eq!(
+            upgrade_authority(&program_id, &program_data),
+            Ok(Some(authority))
+        );
+        assert_eq!(
+            upgrade_authority(&Pubkey::new_unique(), &program_data),
+            Err(ProgramError::InvalidSeeds)
+        );
+
+        // an immutable program has no authority left
+        program_data.try_borrow_mut_data().unwrap()[12] = 0;
+        assert_eq!(upgrade_authority(&program_id, &program_data), Ok(None));
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(0, 0), "0");
+        assert_eq!(format_amount(1_500, 0), "1500");
+        assert_eq!(format_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_amount(2_000_000, 6), "2");
+        assert_eq!(format_amount(1, 6), "0.000001");
+        assert_eq!(format_amount(123_456_789, 6), "123.456789");
+    }
+
+    #[test]
+    fn test_next_named_logs_missing_account() {
+        log_recorder::install();
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let accounts = [AccountInfo::new(
+            &key,
+            true,
+            false,
+            &mut lamports,
+            &mut [],
+            &key,
+            false,
+            0,
+        )];
+        let account_info_iter = &mut accounts.iter();
+
+        assert!(next_named(account_info_iter, "initializer").is_ok());
+        assert_eq!(
+            next_named(account_info_iter, "escrow_account").err(),
+            Some(ProgramError::NotEnoughAccountKeys)
+        );
+        assert!(log_recorder::logged("missing account: escrow_account"));
+    }
+}
@@ -0,0 +1,89 @@
+//! Instruction data packers for browser clients, built with the `wasm` feature. They only go
+//! through [EscrowInstruction] packing, which has no `target_os = "solana"` dependency, so the
+//! crate compiles for `wasm32-unknown-unknown` without the entrypoint.
+
+use crate::instruction::EscrowInstruction;
+use solana_program::pubkey::Pubkey;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// The data of [crate::instruction::init_escrow] for an escrow anyone can take, `None` unless
+/// `offered_mint` is 32 bytes
+#[wasm_bindgen]
+pub fn pack_init_escrow(amount: u64, offered_mint: &[u8]) -> Option<Vec<u8>> {
+    let offered_mint = Pubkey::try_from(offered_mint).ok()?;
+    Some(
+        EscrowInstruction::InitEscrow {
+            amount,
+            offered_mint,
+            allowed_taker: None,
+        }
+        .pack(),
+    )
+}
+
+/// The data of [crate::instruction::exchange]
+#[wasm_bindgen]
+pub fn pack_exchange(amount: u64, nonce: u64) -> Vec<u8> {
+    EscrowInstruction::Exchange { amount, nonce }.pack()
+}
+
+/// The data of [crate::instruction::cancel]
+#[wasm_bindgen]
+pub fn pack_cancel(nonce: u64) -> Vec<u8> {
+    EscrowInstruction::Cancel { nonce }.pack()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instruction::{cancel, exchange, init_escrow, BuilderAmount};
+
+    #[cfg(feature = "strict-amounts")]
+    fn builder_amount(base_units: u64) -> BuilderAmount {
+        crate::instruction::Amount::from_base_units(base_units)
+    }
+    #[cfg(not(feature = "strict-amounts"))]
+    fn builder_amount(base_units: u64) -> BuilderAmount {
+        base_units
+    }
+
+    #[test]
+    fn test_wrappers_match_native_builders() {
+        let key = Pubkey::new_unique();
+
+        let native = init_escrow(
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            builder_amount(42),
+            None,
+        );
+        assert_eq!(
+            pack_init_escrow(42, key.as_ref()),
+            Some(native.unwrap().data)
+        );
+        assert_eq!(pack_init_escrow(42, &[0; 31]), None);
+
+        let native = exchange(
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            &key,
+            builder_amount(42),
+            7,
+        );
+        assert_eq!(pack_exchange(42, 7), native.unwrap().data);
+
+        let native = cancel(&key, &key, &key, &key, &key, &key, 7);
+        assert_eq!(pack_cancel(7), native.unwrap().data);
+    }
+}
@@ -0,0 +1,211 @@
+//! Fixed byte vectors for every [EscrowInstruction], the wire format clients in other languages
+//! must produce. A failing vector here is a breaking change of the instruction format.
+
+use escrow_program::instruction::{pack_many, EscrowInstruction};
+use solana_program::pubkey::Pubkey;
+
+const KEY: [u8; 32] = [7; 32];
+
+fn packed(instruction: EscrowInstruction) -> Vec<u8> {
+    pack_many(&[instruction]).remove(0)
+}
+
+fn with_key(prefix: &[u8]) -> Vec<u8> {
+    [prefix, &KEY].concat()
+}
+
+#[test]
+fn init_escrow() {
+    assert_eq!(
+        packed(EscrowInstruction::InitEscrow {
+            amount: 1,
+            offered_mint: Pubkey::new_from_array(KEY),
+            allowed_taker: None,
+        }),
+        with_key(&[0, 1, 0, 0, 0, 0, 0, 0, 0])
+    );
+    assert_eq!(
+        packed(EscrowInstruction::InitEscrow {
+            amount: u64::MAX,
+            offered_mint: Pubkey::new_from_array(KEY),
+            allowed_taker: None,
+        }),
+        with_key(&[0, 255, 255, 255, 255, 255, 255, 255, 255])
+    );
+    assert_eq!(
+        packed(EscrowInstruction::InitEscrow {
+            amount: 0x0102,
+            offered_mint: Pubkey::new_from_array([1; 32]),
+            allowed_taker: Some(Pubkey::new_from_array(KEY)),
+        }),
+        with_key(&[[0, 2, 1, 0, 0, 0, 0, 0, 0].as_slice(), &[1; 32]].concat())
+    );
+}
+
+#[test]
+fn exchange() {
+    assert_eq!(
+        packed(EscrowInstruction::Exchange {
+            amount: 1,
+            nonce: 0,
+        }),
+        [1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(
+        packed(EscrowInstruction::Exchange {
+            amount: u64::MAX,
+            nonce: u64::MAX,
+        }),
+        [1, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]
+    );
+}
+
+#[test]
+fn cancel() {
+    assert_eq!(
+        packed(EscrowInstruction::Cancel { nonce: 0 }),
+        [2, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(
+        packed(EscrowInstruction::Cancel { nonce: u64::MAX }),
+        [2, 255, 255, 255, 255, 255, 255, 255, 255]
+    );
+}
+
+#[test]
+fn reset_time_lock() {
+    assert_eq!(packed(EscrowInstruction::ResetTimeLock {}), [3]);
+}
+
+#[test]
+fn init_escrow_pda() {
+    assert_eq!(
+        packed(EscrowInstruction::InitEscrowPda {
+            amount: 1,
+            nonce: 2,
+            offered_mint: Pubkey::new_from_array(KEY),
+            allowed_taker: None,
+        }),
+        with_key(&[4, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0])
+    );
+    assert_eq!(
+        packed(EscrowInstruction::InitEscrowPda {
+            amount: u64::MAX,
+            nonce: 0,
+            offered_mint: Pubkey::new_from_array(KEY),
+            allowed_taker: None,
+        }),
+        with_key(&[4, 255, 255, 255, 255, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0])
+    );
+    assert_eq!(
+        packed(EscrowInstruction::InitEscrowPda {
+            amount: 1,
+            nonce: 2,
+            offered_mint: Pubkey::new_from_array([1; 32]),
+            allowed_taker: Some(Pubkey::new_from_array(KEY)),
+        }),
+        with_key(
+            &[
+                [4, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0].as_slice(),
+                &[1; 32]
+            ]
+            .concat()
+        )
+    );
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn dump_escrow() {
+    assert_eq!(packed(EscrowInstruction::DumpEscrow {}), [5]);
+}
+
+#[test]
+fn set_allowed_taker() {
+    assert_eq!(
+        packed(EscrowInstruction::SetAllowedTaker { taker: None }),
+        [6]
+    );
+    assert_eq!(
+        packed(EscrowInstruction::SetAllowedTaker {
+            taker: Some(Pubkey::new_from_array(KEY)),
+        }),
+        with_key(&[6])
+    );
+}
+
+#[test]
+fn tag_only_instructions() {
+    assert_eq!(packed(EscrowInstruction::CancelAll {}), [7]);
+    assert_eq!(packed(EscrowInstruction::InitConfig {}), [8]);
+    assert_eq!(packed(EscrowInstruction::HealthCheck {}), [12]);
+    assert_eq!(packed(EscrowInstruction::MergeEscrows {}), [13]);
+    assert_eq!(packed(EscrowInstruction::Freeze {}), [14]);
+    assert_eq!(packed(EscrowInstruction::Unfreeze {}), [15]);
+    assert_eq!(packed(EscrowInstruction::InitInitializerStats {}), [18]);
+    assert_eq!(packed(EscrowInstruction::GetProgramVersion {}), [19]);
+}
+
+#[test]
+fn set_paused() {
+    assert_eq!(
+        packed(EscrowInstruction::SetPaused { paused: false }),
+        [9, 0]
+    );
+    assert_eq!(
+        packed(EscrowInstruction::SetPaused { paused: true }),
+        [9, 1]
+    );
+}
+
+#[test]
+fn check_taker() {
+    assert_eq!(
+        packed(EscrowInstruction::CheckTaker {
+            taker: Pubkey::new_from_array(KEY),
+        }),
+        with_key(&[10])
+    );
+}
+
+#[test]
+fn set_receive_account() {
+    assert_eq!(
+        packed(EscrowInstruction::SetReceiveAccount {
+            new_account: Pubkey::new_from_array(KEY),
+        }),
+        with_key(&[11])
+    );
+}
+
+#[test]
+fn split_escrow() {
+    assert_eq!(
+        packed(EscrowInstruction::SplitEscrow { amount: 1 }),
+        [16, 1, 0, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(
+        packed(EscrowInstruction::SplitEscrow { amount: u64::MAX }),
+        [16, 255, 255, 255, 255, 255, 255, 255, 255]
+    );
+}
+
+#[test]
+fn set_amount_tolerance() {
+    assert_eq!(
+        packed(EscrowInstruction::SetAmountTolerance {
+            amount_tolerance: 2,
+        }),
+        [20, 2, 0, 0, 0, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn transfer_escrow() {
+    assert_eq!(
+        packed(EscrowInstruction::TransferEscrow {
+            new_initializer: Pubkey::new_from_array(KEY),
+        }),
+        with_key(&[17])
+    );
+}
@@ -17,6 +17,8 @@ pub enum EscrowError {
     InvalidUnlockTime,
     #[error("Cannot exchange after time out")]
     InvalidTimeOut,
+    #[error("Missing clock sysvar account")]
+    MissingClock,
 }
 
 impl From<EscrowError> for ProgramError {
@@ -11,9 +11,14 @@ pub enum EscrowInstruction {
     /// 3. `[writable]` The escrow account, it will hold all necessary info about the trade.
     /// 4. `[]` The rent sysvar
     /// 5. `[]` The token program
+    /// 6. `[]` The clock sysvar
     InitEscrow {
         /// The amount party A expects to receive of token Y
         amount: u64,
+        /// The slot after which the escrow can be taken, it must still be ahead of the clock
+        unlock_time: u64,
+        /// The last slot the escrow can be taken at, after it anyone can crank the refund
+        time_out: u64,
     },
     /// Accounts expected:
     ///
@@ -26,10 +31,28 @@ pub enum EscrowInstruction {
     /// 6. `[writable]` The escrow account holding the escrow info
     /// 7. `[]` The token program
     /// 8. `[]` The PDA account
+    /// 9. `[]` The clock sysvar
     Exchange {
         /// the amount the taker expects to be paid in the other token, as a u64 because that's the max possible supply of a token
         amount: u64,
     },
+    /// Refunds the initializer and closes an escrow after its time out, anyone can call it
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` The escrow account holding the escrow info
+    /// 1. `[writable]` The PDA's temp token account to get tokens from and eventually close
+    /// 2. `[writable]` The initializer's token account the tokens are refunded to
+    /// 3. `[writable]` The initializer's main account to send their rent fees to
+    /// 4. `[]` The token program
+    /// 5. `[]` The PDA account
+    /// 6. `[]` The clock sysvar
+    Crank {},
+    /// Returns the program's [crate::PROGRAM_VERSION] through the return data, as 4 little endian
+    /// bytes, so a client can confirm which build is deployed
+    ///
+    /// No accounts expected
+    GetProgramVersion {},
 }
 
 impl EscrowInstruction {
@@ -39,10 +62,14 @@ impl EscrowInstruction {
         Ok(match tag {
             0 => Self::InitEscrow {
                 amount: Self::unpack_amount(rest)?,
+                unlock_time: Self::unpack_amount(rest.get(8..).ok_or(InvalidInstruction)?)?,
+                time_out: Self::unpack_amount(rest.get(16..).ok_or(InvalidInstruction)?)?,
             },
             1 => Self::Exchange {
                 amount: Self::unpack_amount(rest)?,
             },
+            2 => Self::Crank {},
+            3 => Self::GetProgramVersion {},
             _ => return Err(InvalidInstruction.into()),
         })
     }
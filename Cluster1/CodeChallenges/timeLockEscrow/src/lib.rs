@@ -1,5 +1,24 @@
 pub mod entrypoint;
 pub mod error;
 pub mod instruction;
+pub mod math;
 pub mod processor;
 pub mod state;
+
+/// The crate version `major.minor.patch` this program was built from, as
+/// `major * 1_000_000 + minor * 1_000 + patch`, so clients can tell which build is deployed
+pub const PROGRAM_VERSION: u32 = number(env!("CARGO_PKG_VERSION_MAJOR")) * 1_000_000
+    + number(env!("CARGO_PKG_VERSION_MINOR")) * 1_000
+    + number(env!("CARGO_PKG_VERSION_PATCH"));
+
+/// Cargo hands out each version part as a string of decimal digits
+const fn number(digits: &str) -> u32 {
+    let bytes = digits.as_bytes();
+    let mut value = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0') as u32;
+        i += 1;
+    }
+    value
+}
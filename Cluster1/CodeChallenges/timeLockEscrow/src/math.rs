@@ -0,0 +1,49 @@
+use crate::error::EscrowError;
+
+/// `a + b`, failing with [EscrowError::AmountOverflow] instead of wrapping
+pub fn add(a: u64, b: u64) -> Result<u64, EscrowError> {
+    a.checked_add(b).ok_or(EscrowError::AmountOverflow)
+}
+
+/// `a - b`, failing with [EscrowError::AmountOverflow] instead of wrapping
+pub fn sub(a: u64, b: u64) -> Result<u64, EscrowError> {
+    a.checked_sub(b).ok_or(EscrowError::AmountOverflow)
+}
+
+/// `a * b`, failing with [EscrowError::AmountOverflow] instead of wrapping
+pub fn mul(a: u64, b: u64) -> Result<u64, EscrowError> {
+    a.checked_mul(b).ok_or(EscrowError::AmountOverflow)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add() {
+        assert_eq!(add(0, 0).unwrap(), 0);
+        assert_eq!(add(u64::MAX - 1, 1).unwrap(), u64::MAX);
+        assert!(matches!(add(u64::MAX, 1), Err(EscrowError::AmountOverflow)));
+    }
+
+    #[test]
+    fn test_sub() {
+        assert_eq!(sub(u64::MAX, u64::MAX).unwrap(), 0);
+        assert_eq!(sub(1, 0).unwrap(), 1);
+        assert!(matches!(sub(0, 1), Err(EscrowError::AmountOverflow)));
+    }
+
+    #[test]
+    fn test_mul() {
+        assert_eq!(mul(u64::MAX, 1).unwrap(), u64::MAX);
+        assert_eq!(mul(u64::MAX, 0).unwrap(), 0);
+        assert_eq!(
+            mul(1 << 32, (1 << 32) - 1).unwrap(),
+            u64::MAX - ((1 << 32) - 1)
+        );
+        assert!(matches!(
+            mul(1 << 32, 1 << 32),
+            Err(EscrowError::AmountOverflow)
+        ));
+    }
+}
@@ -1,15 +1,20 @@
-use crate::{error::EscrowError, instruction::EscrowInstruction, state::Escrow};
+use crate::{
+    error::EscrowError,
+    instruction::EscrowInstruction,
+    math,
+    state::{validate_window, Escrow},
+};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
     rent::Rent,
-    sysvar::Sysvar,
+    sysvar::{self, Sysvar},
 };
 use spl_token::state::Account as TokenAccount;
 
@@ -21,30 +26,45 @@ impl Processor {
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
+        if instruction_data.is_empty() {
+            return Err(EscrowError::InvalidInstruction.into());
+        }
         let instruction = EscrowInstruction::unpack(instruction_data)?;
 
         match instruction {
-            EscrowInstruction::InitEscrow { amount } => {
+            EscrowInstruction::InitEscrow {
+                amount,
+                unlock_time,
+                time_out,
+            } => {
                 msg!("Instruction: InitEscrow");
-                Self::process_init_escrow(accounts, amount, program_id)
+                Self::process_init_escrow(accounts, amount, unlock_time, time_out, program_id)
             }
             EscrowInstruction::Exchange { amount } => {
                 msg!("Instruction: Exchange");
                 Self::process_exchange(accounts, amount, program_id)
             }
+            EscrowInstruction::Crank {} => {
+                msg!("Instruction: Crank");
+                Self::process_crank(accounts, program_id)
+            }
+            EscrowInstruction::GetProgramVersion {} => {
+                msg!("Instruction: GetProgramVersion");
+                set_return_data(&crate::PROGRAM_VERSION.to_le_bytes());
+                Ok(())
+            }
         }
     }
 
     fn process_init_escrow(
         accounts: &[AccountInfo],
         amount: u64,
+        unlock_time: u64,
+        time_out: u64,
         program_id: &Pubkey,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let initializer = next_account_info(account_info_iter)?;
-        let slot = Clock::get()?.slot;
-        let unlock_time = slot.checked_add(100).unwrap();
-        let time_out = unlock_time.checked_add(1000).unwrap();
 
         if !initializer.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
@@ -59,6 +79,9 @@ impl Processor {
         if !rent.is_exempt(escrow_account.lamports(), escrow_account.data_len()) {
             return Err(EscrowError::NotRentExempt.into());
         }
+        let token_program = next_account_info(account_info_iter)?;
+        let slot = clock_sysvar(account_info_iter.next())?.slot;
+        validate_window(unlock_time, time_out, slot)?;
         let mut escrow_info = Escrow::unpack_unchecked(&escrow_account.try_borrow_data()?)?;
         if escrow_info.is_initialized() {
             return Err(ProgramError::AccountAlreadyInitialized);
@@ -72,7 +95,6 @@ impl Processor {
         escrow_info.time_out = time_out;
         Escrow::pack(escrow_info, &mut escrow_account.try_borrow_mut_data()?)?;
         let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
-        let token_program = next_account_info(account_info_iter)?;
         let owner_change_ix = spl_token::instruction::set_authority(
             token_program.key,
             temp_token_account.key,
@@ -100,7 +122,6 @@ impl Processor {
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let taker = next_account_info(account_info_iter)?;
-        let slot = Clock::get()?.slot;
 
         if !taker.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
@@ -124,6 +145,9 @@ impl Processor {
         let escrow_account = next_account_info(account_info_iter)?;
 
         let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+        let token_program = next_account_info(account_info_iter)?;
+        let pda_account = next_account_info(account_info_iter)?;
+        let slot = clock_sysvar(account_info_iter.next())?.slot;
 
         if !slot.gt(&escrow_info.unlock_time) {
             return Err(EscrowError::InvalidUnlockTime.into());
@@ -147,8 +171,6 @@ impl Processor {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        let token_program = next_account_info(account_info_iter)?;
-
         let transfer_to_initializer_ix = spl_token::instruction::transfer(
             token_program.key,
             takers_sending_token_account.key,
@@ -167,7 +189,6 @@ impl Processor {
                 token_program.clone(),
             ],
         )?;
-        let pda_account = next_account_info(account_info_iter)?;
         let transfer_to_taker_ix = spl_token::instruction::transfer(
             token_program.key,
             pdas_temp_token_account.key,
@@ -207,12 +228,574 @@ impl Processor {
             &[&[&b"escrow"[..], &[bump_seed]]],
         )?;
         msg!("Closing the escrow account...");
-        **initializers_main_account.lamports.borrow_mut() = initializers_main_account
-            .lamports()
-            .checked_add(escrow_account.lamports())
-            .ok_or(EscrowError::AmountOverflow)?;
+        **initializers_main_account.lamports.borrow_mut() = math::add(
+            initializers_main_account.lamports(),
+            escrow_account.lamports(),
+        )?;
+        **escrow_account.lamports.borrow_mut() = 0;
+        *escrow_account.try_borrow_mut_data()? = &mut [];
+        Ok(())
+    }
+    fn process_crank(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+        let escrow_info = Escrow::unpack(&escrow_account.try_borrow_data()?)?;
+
+        let pdas_temp_token_account = next_account_info(account_info_iter)?;
+        if escrow_info.temp_token_account_pubkey != *pdas_temp_token_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let pdas_temp_token_account_info =
+            TokenAccount::unpack(&pdas_temp_token_account.try_borrow_data()?)?;
+
+        let initializers_token_account = next_account_info(account_info_iter)?;
+        let initializers_token_account_info =
+            TokenAccount::unpack(&initializers_token_account.try_borrow_data()?)?;
+        if initializers_token_account_info.owner != escrow_info.initializer_pubkey
+            || initializers_token_account_info.mint != pdas_temp_token_account_info.mint
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let initializers_main_account = next_account_info(account_info_iter)?;
+        if escrow_info.initializer_pubkey != *initializers_main_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // anyone can crank, the PDA must not sign for a program or account of their choosing
+        let token_program = next_account_info(account_info_iter)?;
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let pda_account = next_account_info(account_info_iter)?;
+        let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+        if *pda_account.key != pda {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let slot = clock_sysvar(account_info_iter.next())?.slot;
+        if !slot.gt(&escrow_info.time_out) {
+            return Err(EscrowError::InvalidTimeOut.into());
+        }
+
+        let transfer_to_initializer_ix = spl_token::instruction::transfer(
+            token_program.key,
+            pdas_temp_token_account.key,
+            initializers_token_account.key,
+            &pda,
+            &[&pda],
+            pdas_temp_token_account_info.amount,
+        )?;
+        msg!("Calling the token program to refund the escrow's initializer...");
+        invoke_signed(
+            &transfer_to_initializer_ix,
+            &[
+                pdas_temp_token_account.clone(),
+                initializers_token_account.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[&b"escrow"[..], &[bump_seed]]],
+        )?;
+
+        let close_pdas_temp_acc_ix = spl_token::instruction::close_account(
+            token_program.key,
+            pdas_temp_token_account.key,
+            initializers_main_account.key,
+            &pda,
+            &[&pda],
+        )?;
+        msg!("Calling the token program to close pda's temp account...");
+        invoke_signed(
+            &close_pdas_temp_acc_ix,
+            &[
+                pdas_temp_token_account.clone(),
+                initializers_main_account.clone(),
+                pda_account.clone(),
+                token_program.clone(),
+            ],
+            &[&[&b"escrow"[..], &[bump_seed]]],
+        )?;
+        msg!("Closing the escrow account...");
+        **initializers_main_account.lamports.borrow_mut() = math::add(
+            initializers_main_account.lamports(),
+            escrow_account.lamports(),
+        )?;
         **escrow_account.lamports.borrow_mut() = 0;
         *escrow_account.try_borrow_mut_data()? = &mut [];
         Ok(())
     }
 }
+
+/// Reads the clock from the clock sysvar account every instruction passes last, failing with
+/// [EscrowError::MissingClock] when the client left it out or passed another account
+fn clock_sysvar(account: Option<&AccountInfo>) -> Result<Clock, ProgramError> {
+    match account {
+        Some(account) if *account.key == sysvar::clock::id() => Clock::from_account_info(account),
+        _ => Err(EscrowError::MissingClock.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_program::{
+        instruction::Instruction,
+        program_stubs::{set_syscall_stubs, SyscallStubs},
+    };
+    use std::sync::Mutex;
+
+    static RETURN_DATA: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+    static INVOKED: Mutex<Vec<Instruction>> = Mutex::new(Vec::new());
+
+    /// Keeps the last return data and records every CPI, which does nothing else off-chain
+    struct ReturnDataRecorder;
+
+    impl SyscallStubs for ReturnDataRecorder {
+        fn sol_set_return_data(&self, data: &[u8]) {
+            *RETURN_DATA.lock().unwrap() = data.to_vec();
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            _account_infos: &[AccountInfo],
+            _signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            INVOKED.lock().unwrap().push(instruction.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_get_program_version() {
+        set_syscall_stubs(Box::new(ReturnDataRecorder));
+
+        assert_eq!(Processor::process(&Pubkey::new_unique(), &[], &[3]), Ok(()));
+        let returned = RETURN_DATA.lock().unwrap();
+        assert_eq!(
+            u32::from_le_bytes(returned.as_slice().try_into().unwrap()),
+            crate::PROGRAM_VERSION
+        );
+    }
+
+    #[test]
+    fn test_clock_sysvar() {
+        assert_eq!(
+            clock_sysvar(None).err(),
+            Some(EscrowError::MissingClock.into())
+        );
+
+        let (other, clock_id) = (Pubkey::new_unique(), sysvar::clock::id());
+        let mut lamports = 0;
+        let mut data = vec![0; Clock::size_of()];
+        let pda = AccountInfo::new(
+            &other,
+            false,
+            false,
+            &mut lamports,
+            &mut [],
+            &other,
+            false,
+            0,
+        );
+        assert_eq!(
+            clock_sysvar(Some(&pda)).err(),
+            Some(EscrowError::MissingClock.into())
+        );
+
+        let mut clock_lamports = 0;
+        let mut clock_account = AccountInfo::new(
+            &clock_id,
+            false,
+            false,
+            &mut clock_lamports,
+            &mut data,
+            &sysvar::id(),
+            false,
+            0,
+        );
+        Clock {
+            slot: 42,
+            ..Clock::default()
+        }
+        .to_account_info(&mut clock_account)
+        .unwrap();
+        assert_eq!(clock_sysvar(Some(&clock_account)).unwrap().slot, 42);
+    }
+
+    #[test]
+    fn test_init_validates_the_window_it_is_sent() {
+        let program_id = Pubkey::new_unique();
+        let (initializer, temp, receive, escrow) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (token_program, sysvar_id) = (spl_token::id(), sysvar::id());
+        let (rent_id, clock_id) = (sysvar::rent::id(), sysvar::clock::id());
+        let mut lamports = [
+            0,
+            0,
+            0,
+            Rent::default().minimum_balance(Escrow::LEN),
+            0,
+            0,
+            0,
+        ];
+        let [a, b, c, d, e, f, g] = &mut lamports;
+        let mut escrow_data = vec![0; Escrow::LEN];
+        let mut rent_data = vec![0; Rent::size_of()];
+        let mut clock_data = vec![0; Clock::size_of()];
+
+        let mut rent_account = AccountInfo::new(
+            &rent_id,
+            false,
+            false,
+            e,
+            &mut rent_data,
+            &sysvar_id,
+            false,
+            0,
+        );
+        Rent::default().to_account_info(&mut rent_account).unwrap();
+        let mut clock_account = AccountInfo::new(
+            &clock_id,
+            false,
+            false,
+            g,
+            &mut clock_data,
+            &sysvar_id,
+            false,
+            0,
+        );
+        Clock {
+            slot: 500,
+            ..Clock::default()
+        }
+        .to_account_info(&mut clock_account)
+        .unwrap();
+        let accounts = vec![
+            AccountInfo::new(&initializer, true, false, a, &mut [], &program_id, false, 0),
+            AccountInfo::new(&temp, false, true, b, &mut [], &token_program, false, 0),
+            AccountInfo::new(&receive, false, false, c, &mut [], &token_program, false, 0),
+            AccountInfo::new(
+                &escrow,
+                false,
+                true,
+                d,
+                &mut escrow_data,
+                &program_id,
+                false,
+                0,
+            ),
+            rent_account,
+            AccountInfo::new(
+                &token_program,
+                false,
+                false,
+                f,
+                &mut [],
+                &program_id,
+                true,
+                0,
+            ),
+            clock_account,
+        ];
+        let init_data = |unlock_time: u64, time_out: u64| {
+            [
+                &[0][..],
+                &10u64.to_le_bytes(),
+                &unlock_time.to_le_bytes(),
+                &time_out.to_le_bytes(),
+            ]
+            .concat()
+        };
+
+        // the clock is at slot 500
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &init_data(500, 1_500)),
+            Err(EscrowError::InvalidUnlockTime.into())
+        );
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &init_data(600, 600)),
+            Err(EscrowError::InvalidTimeOut.into())
+        );
+        assert!(!Escrow::unpack_unchecked(&accounts[3].data.borrow())
+            .unwrap()
+            .is_initialized());
+
+        set_syscall_stubs(Box::new(ReturnDataRecorder));
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &init_data(600, 1_600)),
+            Ok(())
+        );
+        let escrow_info = Escrow::unpack(&accounts[3].data.borrow()).unwrap();
+        assert_eq!(
+            (escrow_info.unlock_time, escrow_info.time_out),
+            (600, 1_600)
+        );
+    }
+
+    /// Owns everything a Crank borrows: an escrow timing out at slot 1_100 whose temp account
+    /// holds 10 tokens, the initializer's token account for the same mint and a clock at `slot`
+    struct CrankFixture {
+        program_id: Pubkey,
+        escrow: Pubkey,
+        temp: Pubkey,
+        token_account: Pubkey,
+        initializer: Pubkey,
+        token_program: Pubkey,
+        pda: Pubkey,
+        clock: Pubkey,
+        sysvar: Pubkey,
+        escrow_data: Vec<u8>,
+        temp_data: Vec<u8>,
+        token_account_data: Vec<u8>,
+        clock_data: Vec<u8>,
+        lamports: [u64; 7],
+    }
+
+    impl CrankFixture {
+        fn new(slot: u64) -> Self {
+            let program_id = Pubkey::new_unique();
+            let (initializer, temp, mint) = (
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+            );
+            let mut escrow_data = vec![0; Escrow::LEN];
+            Escrow::pack(
+                Escrow {
+                    is_initialized: true,
+                    initializer_pubkey: initializer,
+                    temp_token_account_pubkey: temp,
+                    initializer_token_to_receive_account_pubkey: Pubkey::new_unique(),
+                    expected_amount: 10,
+                    unlock_time: 100,
+                    time_out: 1_100,
+                },
+                &mut escrow_data,
+            )
+            .unwrap();
+            let token_account_data = |owner, amount| {
+                let mut data = vec![0; TokenAccount::LEN];
+                TokenAccount::pack(
+                    TokenAccount {
+                        mint,
+                        owner,
+                        amount,
+                        state: spl_token::state::AccountState::Initialized,
+                        ..TokenAccount::default()
+                    },
+                    &mut data,
+                )
+                .unwrap();
+                data
+            };
+
+            let (clock, sysvar) = (sysvar::clock::id(), sysvar::id());
+            let mut clock_data = vec![0; Clock::size_of()];
+            let mut clock_lamports = 0;
+            let mut clock_account = AccountInfo::new(
+                &clock,
+                false,
+                false,
+                &mut clock_lamports,
+                &mut clock_data,
+                &sysvar,
+                false,
+                0,
+            );
+            Clock {
+                slot,
+                ..Clock::default()
+            }
+            .to_account_info(&mut clock_account)
+            .unwrap();
+
+            CrankFixture {
+                program_id,
+                escrow: Pubkey::new_unique(),
+                temp,
+                token_account: Pubkey::new_unique(),
+                initializer,
+                token_program: spl_token::id(),
+                pda: Pubkey::find_program_address(&[b"escrow"], &program_id).0,
+                clock,
+                sysvar,
+                escrow_data,
+                temp_data: token_account_data(Pubkey::new_unique(), 10),
+                token_account_data: token_account_data(initializer, 0),
+                clock_data,
+                lamports: [1_000, 0, 0, 0, 0, 0, 0],
+            }
+        }
+
+        /// The Crank accounts in order, the clock sysvar last
+        fn accounts(&mut self) -> Vec<AccountInfo> {
+            let [a, b, c, d, e, f, g] = &mut self.lamports;
+            vec![
+                AccountInfo::new(
+                    &self.escrow,
+                    false,
+                    true,
+                    a,
+                    &mut self.escrow_data,
+                    &self.program_id,
+                    false,
+                    0,
+                ),
+                AccountInfo::new(
+                    &self.temp,
+                    false,
+                    true,
+                    b,
+                    &mut self.temp_data,
+                    &self.token_program,
+                    false,
+                    0,
+                ),
+                AccountInfo::new(
+                    &self.token_account,
+                    false,
+                    true,
+                    c,
+                    &mut self.token_account_data,
+                    &self.token_program,
+                    false,
+                    0,
+                ),
+                AccountInfo::new(
+                    &self.initializer,
+                    false,
+                    true,
+                    d,
+                    &mut [],
+                    &self.program_id,
+                    false,
+                    0,
+                ),
+                AccountInfo::new(
+                    &self.token_program,
+                    false,
+                    false,
+                    e,
+                    &mut [],
+                    &self.program_id,
+                    true,
+                    0,
+                ),
+                AccountInfo::new(
+                    &self.pda,
+                    false,
+                    false,
+                    f,
+                    &mut [],
+                    &self.program_id,
+                    false,
+                    0,
+                ),
+                AccountInfo::new(
+                    &self.clock,
+                    false,
+                    false,
+                    g,
+                    &mut self.clock_data,
+                    &self.sysvar,
+                    false,
+                    0,
+                ),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_crank_without_clock_account() {
+        let mut fixture = CrankFixture::new(1_101);
+        let program_id = fixture.program_id;
+        let accounts = fixture.accounts();
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts[..6], &[2]),
+            Err(EscrowError::MissingClock.into())
+        );
+    }
+
+    #[test]
+    fn test_crank_before_time_out_is_rejected() {
+        set_syscall_stubs(Box::new(ReturnDataRecorder));
+        let mut fixture = CrankFixture::new(1_100);
+        let program_id = fixture.program_id;
+        let accounts = fixture.accounts();
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[2]),
+            Err(EscrowError::InvalidTimeOut.into())
+        );
+        assert_eq!(accounts[0].lamports(), 1_000);
+        assert!(Escrow::unpack(&accounts[0].data.borrow()).is_ok());
+    }
+
+    #[test]
+    fn test_crank_rejects_a_bogus_token_program() {
+        set_syscall_stubs(Box::new(ReturnDataRecorder));
+        let mut fixture = CrankFixture::new(1_101);
+        fixture.token_program = Pubkey::new_unique();
+        let program_id = fixture.program_id;
+        let accounts = fixture.accounts();
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[2]),
+            Err(ProgramError::IncorrectProgramId)
+        );
+        assert_eq!(accounts[0].lamports(), 1_000);
+        assert!(Escrow::unpack(&accounts[0].data.borrow()).is_ok());
+    }
+
+    #[test]
+    fn test_crank_rejects_another_pda_account() {
+        set_syscall_stubs(Box::new(ReturnDataRecorder));
+        let mut fixture = CrankFixture::new(1_101);
+        fixture.pda = Pubkey::new_unique();
+        let program_id = fixture.program_id;
+        let accounts = fixture.accounts();
+
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &[2]),
+            Err(ProgramError::InvalidSeeds)
+        );
+        assert_eq!(accounts[0].lamports(), 1_000);
+    }
+
+    #[test]
+    fn test_crank_after_time_out_refunds_the_initializer() {
+        set_syscall_stubs(Box::new(ReturnDataRecorder));
+        let mut fixture = CrankFixture::new(1_101);
+        let (program_id, temp) = (fixture.program_id, fixture.temp);
+        let accounts = fixture.accounts();
+
+        assert_eq!(Processor::process(&program_id, &accounts, &[2]), Ok(()));
+        let invoked = INVOKED.lock().unwrap();
+        let refund = invoked
+            .iter()
+            .find(|instruction| {
+                instruction.accounts[0].pubkey == temp
+                    && instruction.accounts[1].pubkey == *accounts[2].key
+            })
+            .map(|instruction| spl_token::instruction::TokenInstruction::unpack(&instruction.data))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            refund,
+            spl_token::instruction::TokenInstruction::Transfer { amount: 10 }
+        ));
+        assert_eq!(accounts[0].lamports(), 0);
+        assert_eq!(accounts[0].data_len(), 0);
+        assert_eq!(accounts[3].lamports(), 1_000);
+    }
+}
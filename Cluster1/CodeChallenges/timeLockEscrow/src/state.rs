@@ -1,3 +1,4 @@
+use crate::error::EscrowError;
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
     program_error::ProgramError,
@@ -15,6 +16,18 @@ pub struct Escrow {
     pub time_out: u64,
 }
 
+/// Checks a time-lock window in slots before it is sent or stored: it has to unlock after `now`
+/// and time out after it unlocks
+pub fn validate_window(unlock_time: u64, time_out: u64, now: u64) -> Result<(), EscrowError> {
+    if unlock_time <= now {
+        return Err(EscrowError::InvalidUnlockTime);
+    }
+    if time_out <= unlock_time {
+        return Err(EscrowError::InvalidTimeOut);
+    }
+    Ok(())
+}
+
 impl Sealed for Escrow {}
 
 impl IsInitialized for Escrow {
@@ -48,9 +61,9 @@ impl Pack for Escrow {
             initializer_token_to_receive_account_pubkey: Pubkey::new_from_array(
                 *initializer_token_to_receive_account_pubkey,
             ),
-            expected_amount: u64::from_be_bytes(*expected_amount),
+            expected_amount: u64::from_le_bytes(*expected_amount),
             unlock_time: u64::from_le_bytes(*unlock_time),
-            time_out: u64::from_be_bytes(*time_out),
+            time_out: u64::from_le_bytes(*time_out),
         })
     }
 
@@ -82,7 +95,25 @@ impl Pack for Escrow {
         initializer_token_to_receive_account_pubkey_dst
             .copy_from_slice(initializer_token_to_receive_account_pubkey.as_ref());
         *expected_amount_dst = expected_amount.to_le_bytes();
-        *unlock_time_dst = unlock_time.to_be_bytes();
+        *unlock_time_dst = unlock_time.to_le_bytes();
         *time_out_dst = time_out.to_le_bytes();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_window() {
+        assert!(validate_window(200, 1_200, 100).is_ok());
+        assert!(matches!(
+            validate_window(50, 1_200, 100),
+            Err(EscrowError::InvalidUnlockTime)
+        ));
+        assert!(matches!(
+            validate_window(200, 150, 100),
+            Err(EscrowError::InvalidTimeOut)
+        ));
+    }
+}
@@ -0,0 +1,22 @@
+use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError};
+
+// Longest name, in bytes, the power and park programs accept
+//
+pub const MAX_NAME_LEN: usize = 32;
+/*
+Both the power program (the name pulling the switch) and the park program (the visitor's name, also a PDA seed) take a name string.
+They share this limit so a name valid for one is valid for the other, each program returns its own NameTooLong error above it.
+*/
+
+pub fn next_named<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    iter: &mut I,
+    name: &str,
+) -> Result<I::Item, ProgramError> {
+    iter.next().ok_or_else(|| {
+        msg!("missing account: {}", name);
+        ProgramError::NotEnoughAccountKeys
+    })
+}
+/*
+next_named returns the next account like solana_program's next_account_info, for both programs, but first logs which account was expected when the list runs out, then fails with NotEnoughAccountKeys.
+*/
@@ -1,18 +1,22 @@
 use borsh::{ BorshDeserialize, BorshSerialize };
 use solana_program::{
-    account_info::{
-        next_account_info, AccountInfo
-    },
+    account_info::AccountInfo,
+    clock::Clock,
     entrypoint, 
     entrypoint::ProgramResult, 
     msg, 
-    program::invoke,
+    program::{invoke, set_return_data, MAX_RETURN_DATA},
     program_error::ProgramError,
     pubkey::Pubkey,
     rent::Rent,
     system_instruction,
     sysvar::Sysvar,
 };
+use std::cell::Ref;
+use thiserror::Error;
+
+mod common;
+use common::{next_named, MAX_NAME_LEN};
 /*
 BorshDeserialize and BorshSerialize are traits that define methods for serializing and deserializing binary data using the Borsh format.
 The entrypoint module provides functions for defining entry points into a Solana program.
@@ -21,7 +25,7 @@ The program module provides utility functions for working with Solana programs.
 The pubkey module provides a type for public keys in Solana.
 The rent module provides information about rent charged to accounts on the Solana network.
 The sysvar module provides a trait and a type for working with system variables in Solana.
-The account_info module provides functions and types for working with account information in Solana. The next_account_info function is used to iterate over the account information passed to the program. The AccountInfo type holds information about an account in Solana, such as its pubkey, data, and lamports.
+The account_info module provides functions and types for working with account information in Solana. The accounts passed to the program are iterated with next_named, defined in common.rs, which logs the name of a missing account. The AccountInfo type holds information about an account in Solana, such as its pubkey, data, and lamports.
 */
 
 #[cfg(not(feature = "no-entrypoint"))]
@@ -35,13 +39,31 @@ pub fn process_instruction(
     instruction_data: &[u8],
 ) -> ProgramResult {
 
+    if instruction_data.is_empty() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
     match PowerStatus::try_from_slice(&instruction_data) {
         Ok(power_status) => return initialize(program_id, accounts, power_status),
         Err(_) => {},
     }
 
     match SetPowerStatus::try_from_slice(&instruction_data) {
-        Ok(set_power_status) => return switch_power(accounts, set_power_status.name),
+        Ok(set_power_status) => return switch_power(program_id, accounts, set_power_status.name()?.to_string()),
+        Err(_) => {},
+    }
+
+    match PowerInstruction::unpack(&instruction_data) {
+        Ok(PowerInstruction::ScheduleToggle { at }) => return schedule_toggle(program_id, accounts, at),
+        Ok(PowerInstruction::ExecuteScheduled) => return execute_scheduled(program_id, accounts),
+        Ok(PowerInstruction::Batch { instructions }) => return process_batch(program_id, accounts, instructions),
+        Ok(PowerInstruction::GetVersion) => return get_version(program_id, accounts),
+        Ok(PowerInstruction::QueryMany) => return query_many(program_id, accounts),
+        Ok(PowerInstruction::Initialize { power_status }) => return initialize(program_id, accounts, power_status),
+        Ok(PowerInstruction::Switch { set_power_status }) => return switch_power(program_id, accounts, set_power_status.name()?.to_string()),
+        Ok(PowerInstruction::Migrate) => return migrate(program_id, accounts),
+        #[cfg(feature = "debug")]
+        Ok(PowerInstruction::ForceOff) => return force_off(program_id, accounts),
         Err(_) => {},
     }
 
@@ -53,25 +75,37 @@ The process_instruction function is an entrypoint function of a Solana program.
 program_id: A Pubkey representing the program ID.
 accounts: An array of AccountInfo objects representing the accounts that are being passed to the program.
 instruction_data: A byte array representing the data passed in the instruction.
-The function first tries to parse the instruction data as a PowerStatus struct. If the parse is successful, it calls the initialize function with the program_id and accounts as arguments and power_status as the parsed struct.
+An empty instruction_data is rejected right away with ProgramError::InvalidInstructionData.
+The data is tried against three decodings in order, and the first one that consumes it exactly wins:
 
-If the parse fails, the function tries to parse the instruction data as a SetPowerStatus struct. If the parse is successful, it calls the switch_power function with accounts and the parsed SetPowerStatus.name as arguments.
+1. A bare PowerStatus, in its current layout, goes to initialize.
+2. A bare SetPowerStatus goes to switch_power with its name.
+3. A tagged PowerInstruction goes to schedule_toggle, execute_scheduled, process_batch, get_version, query_many, initialize, switch_power or migrate (or force_off in debug builds).
 
-If both parses fail, the function returns ProgramError::InvalidInstructionData.
+If all three fail, the function returns ProgramError::InvalidInstructionData.
+Payloads from the original program are no longer accepted. Its PowerStatus was a single is_on byte, and PowerStatus has grown since, so [0] and [1] don't decode as one any more. [0] then fails all three decodings, and [1] decodes as the tag of ExecuteScheduled. Clients must send the current PowerStatus, or better the tagged Initialize and Switch.
 */
 
 pub fn initialize(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    power_status: PowerStatus,
+    mut power_status: PowerStatus,
 ) -> ProgramResult {
 
     let accounts_iter = &mut accounts.iter();
-    let power = next_account_info(accounts_iter)?;
-    let user = next_account_info(accounts_iter)?;
-    let system_program = next_account_info(accounts_iter)?;
+    let power = next_named(accounts_iter, "power")?;
+    let user = next_named(accounts_iter, "user")?;
+    let system_program = next_named(accounts_iter, "system_program")?;
 
-    let account_span = (power_status.try_to_vec()?).len();
+    if power_status.authorities.len() > MAX_AUTHORITIES
+        || power_status.threshold as usize > power_status.authorities.len()
+        || (power_status.threshold == 0 && !power_status.authorities.is_empty())
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+    power_status.version = POWER_STATUS_VERSION;
+
+    let account_span = (power_status.pack()?).len();
     let lamports_required = (Rent::get()?).minimum_balance(account_span);
 
     invoke(
@@ -87,7 +121,8 @@ pub fn initialize(
         ]
     )?;
 
-    power_status.serialize(&mut &mut power.data.borrow_mut()[..])?;
+    assert_rent_exempt(power)?;
+    power_status.save(power)?;
 
     Ok(())
 }
@@ -95,54 +130,650 @@ pub fn initialize(
 This function is initializing the program with a PowerStatus struct. It takes a program id and a reference to an array of AccountInfo as input.
 The function first sets up an iterator over the accounts and gets three accounts: power, user, and system_program.
 Then it computes the required lamports (the minimum balance required for a new account) based on the size of the serialized power_status using the Rent system variable and the minimum_balance function.
-Finally, it calls the invoke function to create a new user account, with the power account as the owner, the required lamports as the starting balance, the size of the power_status as the account space and the program id as the program id. The power_status is then serialized and stored in the newly created user account's data.
+Finally, it calls the invoke function to create a new user account, with the power account as the owner, the required lamports as the starting balance, the size of the packed power_status (including its checksum byte) as the account space and the program id as the program id. The power_status is then packed and saved in the newly created user account's data.
+Before writing, assert_rent_exempt makes sure the power account ended up rent exempt, failing with NotRentExempt otherwise.
+Whatever version the client sent is replaced with POWER_STATUS_VERSION, the layout this program writes.
 
 */
    
 pub fn switch_power(
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
     name: String,
 ) -> ProgramResult {
 
     let accounts_iter = &mut accounts.iter();
-    let power = next_account_info(accounts_iter)?;
-    
-    let mut power_status = PowerStatus::try_from_slice(&power.data.borrow())?;
+    let power = next_named(accounts_iter, "power")?;
+
+    check_name(&name)?;
+    let mut power_status = PowerStatus::from_account_info(program_id, power)?;
+    check_authorities(&power_status, accounts_iter.as_slice())?;
+
+    let now = Clock::get()?.unix_timestamp;
+    check_cooldown(&power_status, now)?;
+    count_toggle(&mut power_status)?;
     power_status.is_on = !power_status.is_on;
-    power_status.serialize(&mut &mut power.data.borrow_mut()[..])?;
+    power_status.last_toggled_at = now;
+    power_status.save(power)?;
+
+    for line in switch_log_lines(&power_status, &name) {
+        msg!("{}", line);
+    }
+
+    Ok(())
+}
+/*
+The function takes three arguments: program_id is the id of this program, accounts is an array of AccountInfo structs, and name is a string representing the name of the person who is switching the power.
+The function starts by creating an iterator over the accounts array and calling next_named to get the first account. This account stores the status of the power and is read with PowerStatus::from_account_info, so it must be owned by this program, otherwise IncorrectProgramId is returned.
+A name longer than MAX_NAME_LEN bytes is rejected with NameTooLong before anything is read.
+The remaining accounts are the signers approving the toggle; at least threshold of them must be listed in authorities.
+A toggle is rejected with CooldownActive if less than min_toggle_interval seconds passed since last_toggled_at, which is then set to the current Clock time.
+Every toggle is counted in toggle_count through count_toggle.
+Next, the code deserializes the power status from the data field of the account, toggles the is_on property, serializes it back to the data field, and logs the lines switch_log_lines returns for the stored log_level.
+Finally, the function returns Ok(()), indicating success.
+*/
+
+pub fn schedule_toggle(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    at: i64,
+) -> ProgramResult {
 
-    msg!("{} is pulling the power switch!", &name);
+    let accounts_iter = &mut accounts.iter();
+    let power = next_named(accounts_iter, "power")?;
+
+    let mut power_status = PowerStatus::from_account_info(program_id, power)?;
+    check_authorities(&power_status, accounts_iter.as_slice())?;
+
+    if at <= Clock::get()?.unix_timestamp {
+        return Err(ProgramError::InvalidArgument);
+    }
+    power_status.scheduled_toggle_at = at;
+    power_status.save(power)?;
+
+    msg!("The power switch is scheduled to flip at {}.", at);
+
+    Ok(())
+}
+/*
+schedule_toggle takes the power account followed by the approving signers, like switch_power.
+It stores a future unix timestamp in scheduled_toggle_at; a timestamp that is not in the future is rejected with InvalidArgument.
+*/
+
+pub fn execute_scheduled(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+
+    let accounts_iter = &mut accounts.iter();
+    let power = next_named(accounts_iter, "power")?;
+
+    let mut power_status = PowerStatus::from_account_info(program_id, power)?;
+    let now = Clock::get()?.unix_timestamp;
+    check_schedule(&power_status, now)?;
+    count_toggle(&mut power_status)?;
+
+    power_status.is_on = !power_status.is_on;
+    power_status.last_toggled_at = now;
+    power_status.scheduled_toggle_at = 0;
+    power_status.save(power)?;
 
     match power_status.is_on {
-        true => msg!("The power is now on."),
-        false => msg!("The power is now off!"),
+        true => msg!("The scheduled switch turned the power on."),
+        false => msg!("The scheduled switch turned the power off!"),
     };
 
     Ok(())
 }
 /*
-The function takes two arguments: accounts is an array of AccountInfo structs, and name is a string representing the name of the person who is switching the power.
-The function starts by creating an iterator over the accounts array and calling next_account_info to get the first account. This account is assumed to store the status of the power.
-Next, the code deserializes the power status from the data field of the account, toggles the is_on property, serializes it back to the data field, and logs the action of the person switching the power and the current power status.
-Finally, the function returns Ok(()), indicating success.
+execute_scheduled needs no signer, so any cranker can call it once the scheduled time is reached.
+It flips is_on, counts the toggle, records the toggle time and clears scheduled_toggle_at so the same schedule cannot run twice.
+*/
+
+pub fn process_batch(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instructions: Vec<BatchEntry>,
+) -> ProgramResult {
+
+    for entry in instructions {
+        if PowerInstruction::unpack(&entry.data)
+            .map_or(false, |instruction| matches!(instruction, PowerInstruction::Batch { .. }))
+        {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        let entry_accounts = accounts
+            .get(entry.account_offset as usize..)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        process_instruction(program_id, entry_accounts, &entry.data)?;
+    }
+
+    Ok(())
+}
+/*
+process_batch runs every sub-instruction of a Batch in order through process_instruction, so each one is decoded exactly like a standalone instruction.
+Every entry sees the account list starting at its account_offset, e.g. an initialize at offset 0 (power, user, system_program) and a switch at offset 0 again (power, then signers).
+Batches cannot be nested. Any failing entry returns its error, which fails the whole transaction so no partial batch is ever applied.
+*/
+
+pub fn get_version(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+
+    let accounts_iter = &mut accounts.iter();
+    let power = next_named(accounts_iter, "power")?;
+
+    set_return_data(&[PowerStatus::stored_version(program_id, power)?]);
+
+    Ok(())
+}
+/*
+get_version returns the version byte of the power account through set_return_data, so a client can tell which PowerStatus layout it is about to read.
+It reads the byte with PowerStatus::stored_version instead of decoding the whole account, so it also answers for a layout this program can no longer decode.
+*/
+
+pub const MAX_QUERY_DEVICES: usize = (MAX_RETURN_DATA - 4) / (32 + 1);
+
+pub fn query_many(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+
+    if accounts.len() > MAX_QUERY_DEVICES {
+        return Err(PowerError::TooManyDevices.into());
+    }
+
+    let mut statuses: Vec<(Pubkey, bool)> = Vec::with_capacity(accounts.len());
+    for power in accounts {
+        let power_status = PowerStatus::from_account_info(program_id, power)?;
+        statuses.push((*power.key, power_status.is_on()));
+    }
+    set_return_data(&statuses.try_to_vec()?);
+
+    Ok(())
+}
+/*
+query_many reads every passed power account and returns a Borsh Vec<(Pubkey, bool)> of each account's key and power status through set_return_data, so a dashboard polls many devices in one simulated transaction.
+Each entry is 33 bytes after the 4 byte length, so MAX_QUERY_DEVICES accounts fill the MAX_RETURN_DATA bytes the runtime allows. More accounts fail with TooManyDevices instead of returning truncated data.
+*/
+
+pub fn migrate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+
+    let accounts_iter = &mut accounts.iter();
+    let power = next_named(accounts_iter, "power")?;
+    let payer = next_named(accounts_iter, "payer")?;
+    let system_program = next_named(accounts_iter, "system_program")?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut power_status = PowerStatus::from_account_info(program_id, power)?;
+    if power_status.version == POWER_STATUS_VERSION {
+        msg!("The power account is already at version {}.", POWER_STATUS_VERSION);
+        return Ok(());
+    }
+    power_status.version = POWER_STATUS_VERSION;
+
+    grow_power_account(power, payer, system_program, power_status.pack()?.len())?;
+    power_status.save(power)?;
+
+    msg!("The power account was migrated to version {}.", POWER_STATUS_VERSION);
+
+    Ok(())
+}
+/*
+migrate rewrites a power account written with an older PowerStatus layout in the current one. from_account_info decodes the old layout, grow_power_account reallocs the account to the new size with the payer (who must sign) covering the extra rent, then the status is saved with version POWER_STATUS_VERSION.
+A version 1 account can be read and queried without migrating, but anything that saves it (a switch, a schedule) fails with AccountDataTooSmall until it is migrated. An account already at POWER_STATUS_VERSION is left alone.
+*/
+
+#[cfg(feature = "debug")]
+pub fn force_off(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+
+    let accounts_iter = &mut accounts.iter();
+    let power = next_named(accounts_iter, "power")?;
+
+    let mut power_status = PowerStatus::from_account_info(program_id, power)?;
+    power_status.is_on = false;
+    power_status.save(power)?;
+
+    msg!("The power was forced off.");
+
+    Ok(())
+}
+/*
+force_off only exists with the debug feature, so release builds can't decode or run it. It turns the power off without any authority check or cooldown, which lets a test rig reset its fixture in one instruction.
+*/
+
+pub fn check_schedule(power_status: &PowerStatus, now: i64) -> ProgramResult {
+    if power_status.scheduled_toggle_at == 0 {
+        return Err(PowerError::NoScheduledToggle.into());
+    }
+
+    if now < power_status.scheduled_toggle_at {
+        return Err(PowerError::ScheduleNotDue.into());
+    }
+
+    Ok(())
+}
+
+pub fn check_authorities(power_status: &PowerStatus, signers: &[AccountInfo]) -> ProgramResult {
+    let approvals = power_status
+        .authorities
+        .iter()
+        .filter(|authority| {
+            signers
+                .iter()
+                .any(|signer| signer.is_signer && signer.key == *authority)
+        })
+        .count();
+
+    if approvals < power_status.threshold as usize {
+        msg!(
+            "Only {} of the required {} authorities signed.",
+            approvals,
+            power_status.threshold
+        );
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    Ok(())
+}
+/*
+check_authorities counts how many distinct authorities stored in the PowerStatus appear as signers in the passed accounts.
+If fewer than threshold of them signed, the toggle is rejected with MissingRequiredSignature. A PowerStatus without authorities has a threshold of 0, so anyone can switch it.
+*/
+
+pub const MAX_LOG_NAME: usize = 16;
+
+pub fn log_name(name: &str) -> &str {
+    match name.char_indices().nth(MAX_LOG_NAME) {
+        Some((end, _)) => &name[..end],
+        None => name,
+    }
+}
+/*
+log_name cuts the name down to its first MAX_LOG_NAME characters (on a char boundary) so a huge name does not cost extra compute when logged.
+Only the log line is shortened, the name is never stored.
+MAX_LOG_NAME is half of MAX_NAME_LEN: check_name already rejects longer names, so a limit of MAX_NAME_LEN or more would never cut anything.
+*/
+
+pub const LOG_SILENT: u8 = 0;
+pub const LOG_NORMAL: u8 = 1;
+pub const LOG_VERBOSE: u8 = 2;
+
+pub fn switch_log_lines(power_status: &PowerStatus, name: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    if power_status.log_level == LOG_SILENT {
+        return lines;
+    }
+    lines.push(format!("{} is pulling the power switch!", log_name(name)));
+    match power_status.is_on {
+        true => lines.push("The power is now on.".to_string()),
+        false => lines.push("The power is now off!".to_string()),
+    };
+    if power_status.log_level >= LOG_VERBOSE {
+        lines.push(format!("{:?}", power_status));
+    }
+    lines
+}
+/*
+switch_log_lines builds what switch_power logs after a toggle, according to the log_level stored in PowerStatus, so operators can trade logs for compute units.
+LOG_SILENT logs nothing, LOG_NORMAL logs who pulled the switch (name truncated by log_name) and the new power status, LOG_VERBOSE also logs the whole PowerStatus.
 */
 
+pub fn split_tagged<T: BorshDeserialize>(input: &[u8]) -> Result<(u8, T), ProgramError> {
+    let (tag, rest) = input.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+    let payload = T::try_from_slice(rest).map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    Ok((*tag, payload))
+}
+/*
+split_tagged peels the first byte off the instruction data as a tag and deserializes the rest as T, so a program dispatching on a tag byte doesn't repeat the slicing.
+Empty input, or a payload that isn't exactly one T, fails with InvalidInstructionData.
+*/
+
+pub fn assert_rent_exempt(account: &AccountInfo) -> ProgramResult {
+    check_rent_exempt(&Rent::get()?, account)
+}
+
+fn check_rent_exempt(rent: &Rent, account: &AccountInfo) -> ProgramResult {
+    if !rent.is_exempt(account.lamports(), account.data_len()) {
+        return Err(PowerError::NotRentExempt.into());
+    }
+
+    Ok(())
+}
+
+pub fn rent_top_up(rent: &Rent, lamports: u64, new_len: usize) -> u64 {
+    rent.minimum_balance(new_len).saturating_sub(lamports)
+}
+
+pub fn grow_power_account<'a>(
+    power: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    new_len: usize,
+) -> ProgramResult {
+
+    let top_up = rent_top_up(&Rent::get()?, power.lamports(), new_len);
+    if top_up > 0 {
+        if payer.lamports() < top_up {
+            return Err(ProgramError::InsufficientFunds);
+        }
+        invoke(
+            &system_instruction::transfer(payer.key, power.key, top_up),
+            &[payer.clone(), power.clone(), system_program.clone()],
+        )?;
+    }
+
+    power.realloc(new_len, false)?;
+    assert_rent_exempt(power)
+}
+/*
+grow_power_account reallocs the power account to new_len for a PowerStatus whose variable-length fields grew. Before the realloc, the payer sends the rent the bigger account is missing (rent_top_up, computed from Rent), so the account stays rent exempt.
+A payer holding less than the top-up fails with InsufficientFunds. migrate uses it to grow an account written with an older, shorter layout; the authority list itself is fixed at initialize.
+*/
+
+pub fn check_cooldown(power_status: &PowerStatus, now: i64) -> ProgramResult {
+    if now.saturating_sub(power_status.last_toggled_at) < power_status.min_toggle_interval {
+        return Err(PowerError::CooldownActive.into());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "saturating-counters"))]
+pub fn count_toggle(power_status: &mut PowerStatus) -> ProgramResult {
+    power_status.toggle_count = power_status
+        .toggle_count
+        .checked_add(1)
+        .ok_or(PowerError::ToggleCountOverflow)?;
+
+    Ok(())
+}
+
+#[cfg(feature = "saturating-counters")]
+pub fn count_toggle(power_status: &mut PowerStatus) -> ProgramResult {
+    power_status.toggle_count = power_status.toggle_count.saturating_add(1);
+
+    Ok(())
+}
+/*
+count_toggle adds one to toggle_count for every toggle. By default a counter already at u64::MAX fails the toggle with ToggleCountOverflow.
+With the saturating-counters feature the counter stays at u64::MAX instead and the toggle goes through, for operators who care more about the switch working than about an exact count.
+*/
+
+pub fn check_name(name: &str) -> ProgramResult {
+    if name.len() > MAX_NAME_LEN {
+        return Err(PowerError::NameTooLong.into());
+    }
+
+    Ok(())
+}
+
+pub const MAX_AUTHORITIES: usize = 10;
+
+#[derive(Error, Debug, Copy, Clone)]
+pub enum PowerError {
+    #[error("Power was toggled too recently")]
+    CooldownActive,
+    #[error("No toggle is scheduled")]
+    NoScheduledToggle,
+    #[error("Scheduled toggle is not due yet")]
+    ScheduleNotDue,
+    #[error("Not rent exempt")]
+    NotRentExempt,
+    #[error("Name is too long")]
+    NameTooLong,
+    #[error("Toggle count overflowed")]
+    ToggleCountOverflow,
+    #[error("Too many devices to query at once")]
+    TooManyDevices,
+}
+
+impl From<PowerError> for ProgramError {
+    fn from(e: PowerError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub enum PowerInstruction {
+    ScheduleToggle { at: i64 },
+    ExecuteScheduled,
+    Batch { instructions: Vec<BatchEntry> },
+    GetVersion,
+    QueryMany,
+    Initialize { power_status: PowerStatus },
+    Switch { set_power_status: SetPowerStatus },
+    Migrate,
+    #[cfg(feature = "debug")]
+    ForceOff,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct BatchEntry {
+    pub account_offset: u8,
+    pub data: Vec<u8>,
+}
+
+impl PowerInstruction {
+    pub fn pack(&self) -> Result<Vec<u8>, ProgramError> {
+        Ok(self.try_to_vec()?)
+    }
+
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        PowerInstruction::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)
+    }
+}
+/*
+PowerInstruction is tagged by Borsh itself: the first byte is the variant (0 ScheduleToggle, 1 ExecuteScheduled, 2 Batch, 3 GetVersion, 4 QueryMany, 5 Initialize, 6 Switch, 7 Migrate, 8 ForceOff in debug builds) followed by the variant's fields.
+pack and unpack wrap that encoding like EscrowInstruction does, so clients and process_instruction never call Borsh directly. unpack fails with InvalidInstructionData on an unknown tag or trailing bytes.
+Initialize and Switch carry the same PowerStatus and SetPowerStatus that process_instruction also accepts bare, see the dispatch order above it. ForceOff stays last so release builds, which don't have it, keep the same tags.
+There is no TurnOn, TurnOff, SetBrightness or Close: the switch only toggles, the device has no brightness and power accounts are never closed, so those tags would have no handler.
+*/
+
+#[cfg(not(feature = "fixed-name"))]
 #[derive(BorshDeserialize, BorshSerialize, Debug)]
 pub struct SetPowerStatus {
     pub name: String,
 }
 
+#[cfg(not(feature = "fixed-name"))]
+impl SetPowerStatus {
+    pub fn name(&self) -> Result<&str, ProgramError> {
+        Ok(&self.name)
+    }
+}
+
+#[cfg(feature = "fixed-name")]
+pub const FIXED_NAME_LEN: usize = 32;
+
+#[cfg(feature = "fixed-name")]
 #[derive(BorshDeserialize, BorshSerialize, Debug)]
+pub struct SetPowerStatus {
+    pub name_len: u8,
+    pub name: [u8; FIXED_NAME_LEN],
+}
+
+#[cfg(feature = "fixed-name")]
+impl SetPowerStatus {
+    pub fn new(name: &str) -> Result<Self, ProgramError> {
+        if name.len() > FIXED_NAME_LEN {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let mut buf = [0; FIXED_NAME_LEN];
+        buf[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(SetPowerStatus {
+            name_len: name.len() as u8,
+            name: buf,
+        })
+    }
+
+    pub fn name(&self) -> Result<&str, ProgramError> {
+        let bytes = self
+            .name
+            .get(..self.name_len as usize)
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        std::str::from_utf8(bytes).map_err(|_| ProgramError::InvalidInstructionData)
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Default)]
 pub struct PowerStatus {
     pub is_on: bool,
+    pub authorities: Vec<Pubkey>,
+    pub threshold: u8,
+    pub min_toggle_interval: i64,
+    pub last_toggled_at: i64,
+    pub scheduled_toggle_at: i64,
+    pub bump: u8,
+    pub version: u8,
+    pub log_level: u8,
+    pub toggle_count: u64,
+}
+
+pub const POWER_STATUS_VERSION: u8 = 2;
+
+impl PowerStatus {
+    pub fn is_on(&self) -> bool {
+        self.is_on
+    }
+
+    pub fn find_address(program_id: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"power", user.as_ref()], program_id)
+    }
+
+    pub fn address(&self, program_id: &Pubkey, user: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::create_program_address(&[b"power", user.as_ref(), &[self.bump]], program_id)?)
+    }
+
+    pub fn from_account_info(program_id: &Pubkey, account: &AccountInfo) -> Result<Self, ProgramError> {
+        let status = Self::checked_status(program_id, account)?;
+        match version_in(&status)? {
+            1 => Ok(PowerStatusV1::try_from_slice(&status)?.into()),
+            _ => Ok(PowerStatus::try_from_slice(&status)?),
+        }
+    }
+
+    pub fn stored_version(program_id: &Pubkey, account: &AccountInfo) -> Result<u8, ProgramError> {
+        version_in(&Self::checked_status(program_id, account)?)
+    }
+
+    fn checked_status<'a>(program_id: &Pubkey, account: &'a AccountInfo) -> Result<Ref<'a, [u8]>, ProgramError> {
+        if account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if account.data_is_empty() {
+            return Err(ProgramError::UninitializedAccount);
+        }
+        let data = account.try_borrow_data()?;
+        let (checksum_byte, status) = data.split_last().ok_or(ProgramError::UninitializedAccount)?;
+        if checksum(status) != *checksum_byte {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Ref::map(data, |data| &data[..data.len() - 1]))
+    }
+
+    pub fn pack(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = self.try_to_vec()?;
+        data.push(checksum(&data));
+        Ok(data)
+    }
+
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        let data = self.pack()?;
+        account
+            .try_borrow_mut_data()?
+            .get_mut(..data.len())
+            .ok_or(ProgramError::AccountDataTooSmall)?
+            .copy_from_slice(&data);
+        Ok(())
+    }
+}
+
+// Every layout starts with is_on, the authorities (a u32 length, then 32 bytes each), threshold,
+//  the three i64 timestamps and bump, so version sits at the same offset whatever follows it
+fn version_in(status: &[u8]) -> Result<u8, ProgramError> {
+    let authorities = status.get(1..5).ok_or(ProgramError::InvalidAccountData)?;
+    let authorities = u32::from_le_bytes(authorities.try_into().unwrap()) as usize;
+    status
+        .get(1 + 4 + 32 * authorities + 1 + 3 * 8 + 1)
+        .copied()
+        .ok_or(ProgramError::InvalidAccountData)
+}
+
+// PowerStatus as version 1 wrote it, before toggle_count
+#[derive(BorshDeserialize)]
+struct PowerStatusV1 {
+    is_on: bool,
+    authorities: Vec<Pubkey>,
+    threshold: u8,
+    min_toggle_interval: i64,
+    last_toggled_at: i64,
+    scheduled_toggle_at: i64,
+    bump: u8,
+    version: u8,
+    log_level: u8,
+}
+
+impl From<PowerStatusV1> for PowerStatus {
+    fn from(v1: PowerStatusV1) -> Self {
+        PowerStatus {
+            is_on: v1.is_on,
+            authorities: v1.authorities,
+            threshold: v1.threshold,
+            min_toggle_interval: v1.min_toggle_interval,
+            last_toggled_at: v1.last_toggled_at,
+            scheduled_toggle_at: v1.scheduled_toggle_at,
+            bump: v1.bump,
+            version: v1.version,
+            log_level: v1.log_level,
+            toggle_count: 0,
+        }
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+impl std::fmt::Display for PowerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.is_on() {
+            true => write!(f, "ON"),
+            false => write!(f, "OFF"),
+        }
+    }
 }
 /*
 There are two structs: SetPowerStatus and PowerStatus.
 SetPowerStatus has a single field name of type String.
-PowerStatus has a single field is_on of type bool.
+With the fixed-name feature SetPowerStatus instead holds the name in a [u8; FIXED_NAME_LEN] buffer plus a name_len byte, so it always serializes to the same size. SetPowerStatus::name reads the name back in both layouts.
+PowerStatus has a field is_on of type bool, the list of authorities (at most MAX_AUTHORITIES) allowed to switch the power, and the threshold of them that must sign a toggle.
+min_toggle_interval is the number of seconds that must pass between two toggles, last_toggled_at is the unix timestamp of the last toggle.
+scheduled_toggle_at is the unix timestamp a cranker may flip the power at, or 0 when nothing is scheduled.
+bump is the bump seed of the power account PDA, seeded with "power" and the user. PowerStatus::find_address derives it once, PowerStatus::address rebuilds the address from the stored bump with create_program_address, so a later invoke_signed (e.g. a close) never pays for find_program_address again. initialize still creates the power account from a keypair, so bump is only meaningful once the account is created at its PDA.
+version is the PowerStatus layout the account was written with, set to POWER_STATUS_VERSION at initialize.
+log_level is one of LOG_SILENT, LOG_NORMAL and LOG_VERBOSE and decides how much switch_power logs, see switch_log_lines.
+toggle_count is the number of toggles since initialize, added in version 2 of the layout.
+from_account_info still decodes a version 1 account through PowerStatusV1, with toggle_count 0 and version left at 1, so it stays readable until migrate rewrites it in the current layout.
+The account is sized from the serialized PowerStatus at initialize, so the authority list fits without a later realloc.
 
 Both structs implement the BorshDeserialize and BorshSerialize traits, which are used for (de)serializing the structs from and to binary data.
 The Debug trait is also implemented for both structs, allowing them to be printed as human-readable strings when used with the {:?} format specifier.
+PowerStatus also implements Display, printing ON or OFF, so a CLI can show the device state with {}.
+PowerStatus::stored_version reads only the version byte, at an offset that is the same in every layout.
+PowerStatus::from_account_info reads the status from the power account, returning IncorrectProgramId when this program doesn't own it and UninitializedAccount when it holds no data.
+In the account the Borsh PowerStatus is followed by a one byte XOR checksum of its bytes. PowerStatus::pack appends it and save writes both, from_account_info returns InvalidAccountData when the checksum doesn't match, catching a corrupted or half written account.
 */
 
 
@@ -170,3 +801,676 @@ Optimization: The code does not take advantage of parallelism and concurrency to
 Security: The code does not perform any input validation, which can lead to security vulnerabilities. A better solution would be to validate inputs before performing any operations.
 
 */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_program::{
+        entrypoint::SUCCESS,
+        instruction::Instruction,
+        program_stubs::{set_syscall_stubs, SyscallStubs},
+        system_program,
+    };
+    use std::sync::{Mutex, MutexGuard};
+
+    static LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static RETURN_DATA: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+    static SYSCALLS: Mutex<()> = Mutex::new(());
+
+    // The stubs, LOGS and RETURN_DATA are global while tests run in parallel, so every test using
+    //  them holds this guard for its whole body and starts from empty recordings
+    fn record_syscalls() -> MutexGuard<'static, ()> {
+        let guard = SYSCALLS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        set_syscall_stubs(Box::new(LogRecorder));
+        LOGS.lock().unwrap().clear();
+        RETURN_DATA.lock().unwrap().clear();
+        guard
+    }
+
+    struct LogRecorder;
+
+    impl SyscallStubs for LogRecorder {
+        fn sol_log(&self, message: &str) {
+            LOGS.lock().unwrap().push(message.to_string());
+        }
+
+        fn sol_set_return_data(&self, data: &[u8]) {
+            *RETURN_DATA.lock().unwrap() = data.to_vec();
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Clock) = Clock::default() };
+            SUCCESS
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            SUCCESS
+        }
+
+        // Plays the system program, the only program this one calls: create_account (tag 0) and
+        //  transfer (tag 2) move the lamports, create_account also gives the new account its space
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            _signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            let account = |index: usize| {
+                account_infos
+                    .iter()
+                    .find(|account| *account.key == instruction.accounts[index].pubkey)
+                    .unwrap()
+            };
+            let lamports = u64::from_le_bytes(instruction.data[4..12].try_into().unwrap());
+            **account(0).lamports.borrow_mut() -= lamports;
+            **account(1).lamports.borrow_mut() += lamports;
+            if instruction.data[0] == 0 {
+                let space = u64::from_le_bytes(instruction.data[12..20].try_into().unwrap()) as usize;
+                *account(1).data.borrow_mut() = Box::leak(vec![0; space].into_boxed_slice());
+            }
+            Ok(())
+        }
+    }
+
+    fn signer_info<'a>(key: &'a Pubkey, lamports: &'a mut u64, owner: &'a Pubkey) -> AccountInfo<'a> {
+        AccountInfo::new(key, true, false, lamports, &mut [], owner, false, 0)
+    }
+
+    #[test]
+    fn test_single_authority_can_switch() {
+        let owner = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let mut lamports = 0;
+        let power_status = PowerStatus {
+            is_on: false,
+            authorities: vec![authority],
+            threshold: 1,
+            ..PowerStatus::default()
+        };
+
+        let signers = [signer_info(&authority, &mut lamports, &owner)];
+        assert!(check_authorities(&power_status, &signers).is_ok());
+    }
+
+    #[test]
+    fn test_two_of_three_rejects_unauthorized_signer() {
+        let owner = Pubkey::new_unique();
+        let authorities = vec![Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let intruder = Pubkey::new_unique();
+        let (mut lamports_a, mut lamports_b) = (0, 0);
+        let power_status = PowerStatus {
+            is_on: false,
+            authorities: authorities.clone(),
+            threshold: 2,
+            ..PowerStatus::default()
+        };
+
+        let signers = [
+            signer_info(&authorities[0], &mut lamports_a, &owner),
+            signer_info(&intruder, &mut lamports_b, &owner),
+        ];
+        assert_eq!(
+            check_authorities(&power_status, &signers),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    #[test]
+    fn test_cooldown_rejects_immediate_toggle() {
+        let power_status = PowerStatus {
+            is_on: true,
+            min_toggle_interval: 60,
+            last_toggled_at: 1_000,
+            ..PowerStatus::default()
+        };
+
+        assert_eq!(
+            check_cooldown(&power_status, 1_000),
+            Err(PowerError::CooldownActive.into())
+        );
+        assert!(check_cooldown(&power_status, 1_060).is_ok());
+    }
+
+    #[test]
+    fn test_scheduled_toggle_only_runs_when_due() {
+        let power_status = PowerStatus {
+            scheduled_toggle_at: 2_000,
+            ..PowerStatus::default()
+        };
+
+        assert_eq!(
+            check_schedule(&power_status, 1_999),
+            Err(PowerError::ScheduleNotDue.into())
+        );
+        assert!(check_schedule(&power_status, 2_000).is_ok());
+    }
+
+    #[test]
+    fn test_switch_inside_the_cooldown_is_rejected() {
+        let _syscalls = record_syscalls();
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        // the stubbed clock reads 0, the last toggle was 0 seconds ago
+        let mut data = PowerStatus { is_on: true, min_toggle_interval: 60, ..PowerStatus::default() }.pack().unwrap();
+        let mut lamports = 1;
+        let power = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        #[cfg(not(feature = "fixed-name"))]
+        let set_power_status = SetPowerStatus { name: "ana".to_string() };
+        #[cfg(feature = "fixed-name")]
+        let set_power_status = SetPowerStatus::new("ana").unwrap();
+        let instruction_data = PowerInstruction::Switch { set_power_status }.pack().unwrap();
+
+        assert_eq!(
+            process_instruction(&program_id, &[power.clone()], &instruction_data),
+            Err(PowerError::CooldownActive.into())
+        );
+        let power_status = PowerStatus::from_account_info(&program_id, &power).unwrap();
+        assert!(power_status.is_on());
+        assert_eq!(power_status.toggle_count, 0);
+    }
+
+    #[test]
+    fn test_execute_scheduled_before_it_is_due_is_rejected() {
+        let _syscalls = record_syscalls();
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut data = PowerStatus { scheduled_toggle_at: 2_000, ..PowerStatus::default() }.pack().unwrap();
+        let mut lamports = 1;
+        let power = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        let instruction_data = PowerInstruction::ExecuteScheduled.pack().unwrap();
+
+        assert_eq!(
+            process_instruction(&program_id, &[power.clone()], &instruction_data),
+            Err(PowerError::ScheduleNotDue.into())
+        );
+        let power_status = PowerStatus::from_account_info(&program_id, &power).unwrap();
+        assert!(!power_status.is_on());
+        assert_eq!(power_status.scheduled_toggle_at, 2_000);
+    }
+
+    #[test]
+    fn test_check_rent_exempt() {
+        let rent = Rent::default();
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut data = vec![0; 32];
+        let mut lamports = rent.minimum_balance(data.len());
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        assert!(check_rent_exempt(&rent, &account).is_ok());
+
+        **account.try_borrow_mut_lamports().unwrap() = 1;
+        assert_eq!(
+            check_rent_exempt(&rent, &account),
+            Err(PowerError::NotRentExempt.into())
+        );
+    }
+
+    #[test]
+    fn test_rent_top_up_keeps_grown_account_exempt() {
+        let rent = Rent::default();
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut data = vec![0; 64];
+        let mut lamports = rent.minimum_balance(32);
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0);
+        assert!(check_rent_exempt(&rent, &account).is_err());
+
+        let top_up = rent_top_up(&rent, account.lamports(), account.data_len());
+        assert!(top_up > 0);
+        **account.try_borrow_mut_lamports().unwrap() += top_up;
+        assert!(check_rent_exempt(&rent, &account).is_ok());
+        assert_eq!(rent_top_up(&rent, account.lamports(), account.data_len()), 0);
+    }
+
+    #[test]
+    fn test_switch_power_rejects_foreign_owned_account() {
+        let program_id = Pubkey::new_unique();
+        let foreign_program = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut data = PowerStatus::default().try_to_vec().unwrap();
+        let mut lamports = 1;
+        let power = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &foreign_program, false, 0);
+
+        assert_eq!(
+            switch_power(&program_id, &[power], "mallory".to_string()),
+            Err(ProgramError::IncorrectProgramId)
+        );
+    }
+
+    #[test]
+    fn test_from_account_info() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut data = PowerStatus { is_on: true, threshold: 1, ..PowerStatus::default() }.pack().unwrap();
+        let mut lamports = 1;
+        let power = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        let power_status = PowerStatus::from_account_info(&program_id, &power).unwrap();
+        assert!(power_status.is_on());
+        assert_eq!(power_status.threshold, 1);
+
+        assert_eq!(
+            PowerStatus::from_account_info(&Pubkey::new_unique(), &power).unwrap_err(),
+            ProgramError::IncorrectProgramId
+        );
+
+        let mut lamports = 1;
+        let empty = AccountInfo::new(&key, false, true, &mut lamports, &mut [], &program_id, false, 0);
+        assert_eq!(
+            PowerStatus::from_account_info(&program_id, &empty).unwrap_err(),
+            ProgramError::UninitializedAccount
+        );
+    }
+
+    #[test]
+    fn test_corrupted_power_status_is_rejected() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut data = PowerStatus { is_on: true, authorities: vec![key], threshold: 1, ..PowerStatus::default() }
+            .pack()
+            .unwrap();
+        data[5] ^= 0xff;
+        let mut lamports = 1;
+        let power = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+
+        assert_eq!(
+            PowerStatus::from_account_info(&program_id, &power).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[test]
+    fn test_next_named_logs_missing_account() {
+        let _syscalls = record_syscalls();
+
+        let accounts: [AccountInfo; 0] = [];
+        assert_eq!(
+            initialize(&Pubkey::new_unique(), &accounts, PowerStatus::default()).unwrap_err(),
+            ProgramError::NotEnoughAccountKeys
+        );
+        assert!(LOGS.lock().unwrap().iter().any(|log| log == "missing account: power"));
+    }
+
+    #[test]
+    fn test_batch_initializes_then_switches() {
+        let _syscalls = record_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (power_key, user_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let system_program_id = system_program::id();
+        let (mut power_lamports, mut user_lamports, mut system_lamports) = (0, 1_000_000_000, 0);
+        let accounts = [
+            AccountInfo::new(&power_key, true, true, &mut power_lamports, &mut [], &program_id, false, 0),
+            AccountInfo::new(&user_key, true, true, &mut user_lamports, &mut [], &system_program_id, false, 0),
+            AccountInfo::new(&system_program_id, false, false, &mut system_lamports, &mut [], &system_program_id, true, 0),
+        ];
+        #[cfg(not(feature = "fixed-name"))]
+        let switch = SetPowerStatus { name: "ana".to_string() };
+        #[cfg(feature = "fixed-name")]
+        let switch = SetPowerStatus::new("ana").unwrap();
+        let batch = PowerInstruction::Batch {
+            instructions: vec![
+                BatchEntry { account_offset: 0, data: PowerStatus::default().try_to_vec().unwrap() },
+                BatchEntry { account_offset: 0, data: switch.try_to_vec().unwrap() },
+            ],
+        };
+
+        assert!(process_instruction(&program_id, &accounts, &batch.pack().unwrap()).is_ok());
+        let power_status = PowerStatus::from_account_info(&program_id, &accounts[0]).unwrap();
+        assert!(power_status.is_on());
+        assert_eq!(power_status.toggle_count, 1);
+    }
+
+    fn round_trip(instruction: PowerInstruction) -> PowerInstruction {
+        PowerInstruction::unpack(&instruction.pack().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_power_instruction_round_trip() {
+        assert!(matches!(round_trip(PowerInstruction::ScheduleToggle { at: -7 }), PowerInstruction::ScheduleToggle { at: -7 }));
+        assert!(matches!(round_trip(PowerInstruction::ExecuteScheduled), PowerInstruction::ExecuteScheduled));
+        assert!(matches!(round_trip(PowerInstruction::GetVersion), PowerInstruction::GetVersion));
+        assert!(matches!(round_trip(PowerInstruction::QueryMany), PowerInstruction::QueryMany));
+        assert!(matches!(round_trip(PowerInstruction::Migrate), PowerInstruction::Migrate));
+
+        let power_status = PowerStatus { is_on: true, authorities: vec![Pubkey::new_unique()], threshold: 1, log_level: LOG_VERBOSE, ..PowerStatus::default() };
+        match round_trip(PowerInstruction::Initialize { power_status }) {
+            PowerInstruction::Initialize { power_status: decoded } => {
+                assert!(decoded.is_on());
+                assert_eq!(decoded.authorities.len(), 1);
+                assert_eq!(decoded.threshold, 1);
+                assert_eq!(decoded.log_level, LOG_VERBOSE);
+            }
+            other => panic!("decoded {:?}", other),
+        }
+
+        #[cfg(not(feature = "fixed-name"))]
+        let switch = SetPowerStatus { name: "Zoë ⚡ 電源".to_string() };
+        #[cfg(feature = "fixed-name")]
+        let switch = SetPowerStatus::new("Zoë ⚡ 電源").unwrap();
+        let batch = PowerInstruction::Batch {
+            instructions: vec![BatchEntry { account_offset: 1, data: switch.try_to_vec().unwrap() }],
+        };
+        match round_trip(PowerInstruction::Switch { set_power_status: switch }) {
+            PowerInstruction::Switch { set_power_status } => assert_eq!(set_power_status.name().unwrap(), "Zoë ⚡ 電源"),
+            other => panic!("decoded {:?}", other),
+        }
+
+        match round_trip(batch) {
+            PowerInstruction::Batch { instructions } => {
+                assert_eq!(instructions.len(), 1);
+                assert_eq!(instructions[0].account_offset, 1);
+                let decoded = SetPowerStatus::try_from_slice(&instructions[0].data).unwrap();
+                assert_eq!(decoded.name().unwrap(), "Zoë ⚡ 電源");
+            }
+            other => panic!("decoded {:?}", other),
+        }
+
+        #[cfg(feature = "debug")]
+        assert!(matches!(round_trip(PowerInstruction::ForceOff), PowerInstruction::ForceOff));
+
+        assert_eq!(PowerInstruction::unpack(&[9]).unwrap_err(), ProgramError::InvalidInstructionData);
+    }
+
+    #[test]
+    fn test_original_power_status_payloads_are_not_initialize() {
+        let accounts: [AccountInfo; 0] = [];
+        assert_eq!(
+            process_instruction(&Pubkey::new_unique(), &accounts, &[0]).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+        assert!(PowerStatus::try_from_slice(&[1]).is_err());
+        assert!(matches!(PowerInstruction::unpack(&[1]), Ok(PowerInstruction::ExecuteScheduled)));
+    }
+
+    #[test]
+    fn test_stored_bump_regenerates_pda() {
+        let program_id = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let (pda, bump) = PowerStatus::find_address(&program_id, &user);
+        let power_status = PowerStatus { bump, ..PowerStatus::default() };
+
+        assert_eq!(power_status.address(&program_id, &user).unwrap(), pda);
+    }
+
+    #[test]
+    fn test_get_version() {
+        let _syscalls = record_syscalls();
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut data = PowerStatus { version: POWER_STATUS_VERSION, ..PowerStatus::default() }.pack().unwrap();
+        let mut lamports = 1;
+        let power = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &program_id, false, 0);
+        let instruction_data = PowerInstruction::GetVersion.pack().unwrap();
+
+        assert!(process_instruction(&program_id, &[power], &instruction_data).is_ok());
+        assert_eq!(*RETURN_DATA.lock().unwrap(), vec![POWER_STATUS_VERSION]);
+    }
+
+    // version 1 had no toggle_count after log_level
+    fn packed_v1(power_status: &PowerStatus) -> Vec<u8> {
+        let mut data = power_status.try_to_vec().unwrap();
+        data.truncate(data.len() - 8);
+        data.push(checksum(&data));
+        data
+    }
+
+    // AccountInfo::realloc reads the original data length from the 4 bytes before the key and writes
+    //  the new length to the 8 bytes before the data, where the runtime keeps them. ReallocAccount
+    //  lays an account out the same way so a host test can realloc it up to capacity bytes
+    struct ReallocAccount {
+        key: Vec<u8>,
+        data: Vec<u8>,
+        len: usize,
+    }
+
+    impl ReallocAccount {
+        fn new(key: &Pubkey, data: &[u8], capacity: usize) -> Self {
+            let mut key_buf = (data.len() as u32).to_le_bytes().to_vec();
+            key_buf.extend_from_slice(key.as_ref());
+            let mut data_buf = (data.len() as u64).to_le_bytes().to_vec();
+            data_buf.extend_from_slice(data);
+            data_buf.resize(8 + capacity, 0);
+            ReallocAccount { key: key_buf, data: data_buf, len: data.len() }
+        }
+
+        fn info<'a>(&'a mut self, lamports: &'a mut u64, owner: &'a Pubkey) -> AccountInfo<'a> {
+            let key = unsafe { &*(self.key.as_ptr().add(4) as *const Pubkey) };
+            let data = unsafe { std::slice::from_raw_parts_mut(self.data.as_mut_ptr().add(8), self.len) };
+            AccountInfo::new(key, false, true, lamports, data, owner, false, 0)
+        }
+    }
+
+    #[test]
+    fn test_grow_power_account_reallocs_and_tops_up() {
+        let _syscalls = record_syscalls();
+        let rent = Rent::default();
+        let (key, payer_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (owner, system_program_id) = (Pubkey::new_unique(), system_program::id());
+        let mut account = ReallocAccount::new(&key, &[1; 32], 64);
+        let mut lamports = rent.minimum_balance(32);
+        let top_up = rent_top_up(&rent, lamports, 64);
+        let (mut payer_lamports, mut system_lamports) = (top_up - 1, 0);
+        let power = account.info(&mut lamports, &owner);
+        let payer = AccountInfo::new(&payer_key, true, true, &mut payer_lamports, &mut [], &system_program_id, false, 0);
+        let system_program = AccountInfo::new(&system_program_id, false, false, &mut system_lamports, &mut [], &system_program_id, true, 0);
+
+        assert_eq!(grow_power_account(&power, &payer, &system_program, 64), Err(ProgramError::InsufficientFunds));
+        assert_eq!(power.data_len(), 32);
+
+        **payer.try_borrow_mut_lamports().unwrap() += 1;
+        assert!(grow_power_account(&power, &payer, &system_program, 64).is_ok());
+        assert_eq!(power.data_len(), 64);
+        assert_eq!(payer.lamports(), 0);
+        assert_eq!(power.lamports(), rent.minimum_balance(64));
+        assert_eq!(&power.data.borrow()[..32], &[1; 32]);
+    }
+
+    #[test]
+    fn test_version_1_account_is_read_then_migrated() {
+        let _syscalls = record_syscalls();
+        let program_id = Pubkey::new_unique();
+        let (key, payer_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let system_program_id = system_program::id();
+        let v1 = PowerStatus { is_on: true, version: 1, log_level: LOG_VERBOSE, ..PowerStatus::default() };
+        let v1_data = packed_v1(&v1);
+        let mut account = ReallocAccount::new(&key, &v1_data, v1_data.len() + 8);
+        let mut lamports = Rent::default().minimum_balance(v1_data.len());
+        let (mut payer_lamports, mut system_lamports) = (1_000_000_000, 0);
+        let accounts = [
+            account.info(&mut lamports, &program_id),
+            AccountInfo::new(&payer_key, true, true, &mut payer_lamports, &mut [], &system_program_id, false, 0),
+            AccountInfo::new(&system_program_id, false, false, &mut system_lamports, &mut [], &system_program_id, true, 0),
+        ];
+
+        let power_status = PowerStatus::from_account_info(&program_id, &accounts[0]).unwrap();
+        assert!(power_status.is_on());
+        assert_eq!(power_status.version, 1);
+        assert_eq!(power_status.log_level, LOG_VERBOSE);
+        assert_eq!(power_status.toggle_count, 0);
+        assert_eq!(power_status.save(&accounts[0]), Err(ProgramError::AccountDataTooSmall));
+
+        let instruction_data = PowerInstruction::Migrate.pack().unwrap();
+        assert!(process_instruction(&program_id, &accounts, &instruction_data).is_ok());
+        assert_eq!(accounts[0].data_len(), v1_data.len() + 8);
+        assert_eq!(PowerStatus::stored_version(&program_id, &accounts[0]).unwrap(), POWER_STATUS_VERSION);
+        let power_status = PowerStatus::from_account_info(&program_id, &accounts[0]).unwrap();
+        assert!(power_status.is_on());
+        assert_eq!(power_status.log_level, LOG_VERBOSE);
+        assert!(Rent::default().is_exempt(accounts[0].lamports(), accounts[0].data_len()));
+    }
+
+    #[test]
+    fn test_get_version_reads_an_older_layout() {
+        let _syscalls = record_syscalls();
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut data = packed_v1(&PowerStatus { version: 1, authorities: vec![key, key], threshold: 1, ..PowerStatus::default() });
+        let mut lamports = 1;
+        let power = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &program_id, false, 0);
+
+        let instruction_data = PowerInstruction::GetVersion.pack().unwrap();
+        assert!(process_instruction(&program_id, &[power], &instruction_data).is_ok());
+        assert_eq!(*RETURN_DATA.lock().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_query_many() {
+        let _syscalls = record_syscalls();
+        let program_id = Pubkey::new_unique();
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut data: Vec<Vec<u8>> = [true, false, true]
+            .iter()
+            .map(|&is_on| PowerStatus { is_on, ..PowerStatus::default() }.pack().unwrap())
+            .collect();
+        let mut lamports = [1, 1, 1];
+        let powers: Vec<AccountInfo> = keys
+            .iter()
+            .zip(data.iter_mut())
+            .zip(lamports.iter_mut())
+            .map(|((key, data), lamports)| AccountInfo::new(key, false, false, lamports, data, &program_id, false, 0))
+            .collect();
+        let instruction_data = PowerInstruction::QueryMany.pack().unwrap();
+
+        assert!(process_instruction(&program_id, &powers, &instruction_data).is_ok());
+        let statuses = Vec::<(Pubkey, bool)>::try_from_slice(&RETURN_DATA.lock().unwrap()).unwrap();
+        assert_eq!(statuses, vec![(keys[0], true), (keys[1], false), (keys[2], true)]);
+
+        let too_many = vec![powers[0].clone(); MAX_QUERY_DEVICES + 1];
+        assert_eq!(
+            process_instruction(&program_id, &too_many, &instruction_data),
+            Err(PowerError::TooManyDevices.into())
+        );
+    }
+
+    #[test]
+    fn test_split_tagged() {
+        let power_status = PowerStatus { is_on: true, threshold: 2, ..PowerStatus::default() };
+        let mut data = vec![7];
+        data.extend(power_status.try_to_vec().unwrap());
+
+        let (tag, decoded) = split_tagged::<PowerStatus>(&data).unwrap();
+        assert_eq!(tag, 7);
+        assert!(decoded.is_on());
+        assert_eq!(decoded.threshold, 2);
+
+        assert_eq!(split_tagged::<PowerStatus>(&[]).unwrap_err(), ProgramError::InvalidInstructionData);
+        assert_eq!(split_tagged::<PowerStatus>(&data[..3]).unwrap_err(), ProgramError::InvalidInstructionData);
+    }
+
+    #[test]
+    fn test_switch_log_lines_per_level() {
+        let lines = |log_level| {
+            let power_status = PowerStatus { is_on: true, log_level, ..PowerStatus::default() };
+            switch_log_lines(&power_status, "ana").len()
+        };
+
+        assert_eq!(lines(LOG_SILENT), 0);
+        assert_eq!(lines(LOG_NORMAL), 2);
+        assert_eq!(lines(LOG_VERBOSE), 3);
+    }
+
+    #[test]
+    fn test_check_name_boundary() {
+        assert!(check_name(&"a".repeat(MAX_NAME_LEN)).is_ok());
+        assert_eq!(check_name(&"a".repeat(MAX_NAME_LEN + 1)), Err(PowerError::NameTooLong.into()));
+    }
+
+    #[cfg(not(feature = "saturating-counters"))]
+    #[test]
+    fn test_toggle_count_errors_at_max() {
+        let mut power_status = PowerStatus { toggle_count: u64::MAX - 1, ..PowerStatus::default() };
+
+        assert!(count_toggle(&mut power_status).is_ok());
+        assert_eq!(power_status.toggle_count, u64::MAX);
+        assert_eq!(count_toggle(&mut power_status), Err(PowerError::ToggleCountOverflow.into()));
+        assert_eq!(power_status.toggle_count, u64::MAX);
+    }
+
+    #[cfg(feature = "saturating-counters")]
+    #[test]
+    fn test_toggle_count_saturates_at_max() {
+        let mut power_status = PowerStatus { toggle_count: u64::MAX - 1, ..PowerStatus::default() };
+
+        assert!(count_toggle(&mut power_status).is_ok());
+        assert!(count_toggle(&mut power_status).is_ok());
+        assert_eq!(power_status.toggle_count, u64::MAX);
+    }
+
+    #[test]
+    fn test_switch_power_logs_a_truncated_name() {
+        let _syscalls = record_syscalls();
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut data = PowerStatus { log_level: LOG_NORMAL, ..PowerStatus::default() }.pack().unwrap();
+        let mut lamports = 1;
+        let power = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        let name = "a".repeat(MAX_NAME_LEN);
+
+        assert!(switch_power(&program_id, &[power], name.clone()).is_ok());
+        let logs = LOGS.lock().unwrap();
+        let expected = format!("{} is pulling the power switch!", &name[..MAX_LOG_NAME]);
+        assert!(logs.iter().any(|log| *log == expected));
+        assert!(!logs.iter().any(|log| log.contains(&name)));
+    }
+
+    #[test]
+    fn test_log_name_truncates_long_names() {
+        let long_name = "é".repeat(MAX_LOG_NAME + 5);
+
+        assert_eq!(log_name(&long_name).chars().count(), MAX_LOG_NAME);
+        assert_eq!(log_name("alice"), "alice");
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn test_force_off_needs_no_signer() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut data = PowerStatus { is_on: true, authorities: vec![Pubkey::new_unique()], threshold: 1, ..PowerStatus::default() }
+            .pack()
+            .unwrap();
+        let mut lamports = 1;
+        let power = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+        let instruction_data = PowerInstruction::ForceOff.try_to_vec().unwrap();
+
+        assert!(process_instruction(&program_id, &[power.clone()], &instruction_data).is_ok());
+        assert!(!PowerStatus::from_account_info(&program_id, &power).unwrap().is_on());
+    }
+
+    #[cfg(not(feature = "debug"))]
+    #[test]
+    fn test_force_off_is_absent_from_release_builds() {
+        // ForceOff would be the variant after Migrate
+        assert!(PowerInstruction::try_from_slice(&[8]).is_err());
+    }
+
+    #[cfg(feature = "fixed-name")]
+    #[test]
+    fn test_fixed_name_round_trip() {
+        for name in ["bob", "abcdefghijklmnopqrstuvwxyz012345"] {
+            let data = SetPowerStatus::new(name).unwrap().try_to_vec().unwrap();
+            assert_eq!(data.len(), 1 + FIXED_NAME_LEN);
+
+            let set_power_status = SetPowerStatus::try_from_slice(&data).unwrap();
+            assert_eq!(set_power_status.name().unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_power_status_display() {
+        let mut power_status = PowerStatus::default();
+        assert_eq!(power_status.to_string(), "OFF");
+
+        power_status.is_on = true;
+        assert!(power_status.is_on());
+        assert_eq!(power_status.to_string(), "ON");
+    }
+
+    #[test]
+    fn test_empty_instruction_data_is_rejected() {
+        assert_eq!(
+            process_instruction(&Pubkey::new_unique(), &[], &[]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+}
@@ -1,11 +1,21 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    account_info::AccountInfo, 
+    account_info::AccountInfo,
+    clock::Clock,
     entrypoint, 
     entrypoint::ProgramResult, 
     msg, 
-    pubkey::Pubkey,
+    program::{invoke_signed, set_return_data},
+    program_error::ProgramError,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    rent::Rent,
+    system_instruction,
+    sysvar::Sysvar,
 };
+use thiserror::Error;
+
+mod common;
+use common::{next_named, MAX_NAME_LEN};
 
 
 entrypoint!(process_instruction);
@@ -17,17 +27,310 @@ fn process_instruction(
     instruction_data: &[u8],
 ) -> ProgramResult {
 
+    if instruction_data.is_empty() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    // A length-prefixed list of visitors is admitted as a batch
+    //
+    if let Ok(visitors) = unpack_batch(instruction_data) {
+        return admit_batch(program_id, accounts, visitors);
+    }
+
+    // Ride management instructions are a Borsh tag and its fields, far
+    //  shorter than any visitor or batch
+    //
+    match ParkInstruction::try_from_slice(instruction_data) {
+        Ok(ParkInstruction::InitRide { max_capacity }) => return init_ride(program_id, accounts, max_capacity),
+        Ok(ParkInstruction::Exit) => return exit_ride(program_id, accounts),
+        Ok(ParkInstruction::CleanupExpired) => return cleanup_expired(program_id, accounts),
+        Err(_) => {},
+    }
+
     // Attempt to serialize the BPF format to our struct
     //  using Borsh
     //
-    let instruction_data_object = InstructionData::try_from_slice(&instruction_data)?;
+    let instruction_data_object = unpack_visitor(instruction_data)?;
+    check_name(&instruction_data_object.name)?;
+    check_height(instruction_data_object.height)?;
+
+    let decision = greet(&instruction_data_object);
+    set_return_data(&decision.try_to_vec()?);
+
+    Ok(())
+}
+
+// Shortest height allowed on the ride in cm, inclusive: a visitor exactly this tall rides
+//
+pub const MIN_RIDE_HEIGHT: u32 = 120;
+
+pub fn decide(visitor: &InstructionData) -> RideDecision {
+    if visitor.height >= MIN_RIDE_HEIGHT {
+        RideDecision {
+            can_ride: true,
+            reason: REASON_OK,
+        }
+    } else {
+        RideDecision {
+            can_ride: false,
+            reason: REASON_TOO_SHORT,
+        }
+    }
+}
+
+pub fn unpack_visitor(instruction_data: &[u8]) -> Result<InstructionData, ProgramError> {
+    InstructionData::try_from_slice(instruction_data).map_err(|_| {
+        msg!("Could not decode a visitor from {} bytes.", instruction_data.len());
+        ParkError::DeserializationFailed.into()
+    })
+}
+
+// Heights in cm, anything outside is a unit mistake or garbage data
+//
+pub const MIN_HEIGHT: u32 = 1;
+pub const MAX_HEIGHT: u32 = 300;
+
+pub fn check_name(name: &str) -> ProgramResult {
+    if name.len() > MAX_NAME_LEN {
+        msg!("A name of {} bytes is too long.", name.len());
+        return Err(ParkError::NameTooLong.into());
+    }
+    Ok(())
+}
 
-    msg!("Welcome to the park, {}!", instruction_data_object.name);
-    if instruction_data_object.height > 5 {
+pub fn check_height(height: u32) -> ProgramResult {
+    if !(MIN_HEIGHT..=MAX_HEIGHT).contains(&height) {
+        msg!("A height of {} cm is not plausible.", height);
+        return Err(ParkError::ImplausibleHeight.into());
+    }
+    Ok(())
+}
+
+fn greet(visitor: &InstructionData) -> RideDecision {
+    msg!("Welcome to the park, {}!", visitor.name);
+    let decision = decide(visitor);
+    if decision.can_ride {
         msg!("You are tall enough to ride this ride. Congratulations.");
     } else {
         msg!("You are NOT tall enough to ride this ride. Sorry mate.");
     };
+    decision
+}
+
+// Keeps a batch well inside the compute budget, each visitor costs a
+//  create_account CPI
+//
+pub const MAX_BATCH_SIZE: usize = 8;
+
+pub fn unpack_batch(instruction_data: &[u8]) -> Result<Vec<InstructionData>, ProgramError> {
+    let visitors = Vec::<InstructionData>::try_from_slice(instruction_data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+
+    if visitors.is_empty() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    if visitors.len() > MAX_BATCH_SIZE {
+        return Err(ParkError::BatchTooLarge.into());
+    }
+
+    Ok(visitors)
+}
+
+fn admit_batch(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    visitors: Vec<InstructionData>,
+) -> ProgramResult {
+
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_named(accounts_iter, "payer")?;
+    let system_program = next_named(accounts_iter, "system_program")?;
+    let visitor_accounts = visitors
+        .iter()
+        .map(|_| next_named(accounts_iter, "visitor"))
+        .collect::<Result<Vec<_>, _>>()?;
+    let ride_account = next_named(accounts_iter, "ride")?;
+
+    let mut ride = Ride::load(program_id, ride_account)?;
+    for (visitor, visitor_account) in visitors.iter().zip(visitor_accounts) {
+        check_name(&visitor.name)?;
+        check_height(visitor.height)?;
+        let decision = greet(visitor);
+        if decision.can_ride {
+            ride.admit()?;
+        }
+        write_visitor(program_id, payer, system_program, visitor_account, visitor, decision.can_ride, ride_account.key)?;
+    }
+    ride.serialize(&mut &mut ride_account.data.borrow_mut()[..])?;
+
+    Ok(())
+}
+
+fn init_ride<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    max_capacity: u32,
+) -> ProgramResult {
+
+    let accounts_iter = &mut accounts.iter();
+    let authority = next_named(accounts_iter, "authority")?;
+    let ride_account = next_named(accounts_iter, "ride")?;
+    let system_program = next_named(accounts_iter, "system_program")?;
+
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let (ride_pda, bump_seed) = Ride::find_address(program_id, authority.key);
+    if ride_pda != *ride_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !ride_account.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let ride = Ride {
+        current_occupancy: 0,
+        max_capacity,
+        authority: *authority.key,
+    };
+    let account_span = (ride.try_to_vec()?).len();
+    invoke_signed(
+        &system_instruction::create_account(
+            authority.key,
+            ride_account.key,
+            (Rent::get()?).minimum_balance(account_span),
+            account_span as u64,
+            program_id,
+        ),
+        &[authority.clone(), ride_account.clone(), system_program.clone()],
+        &[&[b"ride", authority.key.as_ref(), &[bump_seed]]],
+    )?;
+
+    ride.serialize(&mut &mut ride_account.data.borrow_mut()[..])?;
+
+    Ok(())
+}
+
+fn exit_ride(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+
+    let accounts_iter = &mut accounts.iter();
+    let ride_account = next_named(accounts_iter, "ride")?;
+    let visitor_account = next_named(accounts_iter, "visitor")?;
+    let signer = next_named(accounts_iter, "signer")?;
+
+    let mut ride = Ride::load(program_id, ride_account)?;
+    let mut record = Visitor::load(program_id, visitor_account)?;
+    if !signer.is_signer || (*signer.key != record.holder && *signer.key != ride.authority) {
+        msg!("Only the ticket holder or the ride authority can take {} off the ride.", record.name);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    record.leave_ride(ride_account.key)?;
+    ride.exit()?;
+    ride.serialize(&mut &mut ride_account.data.borrow_mut()[..])?;
+    record.serialize(&mut &mut visitor_account.data.borrow_mut()[..])?;
+
+    Ok(())
+}
+
+// Anyone may close an expired ticket, its rent goes back to the holder
+//  who paid for it
+//
+fn cleanup_expired(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+
+    let accounts_iter = &mut accounts.iter();
+    let visitor_account = next_named(accounts_iter, "visitor")?;
+    let holder = next_named(accounts_iter, "holder")?;
+
+    let record = Visitor::load(program_id, visitor_account)?;
+    if record.holder != *holder.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if record.ride != Pubkey::default() {
+        msg!("{} has to exit the ride first.", record.name);
+        return Err(ParkError::OnRide.into());
+    }
+    if !record.is_expired(Clock::get()?.unix_timestamp) {
+        msg!("The ticket of {} has not expired yet.", record.name);
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    **holder.lamports.borrow_mut() = holder
+        .lamports()
+        .checked_add(visitor_account.lamports())
+        .ok_or(ProgramError::InvalidArgument)?;
+    **visitor_account.lamports.borrow_mut() = 0;
+    visitor_account.data.borrow_mut().fill(0);
+
+    Ok(())
+}
+
+fn write_visitor<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    visitor_account: &AccountInfo<'a>,
+    visitor: &InstructionData,
+    can_ride: bool,
+    ride: &Pubkey,
+) -> ProgramResult {
+
+    if visitor.name.len() > MAX_SEED_LEN {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (visitor_pda, bump_seed) =
+        Pubkey::find_program_address(&[b"visitor", visitor.name.as_bytes()], program_id);
+    if visitor_pda != *visitor_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let mut record = if visitor_account.data_is_empty() {
+        Visitor {
+            name: visitor.name.clone(),
+            height: visitor.height,
+            can_ride,
+            rides_taken: 0,
+            max_rides: DEFAULT_MAX_RIDES,
+            holder: *payer.key,
+            expiry: now.saturating_add(TICKET_VALIDITY),
+            ride: Pubkey::default(),
+        }
+    } else {
+        if visitor_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+        let mut record = Visitor::try_from_slice(&visitor_account.data.borrow())?;
+        record.check_not_expired(now)?;
+        record.height = visitor.height;
+        record.can_ride = can_ride;
+        record
+    };
+    if can_ride {
+        record.take_ride(ride)?;
+    }
+    let account_span = (record.try_to_vec()?).len();
+
+    if visitor_account.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                visitor_account.key,
+                (Rent::get()?).minimum_balance(account_span),
+                account_span as u64,
+                program_id,
+            ),
+            &[payer.clone(), visitor_account.clone(), system_program.clone()],
+            &[&[b"visitor", record.name.as_bytes(), &[bump_seed]]],
+        )?;
+    }
+
+    record.serialize(&mut &mut visitor_account.data.borrow_mut()[..])?;
 
     Ok(())
 }
@@ -37,11 +340,628 @@ pub struct InstructionData {
     name: String,
     height: u32,
 }
+
+pub const REASON_OK: u8 = 0;
+pub const REASON_TOO_SHORT: u8 = 1;
+// Reserved until the instruction data carries an age
+pub const REASON_TOO_YOUNG: u8 = 2;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub struct RideDecision {
+    pub can_ride: bool,
+    pub reason: u8,
+}
+
+// Size of the ticket book a new visitor record starts with
+pub const DEFAULT_MAX_RIDES: u8 = 3;
+// Seconds a new ticket book stays valid for
+pub const TICKET_VALIDITY: i64 = 24 * 60 * 60;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct Visitor {
+    pub name: String,
+    pub height: u32,
+    pub can_ride: bool,
+    pub rides_taken: u8,
+    pub max_rides: u8,
+    pub holder: Pubkey,
+    pub expiry: i64,
+    // The ride the visitor is on, Pubkey::default() when on none
+    pub ride: Pubkey,
+}
+
+impl Visitor {
+    pub fn load(program_id: &Pubkey, visitor_account: &AccountInfo) -> Result<Visitor, ProgramError> {
+        if visitor_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+        let record = Visitor::try_from_slice(&visitor_account.data.borrow())?;
+        if *visitor_account.key != Pubkey::find_program_address(&[b"visitor", record.name.as_bytes()], program_id).0 {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Ok(record)
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.expiry
+    }
+
+    pub fn check_not_expired(&self, now: i64) -> ProgramResult {
+        if self.is_expired(now) {
+            msg!("The ticket of {} has expired.", self.name);
+            return Err(ParkError::TicketExpired.into());
+        }
+        Ok(())
+    }
+
+    pub fn take_ride(&mut self, ride: &Pubkey) -> ProgramResult {
+        if self.ride != Pubkey::default() {
+            msg!("{} is already on a ride.", self.name);
+            return Err(ParkError::OnRide.into());
+        }
+        if self.rides_taken >= self.max_rides {
+            msg!("{} has no rides left.", self.name);
+            return Err(ParkError::NoRidesLeft.into());
+        }
+        self.rides_taken = self
+            .rides_taken
+            .checked_add(1)
+            .ok_or(ParkError::NoRidesLeft)?;
+        self.ride = *ride;
+        Ok(())
+    }
+
+    pub fn leave_ride(&mut self, ride: &Pubkey) -> ProgramResult {
+        if self.ride != *ride {
+            msg!("{} is not on this ride.", self.name);
+            return Err(ParkError::NotOnRide.into());
+        }
+        self.ride = Pubkey::default();
+        Ok(())
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub enum ParkInstruction {
+    InitRide { max_capacity: u32 },
+    Exit,
+    CleanupExpired,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug)]
+pub struct Ride {
+    pub current_occupancy: u32,
+    pub max_capacity: u32,
+    pub authority: Pubkey,
+}
+
+impl Ride {
+    pub fn find_address(program_id: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"ride", authority.as_ref()], program_id)
+    }
+
+    pub fn load(program_id: &Pubkey, ride_account: &AccountInfo) -> Result<Ride, ProgramError> {
+        if ride_account.owner != program_id {
+            return Err(ProgramError::IllegalOwner);
+        }
+        let ride = Ride::try_from_slice(&ride_account.data.borrow())?;
+        if *ride_account.key != Ride::find_address(program_id, &ride.authority).0 {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Ok(ride)
+    }
+
+    pub fn admit(&mut self) -> ProgramResult {
+        if self.current_occupancy >= self.max_capacity {
+            msg!("The ride is full ({} riders).", self.max_capacity);
+            return Err(ParkError::RideFull.into());
+        }
+        self.current_occupancy = self
+            .current_occupancy
+            .checked_add(1)
+            .ok_or(ParkError::RideFull)?;
+        Ok(())
+    }
+
+    pub fn exit(&mut self) -> ProgramResult {
+        self.current_occupancy = self
+            .current_occupancy
+            .checked_sub(1)
+            .ok_or(ParkError::RideEmpty)?;
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, Copy, Clone)]
+pub enum ParkError {
+    #[error("Too many visitors in one batch")]
+    BatchTooLarge,
+    #[error("Visitor has no rides left")]
+    NoRidesLeft,
+    #[error("Visitor height is outside the plausible range")]
+    ImplausibleHeight,
+    #[error("Instruction data is not a visitor")]
+    DeserializationFailed,
+    #[error("Ride is at capacity")]
+    RideFull,
+    #[error("Nobody is on the ride")]
+    RideEmpty,
+    #[error("Ticket has expired")]
+    TicketExpired,
+    #[error("Visitor name is too long")]
+    NameTooLong,
+    #[error("Visitor is already on a ride")]
+    OnRide,
+    #[error("Visitor is not on this ride")]
+    NotOnRide,
+}
+
+impl From<ParkError> for ProgramError {
+    fn from(e: ParkError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
 /*
 
 There is an entrypoint process_instruction which takes a Pubkey, a slice of AccountInfo, and a slice of u8 as arguments, and returns a ProgramResult.
 The instruction data received in the instruction_data argument is deserialized into an InstructionData struct using Borsh's try_from_slice method.
 If the deserialization is successful, a message is printed to the logs, indicating the name of the person and if they are tall enough to ride a ride based on their height.
-If the deserialization fails, an error with the message ProgramError::InvalidInstructionData is returned.
+A visitor is tall enough when their height is at least MIN_RIDE_HEIGHT, the boundary itself included. Like every height in the park it is in cm.
+If the deserialization fails, unpack_visitor logs the length of the data and returns ParkError::DeserializationFailed.
+A name longer than MAX_NAME_LEN bytes is rejected with ParkError::NameTooLong.
+A height outside MIN_HEIGHT..=MAX_HEIGHT cm is rejected with ParkError::ImplausibleHeight, for a single visitor as well as for every visitor of a batch.
+This is checked before decide, so a height of 0 (usually a client or serialization bug) fails instead of being answered with REASON_TOO_SHORT.
+The outcome is also returned to the caller as a Borsh RideDecision through set_return_data, where reason is one of the REASON_* codes.
+
+The instruction data can also be a Borsh Vec of InstructionData (a u32 length followed by the visitors), capped at MAX_BATCH_SIZE.
+For a batch the accounts are the payer, the system program and one visitor PDA per visitor, seeded with "visitor" and the visitor's name.
+Each visitor is greeted as above and its Visitor record is written to its PDA, creating the account if needed. Any failure aborts the whole batch.
+A Visitor record works like a ticket book: every admitted ride increments rides_taken, and once it reaches max_rides (DEFAULT_MAX_RIDES for a new record) the admission fails with ParkError::NoRidesLeft.
+
+A batch also takes the ride account after the visitor accounts, the PDA seeded with "ride" and the ride's authority. Every visitor tall enough to ride takes a seat: current_occupancy goes up by one,
+and once it reaches max_capacity the whole batch fails with ParkError::RideFull. The seated visitor's record stores the ride in ride, and a visitor already on a ride fails with ParkError::OnRide.
+The ride is created by ParkInstruction::InitRide (accounts: authority, who signs and pays, ride PDA, system program). Ride::load checks the account is the PDA of the authority stored in the ride.
+ParkInstruction::Exit (accounts: ride PDA, visitor PDA, signer) takes the visitor off the ride and frees a seat. The signer must be the ticket holder or the ride authority (MissingRequiredSignature otherwise),
+a visitor not on that ride fails with ParkError::NotOnRide and an empty ride with ParkError::RideEmpty.
+ParkInstruction is a Borsh enum (0 InitRide followed by max_capacity, 1 Exit, 2 CleanupExpired), all encodings are shorter than any visitor so they can't be mistaken for one.
+
+A ticket book is valid for TICKET_VALIDITY seconds after its record is created: expiry is that unix timestamp and holder is the payer of the record.
+Admitting a visitor whose record has expired fails with ParkError::TicketExpired. ParkInstruction::CleanupExpired (accounts: visitor PDA, holder) can be sent by anyone,
+it closes an expired record and returns its rent to the holder. A visitor still on a ride has to exit first (ParkError::OnRide), so a closed record never holds a seat.
+Accounts are read with next_named, shared with the power program through common.rs, so a missing account logs its name before failing with NotEnoughAccountKeys.
 
 */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_program::{
+        entrypoint::SUCCESS,
+        instruction::Instruction,
+        program_stubs::{set_syscall_stubs, SyscallStubs},
+        system_program,
+    };
+    use std::sync::Mutex;
+
+    static LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct LogRecorder;
+
+    impl SyscallStubs for LogRecorder {
+        fn sol_log(&self, message: &str) {
+            LOGS.lock().unwrap().push(message.to_string());
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Clock) = Clock::default() };
+            SUCCESS
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            SUCCESS
+        }
+
+        // Plays the system program's create_account, the only CPI the park program makes:
+        //  the new account (second in the instruction) gets the requested space
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            _signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            let space = u64::from_le_bytes(instruction.data[12..20].try_into().unwrap()) as usize;
+            let new_account = account_infos
+                .iter()
+                .find(|account| *account.key == instruction.accounts[1].pubkey)
+                .unwrap();
+            *new_account.data.borrow_mut() = Box::leak(vec![0; space].into_boxed_slice());
+            Ok(())
+        }
+    }
+
+    fn visitor(name: &str, height: u32) -> InstructionData {
+        InstructionData {
+            name: name.to_string(),
+            height,
+        }
+    }
+
+    // The ride PDA of authority and its data
+    fn ride(program_id: &Pubkey, authority: &Pubkey, max_capacity: u32, current_occupancy: u32) -> (Pubkey, Vec<u8>) {
+        let ride = Ride { current_occupancy, max_capacity, authority: *authority };
+        (Ride::find_address(program_id, authority).0, ride.try_to_vec().unwrap())
+    }
+
+    fn record(name: &str, holder: Pubkey, ride: Pubkey) -> Visitor {
+        Visitor {
+            name: name.to_string(),
+            height: 150,
+            can_ride: true,
+            rides_taken: 1,
+            max_rides: DEFAULT_MAX_RIDES,
+            holder,
+            expiry: i64::MAX,
+            ride,
+        }
+    }
+
+    #[test]
+    fn test_unpack_batch_of_three() {
+        let batch = vec![visitor("ana", 150), visitor("bo", 100), visitor("cy", 120)];
+        let data = batch.try_to_vec().unwrap();
+
+        let visitors = unpack_batch(&data).unwrap();
+        assert_eq!(visitors.len(), 3);
+        assert_eq!(visitors[1].name, "bo");
+        assert_eq!(visitors[2].height, 120);
+    }
+
+    #[test]
+    fn test_batch_of_three_writes_every_visitor() {
+        set_syscall_stubs(Box::new(LogRecorder));
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let system_program_id = system_program::id();
+        let batch = vec![visitor("ana", 150), visitor("bo", 100), visitor("cy", 130)];
+        let keys: Vec<Pubkey> = batch
+            .iter()
+            .map(|visitor| Pubkey::find_program_address(&[b"visitor", visitor.name.as_bytes()], &program_id).0)
+            .collect();
+        let (mut payer_lamports, mut system_lamports) = (1_000_000_000, 0);
+        let mut visitor_lamports = vec![0; keys.len()];
+        let (ride_key, mut ride_data) = ride(&program_id, &Pubkey::new_unique(), 3, 0);
+        let mut ride_lamports = 1;
+
+        let mut accounts = vec![
+            AccountInfo::new(&payer, true, true, &mut payer_lamports, &mut [], &system_program_id, false, 0),
+            AccountInfo::new(&system_program_id, false, false, &mut system_lamports, &mut [], &system_program_id, true, 0),
+        ];
+        accounts.extend(
+            keys.iter()
+                .zip(visitor_lamports.iter_mut())
+                .map(|(key, lamports)| AccountInfo::new(key, false, true, lamports, &mut [], &program_id, false, 0)),
+        );
+        accounts.push(AccountInfo::new(&ride_key, false, true, &mut ride_lamports, &mut ride_data, &program_id, false, 0));
+
+        assert!(process_instruction(&program_id, &accounts, &batch.try_to_vec().unwrap()).is_ok());
+        for (account, expected) in accounts[2..5].iter().zip(&batch) {
+            let record = Visitor::try_from_slice(&account.data.borrow()).unwrap();
+            assert_eq!(record.name, expected.name);
+            assert_eq!(record.height, expected.height);
+            assert_eq!(record.holder, payer);
+            let expected_ride = if expected.height >= MIN_RIDE_HEIGHT { ride_key } else { Pubkey::default() };
+            assert_eq!(record.ride, expected_ride);
+        }
+        assert_eq!(Ride::load(&program_id, &accounts[5]).unwrap().current_occupancy, 2);
+    }
+
+    #[test]
+    fn test_unpack_batch_rejects_oversized_batch() {
+        let batch: Vec<_> = (0..=MAX_BATCH_SIZE).map(|i| visitor("v", i as u32)).collect();
+        let data = batch.try_to_vec().unwrap();
+
+        assert_eq!(
+            unpack_batch(&data).unwrap_err(),
+            ParkError::BatchTooLarge.into()
+        );
+    }
+
+    #[test]
+    fn test_decision_for_too_short_rider() {
+        let data = decide(&visitor("tim", 100)).try_to_vec().unwrap();
+
+        let decision = RideDecision::try_from_slice(&data).unwrap();
+        assert_eq!(
+            decision,
+            RideDecision {
+                can_ride: false,
+                reason: REASON_TOO_SHORT,
+            }
+        );
+    }
+
+    #[test]
+    fn test_decision_at_min_ride_height() {
+        assert_eq!(
+            decide(&visitor("ana", MIN_RIDE_HEIGHT)),
+            RideDecision {
+                can_ride: true,
+                reason: REASON_OK,
+            }
+        );
+        assert_eq!(
+            decide(&visitor("bo", MIN_RIDE_HEIGHT - 1)),
+            RideDecision {
+                can_ride: false,
+                reason: REASON_TOO_SHORT,
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_name_boundary() {
+        assert!(check_name(&"a".repeat(MAX_NAME_LEN)).is_ok());
+        assert_eq!(check_name(&"a".repeat(MAX_NAME_LEN + 1)), Err(ParkError::NameTooLong.into()));
+    }
+
+    #[test]
+    fn test_min_ride_height_is_a_plausible_height() {
+        assert!(check_height(MIN_RIDE_HEIGHT).is_ok());
+        assert!(check_height(MIN_RIDE_HEIGHT - 1).is_ok());
+    }
+
+    #[test]
+    fn test_check_height_bounds() {
+        assert_eq!(check_height(0), Err(ParkError::ImplausibleHeight.into()));
+        assert!(check_height(MIN_HEIGHT).is_ok());
+        assert!(check_height(150).is_ok());
+        assert!(check_height(MAX_HEIGHT).is_ok());
+        assert_eq!(check_height(MAX_HEIGHT + 1), Err(ParkError::ImplausibleHeight.into()));
+    }
+
+    #[test]
+    fn test_zero_height_is_implausible_not_too_short() {
+        let data = visitor("tim", 0).try_to_vec().unwrap();
+
+        assert_eq!(
+            process_instruction(&Pubkey::new_unique(), &[], &data),
+            Err(ParkError::ImplausibleHeight.into())
+        );
+    }
+
+    #[test]
+    fn test_truncated_visitor_is_rejected() {
+        set_syscall_stubs(Box::new(LogRecorder));
+        let data = visitor("tim", 120).try_to_vec().unwrap();
+
+        assert_eq!(
+            unpack_visitor(&data[..5]).unwrap_err(),
+            ParkError::DeserializationFailed.into()
+        );
+        assert!(LOGS.lock().unwrap().iter().any(|log| log == "Could not decode a visitor from 5 bytes."));
+    }
+
+    #[test]
+    fn test_empty_instruction_data_is_rejected() {
+        assert_eq!(
+            process_instruction(&Pubkey::new_unique(), &[], &[]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+
+    #[test]
+    fn test_ride_capacity() {
+        let mut ride = Ride {
+            current_occupancy: 0,
+            max_capacity: 2,
+            authority: Pubkey::new_unique(),
+        };
+
+        assert!(ride.admit().is_ok());
+        assert!(ride.admit().is_ok());
+        assert_eq!(ride.admit(), Err(ParkError::RideFull.into()));
+        assert_eq!(ride.current_occupancy, 2);
+
+        assert!(ride.exit().is_ok());
+        assert!(ride.admit().is_ok());
+        assert_eq!(ride.current_occupancy, 2);
+    }
+
+    #[test]
+    fn test_exit_empty_ride() {
+        let mut ride = Ride {
+            current_occupancy: 0,
+            max_capacity: 2,
+            authority: Pubkey::new_unique(),
+        };
+
+        assert_eq!(ride.exit(), Err(ParkError::RideEmpty.into()));
+    }
+
+    #[test]
+    fn test_ticket_expiry() {
+        let record = Visitor {
+            name: "ana".to_string(),
+            height: 150,
+            can_ride: true,
+            rides_taken: 0,
+            max_rides: 2,
+            holder: Pubkey::new_unique(),
+            expiry: 1_000,
+            ride: Pubkey::default(),
+        };
+
+        assert!(record.check_not_expired(999).is_ok());
+        assert_eq!(record.check_not_expired(1_000), Err(ParkError::TicketExpired.into()));
+        assert!(record.is_expired(2_000));
+    }
+
+    #[test]
+    fn test_take_ride_until_none_left() {
+        let mut record = Visitor {
+            name: "ana".to_string(),
+            height: 150,
+            can_ride: true,
+            rides_taken: 0,
+            max_rides: 2,
+            holder: Pubkey::new_unique(),
+            expiry: 0,
+            ride: Pubkey::default(),
+        };
+        let ride = Pubkey::new_unique();
+
+        assert!(record.take_ride(&ride).is_ok());
+        assert_eq!(record.take_ride(&ride), Err(ParkError::OnRide.into()));
+        assert_eq!(record.leave_ride(&Pubkey::new_unique()), Err(ParkError::NotOnRide.into()));
+        assert!(record.leave_ride(&ride).is_ok());
+        assert!(record.take_ride(&ride).is_ok());
+        assert!(record.leave_ride(&ride).is_ok());
+        assert_eq!(record.take_ride(&ride), Err(ParkError::NoRidesLeft.into()));
+        assert_eq!(record.rides_taken, 2);
+        assert_eq!(record.ride, Pubkey::default());
+    }
+
+    #[test]
+    fn test_full_ride_rejects_the_batch() {
+        set_syscall_stubs(Box::new(LogRecorder));
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let system_program_id = system_program::id();
+        let batch = vec![visitor("ana", 150), visitor("cy", 130)];
+        let keys: Vec<Pubkey> = batch
+            .iter()
+            .map(|visitor| Pubkey::find_program_address(&[b"visitor", visitor.name.as_bytes()], &program_id).0)
+            .collect();
+        let (mut payer_lamports, mut system_lamports) = (1_000_000_000, 0);
+        let mut visitor_lamports = vec![0; keys.len()];
+        let (ride_key, mut ride_data) = ride(&program_id, &Pubkey::new_unique(), 1, 0);
+        let mut ride_lamports = 1;
+
+        let mut accounts = vec![
+            AccountInfo::new(&payer, true, true, &mut payer_lamports, &mut [], &system_program_id, false, 0),
+            AccountInfo::new(&system_program_id, false, false, &mut system_lamports, &mut [], &system_program_id, true, 0),
+        ];
+        accounts.extend(
+            keys.iter()
+                .zip(visitor_lamports.iter_mut())
+                .map(|(key, lamports)| AccountInfo::new(key, false, true, lamports, &mut [], &program_id, false, 0)),
+        );
+        let data = batch.try_to_vec().unwrap();
+
+        assert_eq!(process_instruction(&program_id, &accounts, &data), Err(ProgramError::NotEnoughAccountKeys));
+        accounts.push(AccountInfo::new(&ride_key, false, true, &mut ride_lamports, &mut ride_data, &program_id, false, 0));
+        assert_eq!(process_instruction(&program_id, &accounts, &data), Err(ParkError::RideFull.into()));
+    }
+
+    #[test]
+    fn test_exit_needs_the_holder_or_the_ride_authority() {
+        set_syscall_stubs(Box::new(LogRecorder));
+        let program_id = Pubkey::new_unique();
+        let (holder, authority, stranger) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (ride_key, mut ride_data) = ride(&program_id, &authority, 2, 2);
+        let visitor_keys: Vec<Pubkey> = ["ana", "bo"]
+            .iter()
+            .map(|name| Pubkey::find_program_address(&[b"visitor", name.as_bytes()], &program_id).0)
+            .collect();
+        let mut ana_data = record("ana", holder, ride_key).try_to_vec().unwrap();
+        let mut bo_data = record("bo", holder, ride_key).try_to_vec().unwrap();
+        let mut lamports = [1; 6];
+        let [ride_lamports, ana_lamports, bo_lamports, holder_lamports, authority_lamports, stranger_lamports] = &mut lamports;
+        let ride_account = AccountInfo::new(&ride_key, false, true, ride_lamports, &mut ride_data, &program_id, false, 0);
+        let ana = AccountInfo::new(&visitor_keys[0], false, true, ana_lamports, &mut ana_data, &program_id, false, 0);
+        let bo = AccountInfo::new(&visitor_keys[1], false, true, bo_lamports, &mut bo_data, &program_id, false, 0);
+        let holder = AccountInfo::new(&holder, true, false, holder_lamports, &mut [], &program_id, false, 0);
+        let authority = AccountInfo::new(&authority, true, false, authority_lamports, &mut [], &program_id, false, 0);
+        let stranger = AccountInfo::new(&stranger, true, false, stranger_lamports, &mut [], &program_id, false, 0);
+        let data = ParkInstruction::Exit.try_to_vec().unwrap();
+        let exit = |visitor: &AccountInfo, signer: &AccountInfo| {
+            process_instruction(&program_id, &[ride_account.clone(), visitor.clone(), signer.clone()], &data)
+        };
+
+        assert_eq!(exit(&ana, &stranger), Err(ProgramError::MissingRequiredSignature));
+        assert!(exit(&ana, &holder).is_ok());
+        assert_eq!(exit(&ana, &holder), Err(ParkError::NotOnRide.into()));
+        assert!(exit(&bo, &authority).is_ok());
+
+        assert_eq!(Ride::load(&program_id, &ride_account).unwrap().current_occupancy, 0);
+        assert_eq!(Visitor::load(&program_id, &ana).unwrap().ride, Pubkey::default());
+        assert_eq!(Visitor::load(&program_id, &bo).unwrap().ride, Pubkey::default());
+    }
+
+    #[test]
+    fn test_cleanup_closes_only_expired_tickets() {
+        set_syscall_stubs(Box::new(LogRecorder));
+        let program_id = Pubkey::new_unique();
+        let holder = Pubkey::new_unique();
+        let visitor_keys: Vec<Pubkey> = ["ana", "bo"]
+            .iter()
+            .map(|name| Pubkey::find_program_address(&[b"visitor", name.as_bytes()], &program_id).0)
+            .collect();
+        // the stubbed clock reads 0, ana's ticket ran out then and bo's never does
+        let mut ana_record = record("ana", holder, Pubkey::default());
+        ana_record.expiry = 0;
+        let mut ana_data = ana_record.try_to_vec().unwrap();
+        let mut bo_data = record("bo", holder, Pubkey::default()).try_to_vec().unwrap();
+        let mut lamports = [1_000, 2_000, 5];
+        let [ana_lamports, bo_lamports, holder_lamports] = &mut lamports;
+        let ana = AccountInfo::new(&visitor_keys[0], false, true, ana_lamports, &mut ana_data, &program_id, false, 0);
+        let bo = AccountInfo::new(&visitor_keys[1], false, true, bo_lamports, &mut bo_data, &program_id, false, 0);
+        let holder = AccountInfo::new(&holder, false, true, holder_lamports, &mut [], &program_id, false, 0);
+        let data = ParkInstruction::CleanupExpired.try_to_vec().unwrap();
+
+        assert_eq!(
+            process_instruction(&program_id, &[bo.clone(), holder.clone()], &data),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(bo.lamports(), 2_000);
+        assert!(Visitor::load(&program_id, &bo).is_ok());
+
+        assert!(process_instruction(&program_id, &[ana.clone(), holder.clone()], &data).is_ok());
+        assert_eq!(ana.lamports(), 0);
+        assert!(ana.data.borrow().iter().all(|byte| *byte == 0));
+        assert_eq!(holder.lamports(), 1_005);
+    }
+
+    #[test]
+    fn test_init_ride_needs_the_authority_signature() {
+        set_syscall_stubs(Box::new(LogRecorder));
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let system_program_id = system_program::id();
+        let ride_key = Ride::find_address(&program_id, &authority).0;
+        let (mut authority_lamports, mut ride_lamports, mut system_lamports) = (1_000_000_000, 0, 0);
+        let mut accounts = [
+            AccountInfo::new(&authority, false, true, &mut authority_lamports, &mut [], &system_program_id, false, 0),
+            AccountInfo::new(&ride_key, false, true, &mut ride_lamports, &mut [], &program_id, false, 0),
+            AccountInfo::new(&system_program_id, false, false, &mut system_lamports, &mut [], &system_program_id, true, 0),
+        ];
+        let data = ParkInstruction::InitRide { max_capacity: 4 }.try_to_vec().unwrap();
+
+        assert_eq!(process_instruction(&program_id, &accounts, &data), Err(ProgramError::MissingRequiredSignature));
+        accounts[0].is_signer = true;
+        assert!(process_instruction(&program_id, &accounts, &data).is_ok());
+        let ride = Ride::load(&program_id, &accounts[1]).unwrap();
+        assert_eq!(ride.authority, authority);
+        assert_eq!(ride.max_capacity, 4);
+        assert_eq!(ride.current_occupancy, 0);
+    }
+
+    #[test]
+    fn test_missing_account_is_named() {
+        set_syscall_stubs(Box::new(LogRecorder));
+        let program_id = Pubkey::new_unique();
+        let instructions = [
+            ParkInstruction::InitRide { max_capacity: 1 },
+            ParkInstruction::Exit,
+            ParkInstruction::CleanupExpired,
+        ];
+
+        for (instruction, first_account) in instructions.iter().zip(["authority", "ride", "visitor"]) {
+            let data = instruction.try_to_vec().unwrap();
+            assert_eq!(process_instruction(&program_id, &[], &data), Err(ProgramError::NotEnoughAccountKeys));
+            let expected = format!("missing account: {}", first_account);
+            assert!(LOGS.lock().unwrap().iter().any(|log| *log == expected));
+        }
+    }
+}